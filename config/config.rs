@@ -1,6 +1,5 @@
 #[macro_use]
 extern crate wtftw;
-extern crate wtftw_contrib;
 
 use std::ops::Deref;
 //use std::ffi::AsOsStr;
@@ -9,9 +8,12 @@ use wtftw::window_manager::*;
 use wtftw::handlers::default::*;
 use wtftw::config::*;
 use wtftw::util::*;
+use wtftw::keys::Submap;
+use wtftw::rules::*;
+use wtftw::core::rational_rect::RationalRect;
 use wtftw::layout::Direction;
 use wtftw::layout::LayoutMessage;
-use wtftw_contrib::layout::{ AvoidStrutsLayout, LayoutCollection, BinarySpacePartition, GapLayout, MirrorLayout, NoBordersLayout, FullLayout };
+use wtftw::layout::{ AvoidStrutsLayout, LayoutCollection, BinarySpacePartition, GapLayout, MirrorLayout, NoBordersLayout, FullLayout };
 
 
 #[no_mangle]
@@ -23,10 +25,10 @@ pub extern fn configure(_: &mut WindowManager, w: &dyn WindowSystem, config: &mu
     config.general.focus_border_color = 0xebebeb;
     config.general.border_width = 2;
     config.general.terminal = (String::from("urxvt"), String::from(""));
-    config.general.layout = LayoutCollection::boxed_new(vec!(
-            GapLayout::boxed_new(8, AvoidStrutsLayout::boxed_new(vec!(Direction::Up, Direction::Down), BinarySpacePartition::boxed_new())),
-            GapLayout::boxed_new(8, AvoidStrutsLayout::boxed_new(vec!(Direction::Up, Direction::Down), MirrorLayout::boxed_new(BinarySpacePartition::boxed_new()))),
-            NoBordersLayout::boxed_new(Box::new(FullLayout))));
+    config.general.layout = LayoutCollection::new(vec!(
+            GapLayout::new(8, AvoidStrutsLayout::new(vec!(Direction::Up, Direction::Down), BinarySpacePartition::new())),
+            GapLayout::new(8, AvoidStrutsLayout::new(vec!(Direction::Up, Direction::Down), MirrorLayout::new(BinarySpacePartition::new()))),
+            NoBordersLayout::new(Box::new(FullLayout))));
 
     config.general.tags = (vec!("一: ターミナル", "二: ウェブ", "三: コード",
                                 "四: メディア", "五: スチーム", "六: ラテック",
@@ -36,67 +38,81 @@ pub extern fn configure(_: &mut WindowManager, w: &dyn WindowSystem, config: &mu
     // Register key handlers
 
     // Some standard key handlers for starting, restarting, etc.
-    add_key_handler_str!(config, w, "q",      modm | KeyModifiers::SHIFTMASK, exit);
-    add_key_handler_str!(config, w, "q",      modm,             restart);
-    add_key_handler_str!(config, w, "Return", modm | KeyModifiers::SHIFTMASK, start_terminal);
-    add_key_handler_str!(config, w, "p",      modm,             start_launcher);
-
-    // Focus and window movement
-    add_key_handler_str!(config, w, "j", modm,             |m, w, c| m.windows(w.deref(), c, &|x| x.focus_down()));
-    add_key_handler_str!(config, w, "k", modm,             |m, w, c| m.windows(w.deref(), c, &|x| x.focus_up()));
-    add_key_handler_str!(config, w, "j", modm | KeyModifiers::SHIFTMASK, |m, w, c| m.windows(w.deref(), c, &|x| x.swap_down()));
-    add_key_handler_str!(config, w, "k", modm | KeyModifiers::SHIFTMASK, |m, w, c| m.windows(w.deref(), c, &|x| x.swap_up()));
-    add_key_handler_str!(config, w, "Return", modm,        |m, w, c| m.windows(w.deref(), c, &|x| x.swap_master()));
-    add_key_handler_str!(config, w, "c", modm, |m, w, c| m.kill_window(w.deref()).windows(w.deref(), c, &|x| x.clone()));
-
-    add_key_handler_str!(config, w, "t", modm, |m, w, c| {
-        match m.workspaces.peek() {
-            Some(window) => m.windows(w.deref(), c, &|x| x.sink(window)),
-            None => m
-        }
-    });
+    add_key_binding!(config, w, "M-S-q", exit);
+    add_key_binding!(config, w, "M-q", restart);
+    add_key_binding!(config, w, "M-S-r", "reload");
+    add_key_binding!(config, w, "M-S-Return", start_terminal);
+    add_key_binding!(config, w, "M-p", start_launcher);
+
+    // Focus and window movement, using the named actions of the registry
+    add_key_binding!(config, w, "M-j", "focus-down");
+    add_key_binding!(config, w, "M-k", "focus-up");
+    add_key_binding!(config, w, "M-S-j", "swap-down");
+    add_key_binding!(config, w, "M-S-k", "swap-up");
+    add_key_binding!(config, w, "M-Return", "swap-master");
+    add_key_binding!(config, w, "M-c", "kill");
+    add_key_binding!(config, w, "M-t", "sink");
 
     // Layout messages
-    add_key_handler_str!(config, w, "h",      modm,             send_layout_message!(LayoutMessage::Decrease));
-    add_key_handler_str!(config, w, "l",      modm,             send_layout_message!(LayoutMessage::Increase));
-    add_key_handler_str!(config, w, "z",      modm,             send_layout_message!(LayoutMessage::DecreaseSlave));
-    add_key_handler_str!(config, w, "a",      modm,             send_layout_message!(LayoutMessage::IncreaseSlave));
-    add_key_handler_str!(config, w, "x",      modm | KeyModifiers::SHIFTMASK, send_layout_message!(LayoutMessage::IncreaseGap));
-    add_key_handler_str!(config, w, "s",      modm | KeyModifiers::SHIFTMASK, send_layout_message!(LayoutMessage::DecreaseGap));
-    add_key_handler_str!(config, w, "comma",  modm,             send_layout_message!(LayoutMessage::IncreaseMaster));
-    add_key_handler_str!(config, w, "period", modm,             send_layout_message!(LayoutMessage::DecreaseMaster));
-    add_key_handler_str!(config, w, "space",  modm,             send_layout_message!(LayoutMessage::Next));
-    add_key_handler_str!(config, w, "space",  modm | KeyModifiers::SHIFTMASK, send_layout_message!(LayoutMessage::Prev));
-    add_key_handler_str!(config, w, "r",      modm,             send_layout_message!(LayoutMessage::TreeRotate));
-    add_key_handler_str!(config, w, "s",      modm,             send_layout_message!(LayoutMessage::TreeSwap));
-    add_key_handler_str!(config, w, "u",      modm | KeyModifiers::SHIFTMASK, send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Left)));
-    add_key_handler_str!(config, w, "p",      modm | KeyModifiers::SHIFTMASK, send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Right)));
-    add_key_handler_str!(config, w, "i",      modm | KeyModifiers::SHIFTMASK, send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Down)));
-    add_key_handler_str!(config, w, "o",      modm | KeyModifiers::SHIFTMASK, send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Up)));
-    add_key_handler_str!(config, w, "u",      modm | KeyModifiers::CONTROLMASK, send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Left)));
-    add_key_handler_str!(config, w, "p",      modm | KeyModifiers::CONTROLMASK, send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Right)));
-    add_key_handler_str!(config, w, "i",      modm | KeyModifiers::CONTROLMASK, send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Down)));
-    add_key_handler_str!(config, w, "o",      modm | KeyModifiers::CONTROLMASK, send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Up)));
-
+    add_key_binding!(config, w, "M-h", send_layout_message!(LayoutMessage::Decrease));
+    add_key_binding!(config, w, "M-l", send_layout_message!(LayoutMessage::Increase));
+    add_key_binding!(config, w, "M-z", send_layout_message!(LayoutMessage::DecreaseSlave));
+    add_key_binding!(config, w, "M-a", send_layout_message!(LayoutMessage::IncreaseSlave));
+    add_key_binding!(config, w, "M-S-x", send_layout_message!(LayoutMessage::IncreaseGap));
+    add_key_binding!(config, w, "M-S-s", send_layout_message!(LayoutMessage::DecreaseGap));
+    add_key_binding!(config, w, "M-comma", send_layout_message!(LayoutMessage::IncreaseMaster));
+    add_key_binding!(config, w, "M-period", send_layout_message!(LayoutMessage::DecreaseMaster));
+    add_key_binding!(config, w, "M-space", send_layout_message!(LayoutMessage::Next));
+    add_key_binding!(config, w, "M-S-space", send_layout_message!(LayoutMessage::Prev));
+    add_key_binding!(config, w, "M-r", send_layout_message!(LayoutMessage::TreeRotate));
+    add_key_binding!(config, w, "M-s", send_layout_message!(LayoutMessage::TreeSwap));
+    add_key_binding!(config, w, "M-S-u", send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Left)));
+    add_key_binding!(config, w, "M-S-p", send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Right)));
+    add_key_binding!(config, w, "M-S-i", send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Down)));
+    add_key_binding!(config, w, "M-S-o", send_layout_message!(LayoutMessage::TreeExpandTowards(Direction::Up)));
+    add_key_binding!(config, w, "M-C-u", send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Left)));
+    add_key_binding!(config, w, "M-C-p", send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Right)));
+    add_key_binding!(config, w, "M-C-i", send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Down)));
+    add_key_binding!(config, w, "M-C-o", send_layout_message!(LayoutMessage::TreeShrinkFrom(Direction::Up)));
+
+
+    // A resize mode, left with Escape
+    config.add_submap("resize", Submap::mode());
+    add_key_binding!(config, w, "M-C-r", submap "resize");
+    add_submap_binding!(config, w, "resize", "h", send_layout_message!(LayoutMessage::Decrease));
+    add_submap_binding!(config, w, "resize", "l", send_layout_message!(LayoutMessage::Increase));
+    add_submap_binding!(config, w, "resize", "j", send_layout_message!(LayoutMessage::IncreaseSlave));
+    add_submap_binding!(config, w, "resize", "k", send_layout_message!(LayoutMessage::DecreaseSlave));
+
+    // Chords, e.g. M-x followed by t
+    add_key_binding!(config, w, "M-x t", "terminal");
+    add_key_binding!(config, w, "M-x l", "launcher");
 
     // Workspace switching and moving
     for i in 1usize..10 {
-        add_key_handler_str!(config, w, &i.to_string(), modm,
-        move |m, w, c| switch_to_workspace(m, w, c, i - 1));
-
-        add_key_handler_str!(config, w, &i.to_string(), modm | KeyModifiers::SHIFTMASK,
-        move |m, w, c| move_window_to_workspace(m, w, c, i - 1));
+        let key = w.get_keycode_from_string(&i.to_string());
+        config.add_key_handler(key, modm, format!("view:{}", i - 1));
+        config.add_key_handler(key, modm | KeyModifiers::SHIFTMASK, format!("shift:{}", i - 1));
     }
 
     // Media keys
-    add_key_handler_str!(config, w, "j", modm | KeyModifiers::CONTROLMASK, run!("amixer", "-q set Master 5%-"));
-    add_key_handler_str!(config, w, "k", modm | KeyModifiers::CONTROLMASK, run!("amixer", "-q set Master 5%+"));
-
-    add_key_handler_code!(config, 0x1008ff11, KeyModifiers::NONEMASK, run!("amixer", "-q set Master 5%-"));
-    add_key_handler_code!(config, 0x1008ff13, KeyModifiers::NONEMASK, run!("amixer", "-q set Master 5%+"));
-
-    add_key_handler_code!(config, 0x1008ff02, KeyModifiers::NONEMASK, run!("xbacklight", "+10"));
-    add_key_handler_code!(config, 0x1008ff03, KeyModifiers::NONEMASK, run!("xbacklight", "-10"));
+    add_key_binding!(config, w, "M-C-j", run!("amixer", "-q set Master 5%-"));
+    add_key_binding!(config, w, "M-C-k", run!("amixer", "-q set Master 5%+"));
+
+    add_key_binding!(config, w, "<XF86AudioLowerVolume>", run!("amixer", "-q set Master 5%-"));
+    add_key_binding!(config, w, "<XF86AudioRaiseVolume>", run!("amixer", "-q set Master 5%+"));
+
+    add_key_binding!(config, w, "<XF86MonBrightnessUp>", run!("xbacklight", "+10"));
+    add_key_binding!(config, w, "<XF86MonBrightnessDown>", run!("xbacklight", "-10"));
+
+    // Window rules, applied in order to every new window
+    config.set_manage_hook(rules_manage_hook(vec!(
+        Rule::new(vec!(Condition::Class(Pattern::Exact(String::from("Gimp")))),
+                  vec!(RuleAction::Float)),
+        Rule::new(vec!(Condition::Class(Pattern::Contains(String::from("Steam")))),
+                  vec!(RuleAction::Shift(4))),
+        Rule::new(vec!(Condition::Title(Pattern::regex("^Picture.in.[Pp]icture$").unwrap())),
+                  vec!(RuleAction::FloatAt(RationalRect(0.7, 0.7, 0.28, 0.28)), RuleAction::SkipFocus)))));
 
     add_mouse_handler!(config, BUTTON1, modm,
                        |m, w, c, s| {
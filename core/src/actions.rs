@@ -0,0 +1,396 @@
+use crate::config::GeneralConfig;
+use crate::handlers::default::{exit, reload, restart, start_launcher, start_terminal};
+use crate::handlers::KeyHandler;
+use crate::layout::LayoutMessage;
+use crate::logging::parse_level;
+use crate::window_manager::WindowManager;
+use crate::window_system::WindowSystem;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// Builds the handler for a parameterized action like `view:3`
+/// from the part after the colon
+pub type ActionBuilder = Box<dyn Fn(&str) -> Result<KeyHandler>>;
+
+/// What a key binding does, either a named action from the
+/// registry, an anonymous handler or entering a submap
+pub enum KeyAction {
+    Named(String),
+    Handler(KeyHandler),
+    /// Enter the submap with the given name, see `keys::Submap`
+    Submap(String),
+}
+
+impl From<&str> for KeyAction {
+    fn from(name: &str) -> KeyAction {
+        KeyAction::Named(name.to_owned())
+    }
+}
+
+impl From<String> for KeyAction {
+    fn from(name: String) -> KeyAction {
+        KeyAction::Named(name)
+    }
+}
+
+impl From<KeyHandler> for KeyAction {
+    fn from(handler: KeyHandler) -> KeyAction {
+        KeyAction::Handler(handler)
+    }
+}
+
+impl<F> From<Box<F>> for KeyAction
+where
+    F: Fn(WindowManager, Rc<dyn WindowSystem>, &GeneralConfig) -> WindowManager + 'static,
+{
+    fn from(handler: Box<F>) -> KeyAction {
+        KeyAction::Handler(handler)
+    }
+}
+
+impl KeyAction {
+    /// The name of the action, if it has one
+    pub fn name(&self) -> Option<&str> {
+        match *self {
+            KeyAction::Named(ref name) => Some(name),
+            KeyAction::Handler(_) | KeyAction::Submap(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for KeyAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyAction::Named(ref name) => write!(f, "{}", name),
+            KeyAction::Handler(_) => write!(f, "a handler"),
+            KeyAction::Submap(ref name) => write!(f, "submap {}", name),
+        }
+    }
+}
+
+/// The name and description of an action, e.g. for a cheat sheet
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ActionInfo {
+    pub name: String,
+    pub description: String,
+}
+
+struct Action {
+    description: String,
+    handler: Rc<KeyHandler>,
+}
+
+struct ActionFamily {
+    description: String,
+    builder: ActionBuilder,
+}
+
+/// Maps action names to handlers, so they can be bound to keys,
+/// listed and called by name. Names with a colon, like `view:3`
+/// or `layout:next`, are handled by the family before the colon.
+pub struct ActionRegistry {
+    actions: BTreeMap<String, Action>,
+    families: BTreeMap<String, ActionFamily>,
+}
+
+/// Turn `increase-master` into `IncreaseMaster`
+fn pascal_case(name: &str) -> String {
+    name.split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Parse layout messages like `next` or `tree-expand-towards:left`
+pub fn parse_layout_message(message: &str) -> Result<LayoutMessage> {
+    let value = match message.find(':') {
+        Some(i) => json!({ pascal_case(&message[..i]): pascal_case(&message[i + 1..]) }),
+        None => Value::String(pascal_case(message)),
+    };
+    serde_json::from_value(value).with_context(|| format!("invalid layout message {}", message))
+}
+
+fn parse_workspace(index: &str) -> Result<u32> {
+    index
+        .parse()
+        .with_context(|| format!("invalid workspace {}", index))
+}
+
+fn check_workspace(index: u32, config: &GeneralConfig) -> bool {
+    let valid = (index as usize) < config.tags.len();
+    if !valid {
+        warn!("no workspace with index {}", index);
+    }
+    valid
+}
+
+impl ActionRegistry {
+    /// An empty registry, without any of the default actions
+    pub fn empty() -> ActionRegistry {
+        ActionRegistry {
+            actions: BTreeMap::new(),
+            families: BTreeMap::new(),
+        }
+    }
+
+    /// A registry with the common actions every config needs
+    pub fn new() -> ActionRegistry {
+        let mut registry = ActionRegistry::empty();
+
+        registry.register(
+            "focus-down",
+            "focus the next window",
+            Box::new(|m, w, c| m.windows(w.deref(), c, &|x| x.focus_down())),
+        );
+        registry.register(
+            "focus-up",
+            "focus the previous window",
+            Box::new(|m, w, c| m.windows(w.deref(), c, &|x| x.focus_up())),
+        );
+        registry.register(
+            "swap-down",
+            "swap the focused window with the next one",
+            Box::new(|m, w, c| m.windows(w.deref(), c, &|x| x.swap_down())),
+        );
+        registry.register(
+            "swap-up",
+            "swap the focused window with the previous one",
+            Box::new(|m, w, c| m.windows(w.deref(), c, &|x| x.swap_up())),
+        );
+        registry.register(
+            "swap-master",
+            "swap the focused window with the master window",
+            Box::new(|m, w, c| m.windows(w.deref(), c, &|x| x.swap_master())),
+        );
+        registry.register(
+            "close",
+            "ask the focused window to close",
+            Box::new(|m, w, c| {
+                m.close_window(w.deref())
+                    .windows(w.deref(), c, &|x| x.clone())
+            }),
+        );
+        registry.register(
+            "kill",
+            "kill the focused window",
+            Box::new(|m, w, c| {
+                m.kill_window(w.deref())
+                    .windows(w.deref(), c, &|x| x.clone())
+            }),
+        );
+        registry.register(
+            "float",
+            "float the focused window",
+            Box::new(|m, w, c| match m.workspaces.peek() {
+                Some(window) => m.float(w.deref(), c, window),
+                None => m,
+            }),
+        );
+        registry.register(
+            "sink",
+            "sink the focused window back into the layout",
+            Box::new(|m, w, c| match m.workspaces.peek() {
+                Some(window) => m.windows(w.deref(), c, &|x| x.sink(window)),
+                None => m,
+            }),
+        );
+        registry.register("terminal", "start the terminal", Box::new(start_terminal));
+        registry.register("launcher", "start the launcher", Box::new(start_launcher));
+        registry.register(
+            "restart",
+            "restart wtftw, keeping all windows",
+            Box::new(|m, w, c| match restart(m.clone(), w, c) {
+                Ok(m) => m,
+                Err(e) => {
+                    error!("unable to restart wtftw: {:#}", e);
+                    m
+                }
+            }),
+        );
+        registry.register(
+            "reload",
+            "reload the config without restarting",
+            Box::new(reload),
+        );
+        registry.register("exit", "quit wtftw", Box::new(exit));
+        registry.register(
+            "leave-submap",
+            "leave all active submaps",
+            Box::new(|m, w, _| m.leave_submaps(w.deref())),
+        );
+
+        registry.register_family(
+            "view",
+            "switch to the workspace with the given index",
+            Box::new(|arg| {
+                let index = parse_workspace(arg)?;
+                Ok(Box::new(move |m, w, c| {
+                    if check_workspace(index, c) {
+                        m.view(w.deref(), index, c)
+                    } else {
+                        m
+                    }
+                }))
+            }),
+        );
+        registry.register_family(
+            "shift",
+            "move the focused window to the workspace with the given index",
+            Box::new(|arg| {
+                let index = parse_workspace(arg)?;
+                Ok(Box::new(move |m, w, c| {
+                    if check_workspace(index, c) {
+                        m.move_window_to_workspace(w.deref(), c, index)
+                    } else {
+                        m
+                    }
+                }))
+            }),
+        );
+        registry.register_family(
+            "log-level",
+            "log messages up to the given level, e.g. debug or warn",
+            Box::new(|arg| {
+                let level = parse_level(arg)?;
+                Ok(Box::new(move |m, _, _| {
+                    log::set_max_level(level);
+                    m
+                }))
+            }),
+        );
+        registry.register_family(
+            "layout",
+            "send a message to the current layout, e.g. next or increase-gap",
+            Box::new(|arg| {
+                let message = parse_layout_message(arg)?;
+                Ok(Box::new(move |m, w, c| {
+                    m.send_layout_message(message, w.deref(), c)
+                        .windows(w.deref(), c, &|x| x.clone())
+                }))
+            }),
+        );
+
+        registry
+    }
+
+    /// Register an action under the given name, replacing an existing one
+    pub fn register(&mut self, name: &str, description: &str, handler: KeyHandler) {
+        self.actions.insert(
+            name.to_owned(),
+            Action {
+                description: description.to_owned(),
+                handler: Rc::new(handler),
+            },
+        );
+    }
+
+    /// Register a family of actions called `name:argument`
+    pub fn register_family(&mut self, name: &str, description: &str, builder: ActionBuilder) {
+        self.families.insert(
+            name.to_owned(),
+            ActionFamily {
+                description: description.to_owned(),
+                builder,
+            },
+        );
+    }
+
+    /// Whether the name refers to a known action
+    pub fn contains(&self, name: &str) -> bool {
+        self.resolve(name).is_ok()
+    }
+
+    /// The description of the named action
+    pub fn describe(&self, name: &str) -> Option<String> {
+        match self.actions.get(name) {
+            Some(action) => Some(action.description.clone()),
+            None => {
+                let (family, _) = name.split_at(name.find(':')?);
+                self.families.get(family).map(|f| f.description.clone())
+            }
+        }
+    }
+
+    /// All actions with their descriptions. Families are listed as `name:ARG`.
+    pub fn list(&self) -> Vec<ActionInfo> {
+        let actions = self.actions.iter().map(|(name, action)| ActionInfo {
+            name: name.clone(),
+            description: action.description.clone(),
+        });
+        let families = self.families.iter().map(|(name, family)| ActionInfo {
+            name: format!("{}:ARG", name),
+            description: family.description.clone(),
+        });
+        actions.chain(families).collect()
+    }
+
+    /// Look up the handler for the given name
+    pub fn resolve(&self, name: &str) -> Result<Rc<KeyHandler>> {
+        if let Some(action) = self.actions.get(name) {
+            return Ok(action.handler.clone());
+        }
+
+        let i = name
+            .find(':')
+            .ok_or_else(|| anyhow!("unknown action {}", name))?;
+        let family = self
+            .families
+            .get(&name[..i])
+            .ok_or_else(|| anyhow!("unknown action {}", name))?;
+        Ok(Rc::new((family.builder)(&name[i + 1..])?))
+    }
+
+    /// Call the action with the given name
+    pub fn call(
+        &self,
+        name: &str,
+        window_manager: WindowManager,
+        window_system: Rc<dyn WindowSystem>,
+        config: &GeneralConfig,
+    ) -> Result<WindowManager> {
+        let handler = self.resolve(name)?;
+        Ok(handler(window_manager, window_system, config))
+    }
+
+    /// Run whatever the key binding refers to. Unknown names are
+    /// logged and leave the window manager as it is. Submaps are
+    /// entered by the window manager itself, as they need the config.
+    pub fn run(
+        &self,
+        action: &KeyAction,
+        window_manager: WindowManager,
+        window_system: Rc<dyn WindowSystem>,
+        config: &GeneralConfig,
+    ) -> WindowManager {
+        match *action {
+            KeyAction::Handler(ref handler) => handler(window_manager, window_system, config),
+            KeyAction::Named(ref name) => match self.resolve(name) {
+                Ok(handler) => handler(window_manager, window_system, config),
+                Err(e) => {
+                    error!("{:#}", e);
+                    window_manager
+                }
+            },
+            KeyAction::Submap(ref name) => {
+                error!("submap {} can't be run as an action", name);
+                window_manager
+            }
+        }
+    }
+}
+
+impl Default for ActionRegistry {
+    fn default() -> ActionRegistry {
+        ActionRegistry::new()
+    }
+}
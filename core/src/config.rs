@@ -1,28 +1,47 @@
+use crate::actions::{ActionInfo, ActionRegistry, KeyAction};
+use crate::config_file::ConfigFile;
 use crate::core::workspaces::Workspaces;
-use crate::handlers::default::{exit, restart, start_terminal};
-use crate::handlers::{KeyHandler, LogHook, ManageHook, MouseHandler, StartupHook};
-use crate::layout::{Layout, TallLayout};
+use crate::handlers::{FdHandler, LogHook, ManageHook, MouseHandler, StartupHook};
+use crate::headless_window_system::HeadlessWindowSystem;
+use crate::keys::{format_key, parse_key, Submap};
+use crate::layout::{Layout, LayoutMessage, TallLayout};
+use crate::util::show_message;
 use crate::window_manager::WindowManager;
 use crate::window_system::{
-    KeyCommand, KeyModifiers, MouseButton, MouseCommand, Window, WindowSystem,
+    KeyCommand, KeyModifiers, MouseButton, MouseCommand, Rectangle, Window, WindowSystem,
 };
 use std::borrow::ToOwned;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use dylib::DynamicLibrary;
+use log::LevelFilter;
 use std::fs::metadata;
 use std::fs::File;
-use std::fs::{create_dir_all, read_dir};
+use std::fs::{copy, create_dir_all, read_dir, read_to_string, remove_file};
 use std::io::Write;
 use std::mem;
+use std::ops::Deref;
+use std::os::unix::io::RawFd;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::path::Path;
 use std::path::PathBuf;
+use std::process;
 use std::process::Child;
 use std::process::Command;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::RwLock;
-use std::thread::spawn;
+
+/// How many windows `Config::check` manages to try the layouts
+const CHECKED_WINDOWS: Window = 4;
+
+/// Counts the config modules loaded, to give each a file of its own
+static LOADED_MODULES: AtomicUsize = AtomicUsize::new(0);
+
+/// How many replaced config modules are kept loaded, see `retired_libraries`
+pub const MAX_RETIRED_LIBRARIES: usize = 16;
 
 pub struct GeneralConfig {
     /// Whether focus follows mouse movements or
@@ -39,13 +58,21 @@ pub struct GeneralConfig {
     /// Keybind for the terminal
     /// Path to the logfile
     pub logfile: String,
+    /// The most verbose messages that are logged
+    pub log_level: LevelFilter,
     /// Default tags for workspaces
     pub tags: Vec<String>,
     /// Default launcher application
     pub launcher: String,
     pub mod_mask: KeyModifiers,
+    /// Modifiers that are ignored in key and mouse bindings,
+    /// CapsLock and NumLock (usually mod2) by default
+    pub lock_mask: KeyModifiers,
     pub pipes: Vec<Rc<RwLock<Child>>>,
     pub layout: Box<dyn Layout>,
+    /// Set while replaying a trace. Handlers that start programs
+    /// or restart the window manager do nothing then.
+    pub replaying: bool,
 }
 
 impl Clone for GeneralConfig {
@@ -57,35 +84,66 @@ impl Clone for GeneralConfig {
             border_width: self.border_width,
             terminal: self.terminal.clone(),
             logfile: self.logfile.clone(),
+            log_level: self.log_level,
             tags: self.tags.clone(),
             launcher: self.launcher.clone(),
             mod_mask: self.mod_mask,
+            lock_mask: self.lock_mask,
             pipes: self.pipes.clone(),
             layout: self.layout.copy(),
+            replaying: self.replaying,
         }
     }
 }
 
 pub struct InternalConfig {
     pub library: Option<DynamicLibrary>,
-    pub key_handlers: BTreeMap<KeyCommand, KeyHandler>,
+    pub actions: ActionRegistry,
+    pub key_handlers: BTreeMap<KeyCommand, KeyAction>,
+    pub key_release_handlers: BTreeMap<KeyCommand, KeyAction>,
+    pub submaps: BTreeMap<String, Submap>,
     pub mouse_handlers: BTreeMap<MouseCommand, MouseHandler>,
+    pub mouse_release_handlers: BTreeMap<MouseCommand, MouseHandler>,
+    pub fd_handlers: BTreeMap<RawFd, FdHandler>,
     pub manage_hook: ManageHook,
     pub startup_hook: StartupHook,
     pub loghook: Option<LogHook>,
     pub wtftw_dir: String,
+    /// Run `cargo update` before building the config module
+    pub update_dependencies: bool,
+    /// Build the config module in release mode
+    pub release_build: bool,
+    /// Config modules replaced by a reload. They stay loaded, as layouts,
+    /// timers, drags and threads created by them might still be around.
+    /// There's no telling when they're gone, so a config module can only
+    /// be reloaded `MAX_RETIRED_LIBRARIES` times before a restart.
+    pub retired_libraries: Vec<DynamicLibrary>,
+    /// Problems found while configuring, reported once the config is loaded
+    pub errors: Vec<String>,
+    /// Bindings that replaced another one for the same key, reported by `check`
+    pub replaced_bindings: Vec<String>,
 }
 
 impl InternalConfig {
     pub fn new(manage_hook: ManageHook, startup_hook: StartupHook, home: String) -> InternalConfig {
         InternalConfig {
             library: None,
+            actions: ActionRegistry::new(),
             key_handlers: BTreeMap::new(),
+            key_release_handlers: BTreeMap::new(),
+            submaps: BTreeMap::new(),
             mouse_handlers: BTreeMap::new(),
+            mouse_release_handlers: BTreeMap::new(),
+            fd_handlers: BTreeMap::new(),
             manage_hook,
             startup_hook,
             loghook: None,
             wtftw_dir: format!("{}/.wtftw", home),
+            update_dependencies: false,
+            release_build: false,
+            retired_libraries: Vec::new(),
+            errors: Vec::new(),
+            replaced_bindings: Vec::new(),
         }
     }
 }
@@ -111,8 +169,10 @@ impl Config {
             border_color: 0x00444444,
             border_width: 2,
             mod_mask: KeyModifiers::MOD1MASK,
+            lock_mask: KeyModifiers::LOCKMASK | KeyModifiers::MOD2MASK,
             terminal: ("xterm".to_owned(), "".to_owned()),
             logfile: format!("{}/.wtftw.log", home),
+            log_level: LevelFilter::Warn,
             tags: vec![
                 "1: term".to_owned(),
                 "2: web".to_owned(),
@@ -126,6 +186,7 @@ impl Config {
                 increment_ratio: 0.3 / 100.0,
                 ratio: 0.5,
             }),
+            replaying: false,
         };
 
         let internal_config = InternalConfig::new(
@@ -142,6 +203,24 @@ impl Config {
         })
     }
 
+    /// The config traces are replayed with: the same one the window manager
+    /// loads, including the config module, but built against a headless
+    /// window system with the given screens. The startup hook isn't run, and
+    /// handlers that start programs or restart wtftw only log what they would
+    /// have done, see `GeneralConfig::replaying`.
+    pub fn for_replay(screens: Vec<Rectangle>) -> Result<Config> {
+        let mut config = Config::initialize()?;
+        let window_system = HeadlessWindowSystem::new(screens.clone());
+        // The config module gets a window manager, but its tags might not fit the screens yet
+        let mut general = config.general.clone();
+        general.tags = vec![String::new(); screens.len()];
+        let window_manager = WindowManager::new(&window_system, &general);
+
+        config.load(&window_manager, &window_system)?;
+        config.general.replaying = true;
+        Ok(config)
+    }
+
     pub fn default_manage_hook(m: Workspaces, _: Rc<dyn WindowSystem>, _: Window) -> Workspaces {
         m
     }
@@ -159,17 +238,13 @@ impl Config {
         self.add_key_handler(
             w.get_keycode_from_string("Return"),
             mod_mask | KeyModifiers::SHIFTMASK,
-            Box::new(|m, ws, c| start_terminal(m, ws, c)),
-        );
-        self.add_key_handler(
-            w.get_keycode_from_string("q"),
-            mod_mask,
-            Box::new(|m, ws, c| restart(m, ws, c).expect("error while restarting wtftw")),
+            "terminal",
         );
+        self.add_key_handler(w.get_keycode_from_string("q"), mod_mask, "restart");
         self.add_key_handler(
             w.get_keycode_from_string("q"),
             mod_mask | KeyModifiers::SHIFTMASK,
-            Box::new(|m, ws, c| exit(m, ws, c)),
+            "exit",
         );
     }
 
@@ -177,10 +252,235 @@ impl Config {
         self.general.mod_mask
     }
 
-    pub fn add_key_handler(&mut self, key: u64, mask: KeyModifiers, keyhandler: KeyHandler) {
+    /// Bind the key to either the name of an action in the registry,
+    /// e.g. `"focus-down"` or `"view:3"`, or to a boxed handler. Closures
+    /// need the types of their arguments, unless they're boxed as a
+    /// `KeyHandler`. An existing binding of the key is replaced, with a
+    /// warning, and `check` reports it.
+    pub fn add_key_handler<A: Into<KeyAction>>(&mut self, key: u64, mask: KeyModifiers, action: A) {
+        let action = action.into();
+        let key = KeyCommand::new(key, mask);
+        if let Some(existing) = self.internal.key_handlers.get(&key) {
+            let message = format!(
+                "{} was bound to {}, now to {}",
+                format_key(key, self.general.mod_mask),
+                existing,
+                action
+            );
+            warn!("{}", message);
+            self.internal.replaced_bindings.push(message);
+        }
+        self.check_action(&action);
+        self.internal.key_handlers.insert(key, action);
+    }
+
+    /// Bind the key given by an emacs style specification like `M-S-Return`,
+    /// see `keys::parse_key`. Several keys separated by spaces, like `M-x t`,
+    /// form a chord. Unlike `add_key_handler`, binding a key twice is an error.
+    pub fn add_key_binding<A: Into<KeyAction>>(
+        &mut self,
+        w: &dyn WindowSystem,
+        spec: &str,
+        action: A,
+    ) -> Result<()> {
+        self.bind(w, None, spec, action.into())
+    }
+
+    /// Add a submap, which is entered by binding a key to `KeyAction::Submap`
+    pub fn add_submap(&mut self, name: &str, submap: Submap) {
+        self.internal.submaps.insert(name.to_owned(), submap);
+    }
+
+    /// Bind the key given by the specification in the submap with the given name
+    pub fn add_submap_binding<A: Into<KeyAction>>(
+        &mut self,
+        w: &dyn WindowSystem,
+        submap: &str,
+        spec: &str,
+        action: A,
+    ) -> Result<()> {
+        self.bind(w, Some(submap), spec, action.into())
+    }
+
+    /// Bind an action to releasing the key given by the specification.
+    /// Releasing a modifier key, e.g. `Super_L`, doesn't count the
+    /// modifier itself, so it's bound without it.
+    pub fn add_key_release_binding<A: Into<KeyAction>>(
+        &mut self,
+        w: &dyn WindowSystem,
+        spec: &str,
+        action: A,
+    ) -> Result<()> {
+        let key = parse_key(spec, self.general.mod_mask, w)?;
+        let action = action.into();
+        if let Some(existing) = self.internal.key_release_handlers.get(&key) {
+            return Err(anyhow!(
+                "release of {} is already bound to {}",
+                spec,
+                existing
+            ));
+        }
+        self.check_action(&action);
+        self.internal.key_release_handlers.insert(key, action);
+        Ok(())
+    }
+
+    fn bind(
+        &mut self,
+        w: &dyn WindowSystem,
+        submap: Option<&str>,
+        spec: &str,
+        action: KeyAction,
+    ) -> Result<()> {
+        let words: Vec<&str> = spec.split_whitespace().collect();
+        let keys = words
+            .iter()
+            .map(|word| parse_key(word, self.general.mod_mask, w))
+            .collect::<Result<Vec<KeyCommand>>>()?;
+        let (&key, prefixes) = keys
+            .split_last()
+            .ok_or_else(|| anyhow!("missing key in {}", spec))?;
+
+        // Every key but the last enters a chord, named after the keys leading to it
+        let mut submap = submap.map(ToOwned::to_owned);
+        for (i, prefix) in prefixes.iter().enumerate() {
+            let chord = match self.keymap(submap.as_deref())?.get(prefix) {
+                Some(KeyAction::Submap(name)) => name.clone(),
+                Some(existing) => {
+                    return Err(anyhow!(
+                        "{} is already bound to {}",
+                        words[..=i].join(" "),
+                        existing
+                    ))
+                }
+                None => {
+                    let name = match submap {
+                        Some(ref parent) => format!("{} {}", parent, words[i]),
+                        None => words[i].to_owned(),
+                    };
+                    self.add_submap(&name, Submap::chord());
+                    self.keymap(submap.as_deref())?
+                        .insert(*prefix, KeyAction::Submap(name.clone()));
+                    name
+                }
+            };
+            submap = Some(chord);
+        }
+
+        self.check_action(&action);
+        let keymap = self.keymap(submap.as_deref())?;
+        if let Some(existing) = keymap.get(&key) {
+            return Err(anyhow!("{} is already bound to {}", spec, existing));
+        }
+        keymap.insert(key, action);
+        Ok(())
+    }
+
+    /// The bindings of the given submap, or the top level ones
+    fn keymap(&mut self, submap: Option<&str>) -> Result<&mut BTreeMap<KeyCommand, KeyAction>> {
+        match submap {
+            Some(name) => self
+                .internal
+                .submaps
+                .get_mut(name)
+                .map(|s| &mut s.keys)
+                .ok_or_else(|| anyhow!("unknown submap {}", name)),
+            None => Ok(&mut self.internal.key_handlers),
+        }
+    }
+
+    fn check_action(&self, action: &KeyAction) {
+        match *action {
+            KeyAction::Named(ref name) if !self.internal.actions.contains(name) => {
+                warn!("binding unknown action {}", name)
+            }
+            KeyAction::Submap(ref name) if !self.internal.submaps.contains_key(name) => {
+                warn!("binding unknown submap {}", name)
+            }
+            _ => (),
+        }
+    }
+
+    /// All key bindings with the action they refer to, e.g. for
+    /// a cheat sheet. Anonymous handlers don't have an action.
+    pub fn key_bindings(&self) -> Vec<(KeyCommand, Option<ActionInfo>)> {
         self.internal
             .key_handlers
-            .insert(KeyCommand::new(key, mask), keyhandler);
+            .iter()
+            .map(|(key, action)| {
+                let info = action.name().map(|name| ActionInfo {
+                    name: name.to_owned(),
+                    description: self.internal.actions.describe(name).unwrap_or_default(),
+                });
+                (*key, info)
+            })
+            .collect()
+    }
+
+    /// Describe everything about the config a recorded session depends on:
+    /// the tags, the layout, a few settings and all bindings, one per line.
+    /// Handlers can't be told apart, only that there is one.
+    pub fn describe(&self) -> Vec<String> {
+        let mod_mask = self.general.mod_mask;
+        let mut lines = vec![
+            format!("tags: {}", self.general.tags.join(", ")),
+            format!("layout: {}", self.general.layout.description()),
+            format!("border width: {}", self.general.border_width),
+            format!("focus follows mouse: {}", self.general.focus_follows_mouse),
+        ];
+        for (&key, action) in self.internal.key_handlers.iter() {
+            lines.push(format!("key {}: {}", format_key(key, mod_mask), action));
+        }
+        for (&key, action) in self.internal.key_release_handlers.iter() {
+            lines.push(format!("release {}: {}", format_key(key, mod_mask), action));
+        }
+        for (name, submap) in self.internal.submaps.iter() {
+            for (&key, action) in submap.keys.iter() {
+                lines.push(format!(
+                    "key {} in {}: {}",
+                    format_key(key, mod_mask),
+                    name,
+                    action
+                ));
+            }
+        }
+        for command in self.internal.mouse_handlers.keys() {
+            lines.push(format!("button {:?} with {:?}", command, command.mask));
+        }
+        for command in self.internal.mouse_release_handlers.keys() {
+            lines.push(format!("release {:?} with {:?}", command, command.mask));
+        }
+        lines
+    }
+
+    /// Grab the keys of all top level press and release bindings, releasing the old grabs first.
+    /// Needs to be repeated whenever the keyboard mapping changes, as the
+    /// grabs are for the physical keys the keysyms were on at the time.
+    pub fn grab_keys(&self, w: &dyn WindowSystem) {
+        w.ungrab_keys();
+        for (&key, action) in self.internal.key_handlers.iter() {
+            debug!(
+                "binding {} to {}",
+                format_key(key, self.general.mod_mask),
+                action
+            );
+        }
+        for (&key, action) in self.internal.key_release_handlers.iter() {
+            debug!(
+                "binding release of {} to {}",
+                format_key(key, self.general.mod_mask),
+                action
+            );
+        }
+
+        let keys: BTreeSet<KeyCommand> = self
+            .internal
+            .key_handlers
+            .keys()
+            .chain(self.internal.key_release_handlers.keys())
+            .cloned()
+            .collect();
+        w.grab_keys(keys.into_iter().collect());
     }
 
     pub fn add_mouse_handler(
@@ -194,6 +494,43 @@ impl Config {
             .insert(MouseCommand::new(button, mask), mousehandler);
     }
 
+    /// Call the handler when the button is released, with the window the
+    /// pointer is on. Buttons bound this way are grabbed like pressed ones.
+    pub fn add_mouse_release_handler(
+        &mut self,
+        button: MouseButton,
+        mask: KeyModifiers,
+        mousehandler: MouseHandler,
+    ) {
+        self.internal
+            .mouse_release_handlers
+            .insert(MouseCommand::new(button, mask), mousehandler);
+    }
+
+    /// The buttons of all press and release handlers, to be grabbed
+    pub fn mouse_bindings(&self) -> BTreeSet<MouseCommand> {
+        self.internal
+            .mouse_handlers
+            .keys()
+            .chain(self.internal.mouse_release_handlers.keys())
+            .cloned()
+            .collect()
+    }
+
+    /// Grab the buttons of all mouse bindings, releasing the old grabs first
+    pub fn grab_buttons(&self, w: &dyn WindowSystem) {
+        w.ungrab_buttons();
+        for command in self.mouse_bindings() {
+            w.grab_button(command);
+        }
+    }
+
+    /// Call the given handler whenever the file descriptor becomes readable.
+    /// The handler has to read the data itself.
+    pub fn add_fd_handler(&mut self, fd: RawFd, handler: FdHandler) {
+        self.internal.fd_handlers.insert(fd, handler);
+    }
+
     pub fn set_manage_hook(&mut self, hook: ManageHook) {
         self.internal.manage_hook = hook;
     }
@@ -216,119 +553,378 @@ impl Config {
             }
         }
 
+        // An existing manifest is never changed, it might have been edited
         if !path_exists(&toml) {
-            let file = File::create(Path::new(&toml).as_os_str());
-            file?.write_all(
-                "[project]\n\
-                                     name = \"config\"\n\
-                                     version = \"0.0.0\"\n\
-                                     authors = [\"wtftw\"]\n\n\
-                                     [dependencies.wtftw_contrib]
-                                     git = \"https://github.com/Kintaro/wtftw-contrib.git\"\n
-                                     [dependencies.wtftw]\n\
-                                     git = \"https://github.com/Kintaro/wtftw.git\"\n\n\
-                                     [lib]\n\
-                                     name = \"config\"\n\
-                                     crate-type = [\"dylib\"]"
-                    .as_bytes(),
-            )?;
-        }
-
-        let config_source = format!("{}/src/lib.rs", self.internal.wtftw_dir.clone());
-        if path_exists(&config_source) && self.compile()? {
-            self.call(m, w)?;
+            File::create(&toml)?.write_all(config_manifest().as_bytes())?;
+        } else if read_to_string(&toml)?.contains("github.com/Kintaro/wtftw.git") {
+            let migrated = format!("{}.new", toml);
+            File::create(&migrated)?.write_all(config_manifest().as_bytes())?;
+            warn!(
+                "{} depends on wtftw from GitHub, which needs network access and might not fit \
+                 this wtftw. To migrate, add your other dependencies, e.g. wtftw_contrib, to {} \
+                 and move it in its place.",
+                toml, migrated
+            );
+        }
+
+        let config_source = self.config_source();
+        let config_file = self.config_file();
+        if path_exists(&config_source) {
+            if let Err(e) = self.compile() {
+                let message = format!("{:#}", e);
+                error!("{}", message);
+                show_message(message);
+                self.default_configuration(w);
+            } else if let Err(e) = self.call(m, w) {
+                error!("{:#}", e);
+                self.default_configuration(w);
+            }
+        } else if path_exists(&config_file) {
+            self.load_config_file(&config_file, w);
         } else {
             self.default_configuration(w);
         }
+
+        for e in self.internal.errors.iter() {
+            error!("error in config: {}", e);
+        }
         Ok(())
     }
 
-    pub fn compile(&self) -> Result<bool> {
-        info!("updating dependencies");
-        Command::new("cargo")
-            .current_dir(&Path::new(&self.internal.wtftw_dir.clone()))
-            .arg("update")
-            .env("RUST_LOG", "none")
-            .output()?;
-        info!("compiling config module");
-        let output = Command::new("cargo")
-            .current_dir(&Path::new(&self.internal.wtftw_dir.clone()))
-            .arg("build") //.arg("--release")
-            .env("RUST_LOG", "none")
-            .output();
-
-        Ok(match output {
-            Ok(o) => {
-                if o.status.success() {
-                    info!("config module compiled");
-                    true
-                } else {
-                    error!("error compiling config module");
-
-                    spawn(move || {
-                        Command::new("xmessage").arg("\"error compiling config module. run 'cargo build' in ~/.wtftw to get more info.\"").spawn().unwrap();
-                    });
-                    false
+    /// Build the config again, the same way as at startup, and replace this
+    /// one with it. If the new config has any errors, this one stays in place.
+    /// Changes the config module makes to the window manager are dropped.
+    pub fn reload(&mut self, m: &WindowManager, w: &dyn WindowSystem) -> Result<()> {
+        if self.internal.library.is_some()
+            && self.internal.retired_libraries.len() >= MAX_RETIRED_LIBRARIES
+        {
+            return Err(anyhow!(
+                "the config module was reloaded {} times, restart wtftw to reload it again",
+                MAX_RETIRED_LIBRARIES
+            ));
+        }
+        let mut config = Config::initialize()?;
+        config.internal.wtftw_dir = self.internal.wtftw_dir.clone();
+        config.internal.update_dependencies = self.internal.update_dependencies;
+        config.internal.release_build = self.internal.release_build;
+        config.load(m, w)?;
+
+        let mut old = mem::replace(self, config);
+        // The new config starts its own pipes
+        for pipe in old.general.pipes.iter() {
+            let _ = pipe.write().unwrap().kill();
+        }
+        self.internal
+            .retired_libraries
+            .append(&mut old.internal.retired_libraries);
+        self.internal
+            .retired_libraries
+            .extend(old.internal.library.take());
+        Ok(())
+    }
+
+    /// Build the config from the config module or file, like at startup,
+    /// but fail on any error instead of falling back to the defaults
+    pub fn load(&mut self, m: &WindowManager, w: &dyn WindowSystem) -> Result<()> {
+        let config_source = self.config_source();
+        let config_file = self.config_file();
+        if path_exists(&config_source) {
+            self.compile()?;
+            self.call(&mut m.clone(), w)?;
+        } else if path_exists(&config_file) {
+            info!("loading config file {}", config_file);
+            self.apply_config_file(&config_file, w)
+                .with_context(|| format!("error in config file {}", config_file))?;
+        } else {
+            self.default_configuration(w);
+        }
+
+        if self.internal.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "errors in config:\n  {}",
+                self.internal.errors.join("\n  ")
+            ))
+        }
+    }
+
+    /// Look for problems that only show once the config is used: keys
+    /// and actions that don't exist, too few tags for the screens and
+    /// layouts that panic. The config is run against a headless window
+    /// system with the given screens and a few windows.
+    pub fn check(&self, screens: Vec<Rectangle>) -> Vec<String> {
+        let mut problems = Vec::new();
+        let tags = self.general.tags.len();
+        if tags < screens.len() {
+            problems.push(format!(
+                "{} tags for {} screens, every screen needs a workspace",
+                tags,
+                screens.len()
+            ));
+        }
+        problems.extend(self.internal.replaced_bindings.iter().cloned());
+
+        let submaps = self.internal.submaps.iter().flat_map(|(name, submap)| {
+            submap
+                .keys
+                .iter()
+                .map(move |(key, action)| (Some(name), key, action))
+        });
+        let bindings = self
+            .internal
+            .key_handlers
+            .iter()
+            .chain(self.internal.key_release_handlers.iter())
+            .map(|(key, action)| (None, key, action))
+            .chain(submaps);
+        for (submap, key, action) in bindings {
+            let place = match submap {
+                Some(name) => format!(" in submap {}", name),
+                None => String::new(),
+            };
+            if key.key == 0 {
+                problems.push(format!("{} is bound to an unknown key{}", action, place));
+            }
+            match *action {
+                KeyAction::Named(ref name) if !self.internal.actions.contains(name) => {
+                    problems.push(format!("unknown action {}{}", name, place))
+                }
+                KeyAction::Submap(ref name) if !self.internal.submaps.contains_key(name) => {
+                    problems.push(format!("unknown submap {}{}", name, place))
                 }
+                _ => (),
+            }
+        }
+
+        // Without enough tags, creating the workspaces panics anyway
+        if tags >= screens.len() {
+            if let Err(message) = self.try_layouts(screens) {
+                problems.push(format!("the layout panicked: {}", message));
             }
-            Err(err) => {
-                error!("error compiling config module");
-                spawn(move || {
-                    Command::new("xmessage")
-                        .arg(format!("{}", err))
-                        .spawn()
-                        .unwrap();
+        }
+        problems
+    }
+
+    /// Manage some windows and go through all layouts of the default one
+    fn try_layouts(&self, screens: Vec<Rectangle>) -> std::result::Result<(), String> {
+        let windows = 1..=CHECKED_WINDOWS;
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let headless = Rc::new(HeadlessWindowSystem::new(screens));
+            for window in windows.clone() {
+                headless.add_window(window, Rectangle(0, 0, 100, 100));
+            }
+            let w: Rc<dyn WindowSystem> = headless;
+
+            let mut m = WindowManager::new(w.deref(), &self.general);
+            for window in windows.clone() {
+                m = m.manage_with(w.deref(), window, &self.general, &|x| {
+                    (self.internal.manage_hook)(x.clone(), w.clone(), window)
                 });
-                false
             }
+            let layouts = self.general.layout.state().children.len().max(1);
+            for _ in 0..layouts {
+                m = m
+                    .send_layout_message(LayoutMessage::Next, w.deref(), &self.general)
+                    .windows(w.deref(), &self.general, &|x| x.clone());
+            }
+        }));
+
+        result.map_err(|panic| {
+            panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_owned())
         })
     }
 
+    /// The path of the config module's source
+    pub fn config_source(&self) -> String {
+        format!("{}/src/lib.rs", self.internal.wtftw_dir)
+    }
+
+    /// The path of the declarative config, used if there is no config module
+    pub fn config_file(&self) -> String {
+        format!("{}/config.toml", self.internal.wtftw_dir)
+    }
+
+    /// Apply the declarative config on top of the current one. Errors are
+    /// shown to the user, but don't keep the valid entries from being used.
+    /// The default key bindings are only used if the file has none.
+    pub fn load_config_file(&mut self, path: &str, w: &dyn WindowSystem) {
+        info!("loading config file {}", path);
+        let result = self.apply_config_file(path, w);
+        if result.is_err() && self.internal.key_handlers.is_empty() {
+            self.default_configuration(w);
+        }
+
+        if let Err(e) = result {
+            let message = format!("error in config file {}: {:#}", path, e);
+            error!("{}", message);
+            show_message(message);
+        }
+    }
+
+    /// Apply the declarative config, with the default key bindings if it has none
+    fn apply_config_file(&mut self, path: &str, w: &dyn WindowSystem) -> Result<()> {
+        let file = ConfigFile::load(path)?;
+        if file.keys.is_empty() {
+            self.default_configuration(w);
+        }
+        file.apply(self, w)
+    }
+
+    /// Where the output of the last config module build goes
+    pub fn build_log(&self) -> String {
+        format!("{}/build.log", self.internal.wtftw_dir)
+    }
+
+    /// The cargo profile the config module is built with
+    fn profile(&self) -> &str {
+        if self.internal.release_build {
+            "release"
+        } else {
+            "debug"
+        }
+    }
+
+    /// Build the config module, without touching the network unless the
+    /// dependencies are to be updated. The output goes to `build_log`.
+    pub fn compile(&self) -> Result<()> {
+        let dir = Path::new(&self.internal.wtftw_dir);
+        let log_path = self.build_log();
+        let log = File::create(&log_path)?;
+
+        if self.internal.update_dependencies {
+            info!("updating dependencies");
+            Command::new("cargo")
+                .current_dir(dir)
+                .arg("update")
+                .env("RUST_LOG", "none")
+                .stdout(log.try_clone()?)
+                .stderr(log.try_clone()?)
+                .status()?;
+        }
+
+        info!("compiling config module");
+        let mut build = Command::new("cargo");
+        build
+            .current_dir(dir)
+            .arg("build")
+            .env("RUST_LOG", "none")
+            .stdout(log.try_clone()?)
+            .stderr(log);
+        if self.internal.release_build {
+            build.arg("--release");
+        }
+        if !self.internal.update_dependencies {
+            build.arg("--offline");
+        }
+
+        match build.status() {
+            Ok(status) if status.success() => {
+                info!("config module compiled");
+                Ok(())
+            }
+            Ok(_) => Err(anyhow!("error compiling config module, see {}", log_path)),
+            Err(e) => Err(anyhow!("unable to run cargo: {}", e)),
+        }
+    }
+
     pub fn call(&mut self, m: &mut WindowManager, w: &dyn WindowSystem) -> Result<()> {
         debug!("looking for config module");
-        let mut contents = read_dir(&Path::new(&format!(
-            "{}/target/debug",
-            self.internal.wtftw_dir.clone()
-        )))?;
-        let libname = contents.find(|x| match *x {
-            Ok(ref y) => y
-                .path()
-                .into_os_string()
-                .as_os_str()
-                .to_str()
-                .map(|x| x.contains("libconfig.so"))
-                .unwrap_or(false),
-            Err(_) => false,
-        });
+        let target = format!("{}/target/{}", self.internal.wtftw_dir, self.profile());
+        let library = read_dir(&target)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .find(|path| {
+                path.to_str()
+                    .map(|x| x.contains("libconfig.so"))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| anyhow!("no config module in {}", target))?;
 
-        if let Ok(lib) = DynamicLibrary::open(Some(&Path::new(
-            &libname
-                .unwrap()
-                .unwrap()
-                .path()
-                .as_os_str()
-                .to_str()
-                .unwrap(),
-        ))) {
-            unsafe {
-                if let Ok(symbol) = lib.symbol("configure") {
-                    let result = mem::transmute::<
-                        *mut u8,
-                        extern "C" fn(&mut WindowManager, &dyn WindowSystem, &mut Config),
-                    >(symbol);
-
-                    self.internal.library = Some(lib);
-                    result(m, w, self);
-                } else {
-                    error!("Error loading config module")
-                }
-            }
+        // dlopen hands out the already loaded module for the same file,
+        // so every load gets a fresh copy to pick up a recompiled one
+        let loaded = Path::new(&target).join(format!(
+            "config-{}-{}.so",
+            process::id(),
+            LOADED_MODULES.fetch_add(1, Ordering::SeqCst)
+        ));
+        copy(&library, &loaded)?;
+        let lib = DynamicLibrary::open(Some(&loaded));
+        let _ = remove_file(&loaded);
+        let lib = lib.map_err(|e| anyhow!("error loading config module: {}", e))?;
+
+        unsafe {
+            let symbol = lib
+                .symbol("configure")
+                .map_err(|e| anyhow!("error loading config module: {}", e))?;
+            let result = mem::transmute::<
+                *mut u8,
+                extern "C" fn(&mut WindowManager, &dyn WindowSystem, &mut Config),
+            >(symbol);
+
+            self.internal.library = Some(lib);
+            result(m, w, self);
         }
 
         Ok(())
     }
 }
 
+/// The sources of the running wtftw, if it runs from where it was built,
+/// e.g. `target/release/wtftw` in a checkout, where they're in `core`.
+/// Installed binaries have none.
+fn wtftw_sources() -> Option<PathBuf> {
+    let is_this_wtftw = |dir: &Path| {
+        let manifest = read_to_string(dir.join("Cargo.toml")).ok()?;
+        let manifest = manifest.parse::<toml::Value>().ok()?;
+        let package = manifest
+            .get("package")
+            .or_else(|| manifest.get("project"))?;
+        Some(
+            package.get("name")?.as_str()? == env!("CARGO_PKG_NAME")
+                && package.get("version")?.as_str()? == env!("CARGO_PKG_VERSION"),
+        )
+    };
+    env::current_exe()
+        .ok()?
+        .ancestors()
+        .skip(1)
+        .flat_map(|dir| vec![dir.to_path_buf(), dir.join("core")])
+        .find(|dir| is_this_wtftw(dir).unwrap_or(false))
+}
+
+/// The manifest of the config module. It depends on the exact wtftw this
+/// is, from its sources if it runs from where it was built, so the module
+/// fits the running window manager and builds without network access.
+/// Otherwise it depends on the published version, which has to be fetched
+/// from the registry once, e.g. with `--update-config`. Other dependencies,
+/// like wtftw_contrib, have to be added by hand.
+pub fn config_manifest() -> String {
+    let dependency = match wtftw_sources() {
+        Some(source) => format!("path = \"{}\"", source.display()),
+        None => format!("version = \"={}\"", env!("CARGO_PKG_VERSION")),
+    };
+
+    format!(
+        "[package]\n\
+         name = \"config\"\n\
+         version = \"0.0.0\"\n\
+         authors = [\"wtftw\"]\n\
+         edition = \"2018\"\n\n\
+         [dependencies.wtftw]\n\
+         package = \"{}\"\n\
+         {}\n\n\
+         [lib]\n\
+         name = \"config\"\n\
+         crate-type = [\"dylib\"]\n",
+        env!("CARGO_PKG_NAME"),
+        dependency
+    )
+}
+
 fn path_exists(path: &str) -> bool {
     metadata(path).is_ok()
 }
@@ -0,0 +1,368 @@
+use crate::actions::KeyAction;
+use crate::config::Config;
+use crate::core::rational_rect::RationalRect;
+use crate::keys::{parse_modifier, Submap};
+use crate::layout::LayoutState;
+use crate::logging::parse_level;
+use crate::rules::{rules_manage_hook, Condition, Pattern, Rule, RuleAction};
+use crate::window_system::{KeyModifiers, Placement, WindowSystem, WindowType};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::time::Duration;
+
+/// A color, either as a number or as a string like `#b6ffb0`
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Color {
+    Value(u32),
+    Hex(String),
+}
+
+impl Color {
+    pub fn value(&self) -> Result<u32> {
+        match *self {
+            Color::Value(value) => Ok(value),
+            Color::Hex(ref hex) => {
+                let digits = hex
+                    .strip_prefix('#')
+                    .or_else(|| hex.strip_prefix("0x"))
+                    .unwrap_or(hex);
+                if digits.len() != 6 && digits.len() != 8 {
+                    return Err(anyhow!("invalid color {}", hex));
+                }
+                u32::from_str_radix(digits, 16).with_context(|| format!("invalid color {}", hex))
+            }
+        }
+    }
+}
+
+/// How a window property is matched in a rule: a plain string
+/// needs to match exactly, `{ contains = "..." }` matches a part
+/// of it and `{ regex = "..." }` a regular expression.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Matcher {
+    Exact(String),
+    Contains { contains: String },
+    Regex { regex: String },
+}
+
+impl Matcher {
+    pub fn pattern(&self) -> Result<Pattern> {
+        match *self {
+            Matcher::Exact(ref value) => Ok(Pattern::Exact(value.clone())),
+            Matcher::Contains { ref contains } => Ok(Pattern::Contains(contains.clone())),
+            Matcher::Regex { ref regex } => Pattern::regex(regex),
+        }
+    }
+}
+
+/// Places windows when they are managed, based on their class,
+/// role, title or type. All given properties need to match.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WindowRule {
+    pub class: Option<Matcher>,
+    pub role: Option<Matcher>,
+    pub title: Option<Matcher>,
+    #[serde(rename = "type")]
+    pub window_type: Option<WindowType>,
+    /// Float the window at its requested position
+    #[serde(default)]
+    pub float: bool,
+    /// Float the window at `[x, y, width, height]`, as parts of the screen
+    pub rect: Option<RationalRect>,
+    /// Float the window in the middle of the screen
+    #[serde(default)]
+    pub center: bool,
+    /// Move the window to the workspace with the given index
+    pub workspace: Option<u32>,
+    /// Move the window to the workspace with the given tag
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// Don't focus the window when it's managed
+    #[serde(default)]
+    pub skip_focus: bool,
+    pub border_width: Option<u32>,
+    /// Don't manage the window at all
+    #[serde(default)]
+    pub ignore: bool,
+}
+
+impl WindowRule {
+    /// Build the rule for the engine, checked against the configured tags
+    pub fn to_rule(&self, tags: &[String]) -> Result<Rule> {
+        let mut conditions = Vec::new();
+        if let Some(ref class) = self.class {
+            conditions.push(Condition::Class(class.pattern()?));
+        }
+        if let Some(ref role) = self.role {
+            conditions.push(Condition::Role(role.pattern()?));
+        }
+        if let Some(ref title) = self.title {
+            conditions.push(Condition::Title(title.pattern()?));
+        }
+        if let Some(window_type) = self.window_type {
+            if window_type.placement() == Placement::Ignore
+                || window_type.placement() == Placement::KeepBelow
+            {
+                return Err(anyhow!(
+                    "windows of type {:?} aren't managed, so no rule applies to them",
+                    window_type
+                ));
+            }
+            conditions.push(Condition::Type(window_type));
+        }
+        if conditions.is_empty() {
+            return Err(anyhow!("rule needs a class, role, title or type to match"));
+        }
+
+        if self.ignore {
+            return Ok(Rule::new(conditions, vec![RuleAction::Ignore]));
+        }
+
+        let mut actions = Vec::new();
+        if let Some(index) = self.workspace {
+            if index as usize >= tags.len() {
+                return Err(anyhow!("no workspace with index {}", index));
+            }
+            actions.push(RuleAction::Shift(index));
+        }
+        if let Some(ref tag) = self.tag {
+            if !tags.contains(tag) {
+                return Err(anyhow!("no workspace with tag {}", tag));
+            }
+            actions.push(RuleAction::ShiftToTag(tag.clone()));
+        }
+        if self.float {
+            actions.push(RuleAction::Float);
+        }
+        if let Some(rect) = self.rect {
+            actions.push(RuleAction::FloatAt(rect));
+        }
+        if self.center {
+            actions.push(RuleAction::Center);
+        }
+        if self.fullscreen {
+            actions.push(RuleAction::Fullscreen);
+        }
+        if let Some(width) = self.border_width {
+            actions.push(RuleAction::BorderWidth(width));
+        }
+        if self.skip_focus {
+            actions.push(RuleAction::SkipFocus);
+        }
+        Ok(Rule::new(conditions, actions))
+    }
+}
+
+/// A mode with its own key bindings, like a resize mode
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SubmapEntry {
+    /// The key that enters the submap
+    pub enter: String,
+    /// Stay in the submap until it's left with Escape,
+    /// instead of leaving it after the first key
+    #[serde(default = "default_sticky")]
+    pub sticky: bool,
+    /// Leave the submap after this many seconds without a key press
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>,
+}
+
+fn default_sticky() -> bool {
+    true
+}
+
+/// Bind the key to the named action, in the submap if one is given
+fn bind_action(
+    config: &mut Config,
+    window_system: &dyn WindowSystem,
+    submap: Option<&str>,
+    spec: &str,
+    action: &str,
+) -> Result<()> {
+    if !config.internal.actions.contains(action) {
+        return Err(anyhow!("unknown action {}", action));
+    }
+    match submap {
+        Some(submap) => config.add_submap_binding(window_system, submap, spec, action),
+        None => config.add_key_binding(window_system, spec, action),
+    }
+}
+
+/// A declarative alternative to the compiled config module,
+/// read from `~/.wtftw/config.toml`. Everything is optional
+/// and falls back to the defaults.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub focus_follows_mouse: Option<bool>,
+    pub focus_border_color: Option<Color>,
+    pub border_color: Option<Color>,
+    pub border_width: Option<u32>,
+    /// The terminal command, including its arguments
+    pub terminal: Option<String>,
+    pub logfile: Option<String>,
+    /// `error`, `warn`, `info`, `debug` or `trace`
+    pub log_level: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub launcher: Option<String>,
+    /// The modifier `M` stands for in key bindings, e.g. `mod4`
+    pub mod_mask: Option<String>,
+    /// The modifiers ignored in key bindings, e.g. `["lock", "mod2"]`
+    /// for CapsLock and NumLock
+    pub lock_mask: Option<Vec<String>>,
+    pub layout: Option<LayoutState>,
+    /// Key specifications mapped to action names
+    pub keys: BTreeMap<String, String>,
+    /// Like `keys`, but the actions run when the key is released
+    pub release_keys: BTreeMap<String, String>,
+    pub submaps: BTreeMap<String, SubmapEntry>,
+    pub rules: Vec<WindowRule>,
+}
+
+impl ConfigFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ConfigFile> {
+        let path = path.as_ref();
+        let content =
+            read_to_string(path).with_context(|| format!("unable to read {}", path.display()))?;
+        ConfigFile::parse(&content).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<ConfigFile> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Apply all settings to the config. Invalid entries are skipped,
+    /// so one typo doesn't cost all other settings, and reported
+    /// together in the error.
+    pub fn apply(&self, config: &mut Config, window_system: &dyn WindowSystem) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut report = |entry: &str, result: Result<()>| {
+            if let Err(e) = result {
+                errors.push(format!("{}: {:#}", entry, e));
+            }
+        };
+
+        let general = &mut config.general;
+        if let Some(focus_follows_mouse) = self.focus_follows_mouse {
+            general.focus_follows_mouse = focus_follows_mouse;
+        }
+        if let Some(ref color) = self.focus_border_color {
+            report(
+                "focus_border_color",
+                color.value().map(|c| general.focus_border_color = c),
+            );
+        }
+        if let Some(ref color) = self.border_color {
+            report(
+                "border_color",
+                color.value().map(|c| general.border_color = c),
+            );
+        }
+        if let Some(border_width) = self.border_width {
+            general.border_width = border_width;
+        }
+        if let Some(ref terminal) = self.terminal {
+            let mut parts = terminal.trim().splitn(2, ' ');
+            general.terminal = (
+                parts.next().unwrap_or_default().to_owned(),
+                parts.next().unwrap_or_default().trim().to_owned(),
+            );
+        }
+        if let Some(ref logfile) = self.logfile {
+            general.logfile = logfile.clone();
+        }
+        if let Some(ref level) = self.log_level {
+            report(
+                "log_level",
+                parse_level(level).map(|l| general.log_level = l),
+            );
+        }
+        if let Some(ref tags) = self.tags {
+            if tags.is_empty() {
+                report("tags", Err(anyhow!("at least one tag is needed")));
+            } else {
+                general.tags = tags.clone();
+            }
+        }
+        if let Some(ref launcher) = self.launcher {
+            general.launcher = launcher.clone();
+        }
+        if let Some(ref mod_mask) = self.mod_mask {
+            report(
+                "mod_mask",
+                parse_modifier(mod_mask).map(|m| general.mod_mask = m),
+            );
+        }
+        if let Some(ref locks) = self.lock_mask {
+            let lock_mask = locks
+                .iter()
+                .map(|name| parse_modifier(name))
+                .collect::<Result<Vec<KeyModifiers>>>();
+            report(
+                "lock_mask",
+                lock_mask.map(|m| general.lock_mask = m.into_iter().collect()),
+            );
+        }
+        if let Some(ref layout) = self.layout {
+            report("layout", layout.build().map(|l| general.layout = l));
+        }
+
+        for (spec, action) in self.keys.iter() {
+            let result = bind_action(config, window_system, None, spec, action);
+            report(&format!("keys.{}", spec), result);
+        }
+
+        for (spec, action) in self.release_keys.iter() {
+            let result = if config.internal.actions.contains(action) {
+                config.add_key_release_binding(window_system, spec, action.as_str())
+            } else {
+                Err(anyhow!("unknown action {}", action))
+            };
+            report(&format!("release_keys.{}", spec), result);
+        }
+
+        for (name, entry) in self.submaps.iter() {
+            config.add_submap(
+                name,
+                Submap {
+                    keys: BTreeMap::new(),
+                    sticky: entry.sticky,
+                    timeout: entry.timeout.map(Duration::from_secs),
+                },
+            );
+            let enter = KeyAction::Submap(name.clone());
+            report(
+                &format!("submaps.{}.enter", name),
+                config.add_key_binding(window_system, &entry.enter, enter),
+            );
+            for (spec, action) in entry.keys.iter() {
+                let result = bind_action(config, window_system, Some(name), spec, action);
+                report(&format!("submaps.{}.keys.{}", name, spec), result);
+            }
+        }
+
+        let mut rules = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            let result = rule.to_rule(&config.general.tags).map(|r| rules.push(r));
+            report(&format!("rules[{}]", i), result);
+        }
+        if !rules.is_empty() {
+            config.set_manage_hook(rules_manage_hook(rules));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid entries:\n  {}", errors.join("\n  ")))
+        }
+    }
+}
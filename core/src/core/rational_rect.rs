@@ -1,2 +1,4 @@
-#[derive(Clone, Copy, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct RationalRect(pub f32, pub f32, pub f32, pub f32);
@@ -1,8 +1,10 @@
+use serde::{Deserialize, Serialize};
+
 /// Handles focus tracking on a workspace.
 /// `focus` keeps track of the focused window's id
 /// and `up` and `down` are the windows above or
 /// below the focus stack respectively.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Stack<T> {
     pub focus: T,
     pub up: Vec<T>,
@@ -1,7 +1,8 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Layout, LayoutMessage};
+use crate::layout::{Layout, LayoutMessage, LayoutState};
 use crate::window_system::{Window, WindowSystem};
+use anyhow::Result;
 
 /// Represents a single workspace with a `tag` (name),
 /// `id`, a `layout` and a `stack` for all windows
@@ -112,6 +113,24 @@ impl Workspace {
         )
     }
 
+    /// Returns the state of the workspace's layout
+    pub fn layout_state(&self) -> LayoutState {
+        self.layout.state()
+    }
+
+    /// Apply the given state to a copy of the workspace's layout.
+    /// Fails if the state doesn't fit the layout.
+    pub fn with_layout_state(&self, state: &LayoutState) -> Result<Workspace> {
+        let mut layout = self.layout.copy();
+        layout.set_state(state)?;
+        Ok(Workspace::new(
+            self.id,
+            self.tag.clone(),
+            layout,
+            self.stack.clone(),
+        ))
+    }
+
     pub fn send_layout_message(
         &self,
         message: LayoutMessage,
@@ -18,6 +18,10 @@ pub struct Workspaces {
     pub hidden: Vec<Workspace>,
     /// A list of all floating windows
     pub floating: BTreeMap<Window, RationalRect>,
+    /// Border widths of the windows that don't use the configured one
+    pub borders: BTreeMap<Window, u32>,
+    /// Transient windows, like dialogs, mapped to the window they belong to
+    pub transients: BTreeMap<Window, Window>,
 }
 
 impl Clone for Workspaces {
@@ -27,6 +31,8 @@ impl Clone for Workspaces {
             visible: self.visible.clone(),
             hidden: self.hidden.clone(),
             floating: self.floating.clone(),
+            borders: self.borders.clone(),
+            transients: self.transients.clone(),
         }
     }
 }
@@ -64,6 +70,8 @@ impl Workspaces {
             visible: current.iter().skip(1).cloned().collect(),
             hidden: unseen,
             floating: BTreeMap::new(),
+            borders: BTreeMap::new(),
+            transients: BTreeMap::new(),
         }
     }
 
@@ -73,6 +81,8 @@ impl Workspaces {
             visible: self.visible.clone(),
             hidden: self.hidden.clone(),
             floating: self.floating.clone(),
+            borders: self.borders.clone(),
+            transients: self.transients.clone(),
         }
     }
 
@@ -82,6 +92,8 @@ impl Workspaces {
             visible,
             hidden: self.hidden.clone(),
             floating: self.floating.clone(),
+            borders: self.borders.clone(),
+            transients: self.transients.clone(),
         }
     }
 
@@ -91,6 +103,8 @@ impl Workspaces {
             visible: self.visible.clone(),
             hidden,
             floating: self.floating.clone(),
+            borders: self.borders.clone(),
+            transients: self.transients.clone(),
         }
     }
 
@@ -199,6 +213,37 @@ impl Workspaces {
         self.delete_p(window).sink(window)
     }
 
+    /// Drop everything remembered about the window, once it's gone.
+    /// Unlike `delete`, this isn't done when a window is only moved.
+    pub fn forget(&self, window: Window) -> Workspaces {
+        let mut w = self.clone();
+        w.borders.remove(&window);
+        w.transients.remove(&window);
+        w
+    }
+
+    /// Remember that the window is transient for the given parent
+    pub fn set_transient_for(&self, window: Window, parent: Window) -> Workspaces {
+        let mut w = self.clone();
+        w.transients.insert(window, parent);
+        w
+    }
+
+    /// Give the window its own border width, instead of the configured one
+    pub fn set_border_width(&self, window: Window, border_width: u32) -> Workspaces {
+        let mut w = self.clone();
+        w.borders.insert(window, border_width);
+        w
+    }
+
+    /// The border width of the window
+    pub fn border_width(&self, window: Window, config: &GeneralConfig) -> u32 {
+        self.borders
+            .get(&window)
+            .copied()
+            .unwrap_or(config.border_width)
+    }
+
     pub fn delete_p(&self, window: Window) -> Workspaces {
         fn remove_from_workspace(stack: Stack<Window>, window: Window) -> Option<Stack<Window>> {
             stack.filter(|&x| x != window)
@@ -294,6 +339,16 @@ impl Workspaces {
         )
     }
 
+    /// Apply the function to every workspace, visible or not
+    pub fn map_workspaces<F>(&self, f: F) -> Workspaces
+    where
+        F: Fn(Workspace) -> Workspace,
+    {
+        self.from_current(self.current.map_workspace(&f))
+            .from_visible(self.visible.iter().map(|s| s.map_workspace(&f)).collect())
+            .from_hidden(self.hidden.iter().cloned().map(&f).collect())
+    }
+
     pub fn get_focus_window(&self) -> Option<Window> {
         self.current.workspace.stack.clone().map(|s| s.focus)
     }
@@ -532,6 +587,8 @@ impl Workspaces {
             visible: self.visible.clone(),
             hidden: self.hidden.clone(),
             floating: map,
+            borders: self.borders.clone(),
+            transients: self.transients.clone(),
         }
     }
 }
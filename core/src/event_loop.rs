@@ -0,0 +1,145 @@
+use crate::window_manager::WindowManager;
+use crate::window_system::{WindowSystem, WindowSystemEvent};
+use std::collections::VecDeque;
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
+use std::thread::sleep;
+use std::time::Instant;
+
+/// The reason the event loop woke up
+pub enum Wakeup {
+    /// The window system sent an event
+    Event(WindowSystemEvent),
+    /// One of the watched file descriptors became readable
+    Readable(RawFd),
+    /// One of the file descriptors watched for writing became writable
+    Writable(RawFd),
+    /// At least one of the window manager's timers is due
+    Timers,
+}
+
+/// Waits on the connection to the window system together with
+/// additional file descriptors and the timers scheduled on the
+/// window manager, so none of them has to block the others.
+pub struct EventLoop {
+    window_system: Rc<dyn WindowSystem>,
+    fds: Vec<RawFd>,
+    writable_fds: Vec<RawFd>,
+    ready: VecDeque<Wakeup>,
+}
+
+impl EventLoop {
+    pub fn new(window_system: Rc<dyn WindowSystem>) -> EventLoop {
+        EventLoop {
+            window_system,
+            fds: Vec::new(),
+            writable_fds: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Report the file descriptor whenever it becomes readable
+    pub fn watch(&mut self, fd: RawFd) {
+        if !self.fds.contains(&fd) {
+            self.fds.push(fd);
+        }
+    }
+
+    /// Report the file descriptor whenever it becomes writable, e.g.
+    /// while there is output queued that it didn't take at once
+    pub fn watch_writable(&mut self, fd: RawFd) {
+        if !self.writable_fds.contains(&fd) {
+            self.writable_fds.push(fd);
+        }
+    }
+
+    /// Stop watching the file descriptor for both reading and
+    /// writing, e.g. before closing it
+    pub fn unwatch(&mut self, fd: RawFd) {
+        self.fds.retain(|&x| x != fd);
+        self.writable_fds.retain(|&x| x != fd);
+        self.ready.retain(|w| match *w {
+            Wakeup::Readable(x) | Wakeup::Writable(x) => x != fd,
+            _ => true,
+        });
+    }
+
+    /// Block until there is something to do. Ready file descriptors
+    /// come first, then due timers, then events from the window system.
+    pub fn next(&mut self, window_manager: &WindowManager) -> Wakeup {
+        loop {
+            if let Some(wakeup) = self.ready.pop_front() {
+                return wakeup;
+            }
+
+            let deadline = window_manager.next_deadline();
+            if deadline.is_some_and(|d| d <= Instant::now()) {
+                return Wakeup::Timers;
+            }
+
+            if self.window_system.event_pending() {
+                return Wakeup::Event(self.window_system.get_event());
+            }
+
+            let timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            let connection = self.window_system.get_connection_fd();
+
+            // Without anything to poll on, the best we can do is
+            // to wait for the next timer or block on the window system
+            if connection.is_none() && self.fds.is_empty() && self.writable_fds.is_empty() {
+                match timeout {
+                    Some(timeout) => sleep(timeout),
+                    None => return Wakeup::Event(self.window_system.get_event()),
+                }
+                continue;
+            }
+
+            self.poll(
+                connection,
+                timeout.map(|t| {
+                    // Round up, so we don't wake up just before the deadline
+                    t.as_micros().div_ceil(1000) as libc::c_int
+                }),
+            );
+        }
+    }
+
+    fn poll(&mut self, connection: Option<RawFd>, timeout: Option<libc::c_int>) {
+        let mut pollfds: Vec<libc::pollfd> = connection
+            .iter()
+            .chain(self.fds.iter())
+            .map(|&fd| (fd, libc::POLLIN))
+            .chain(self.writable_fds.iter().map(|&fd| (fd, libc::POLLOUT)))
+            .map(|(fd, events)| libc::pollfd {
+                fd,
+                events,
+                revents: 0,
+            })
+            .collect();
+
+        let result = unsafe {
+            libc::poll(
+                pollfds.as_mut_ptr(),
+                pollfds.len() as libc::nfds_t,
+                timeout.unwrap_or(-1),
+            )
+        };
+
+        if result < 0 {
+            // Interrupted by a signal, e.g. a dying child
+            return;
+        }
+
+        let skip = if connection.is_some() { 1 } else { 0 };
+        self.ready.extend(
+            pollfds
+                .iter()
+                .skip(skip)
+                .filter(|p| p.revents & (p.events | libc::POLLHUP | libc::POLLERR) != 0)
+                .map(|p| match p.events {
+                    libc::POLLOUT => Wakeup::Writable(p.fd),
+                    _ => Wakeup::Readable(p.fd),
+                }),
+        );
+    }
+}
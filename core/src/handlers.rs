@@ -3,6 +3,7 @@ use crate::core::workspaces::Workspaces;
 use crate::window_manager::WindowManager;
 use crate::window_system::Window;
 use crate::window_system::WindowSystem;
+use std::os::unix::io::RawFd;
 use std::rc::Rc;
 
 pub type KeyHandler =
@@ -12,15 +13,26 @@ pub type MouseHandler =
 pub type ManageHook = Box<dyn Fn(Workspaces, Rc<dyn WindowSystem>, Window) -> Workspaces>;
 pub type StartupHook = Box<dyn Fn(WindowManager, Rc<dyn WindowSystem>, &Config) -> WindowManager>;
 pub type LogHook = Box<dyn FnMut(WindowManager, Rc<dyn WindowSystem>)>;
+pub type TimerHandler =
+    Box<dyn Fn(WindowManager, Rc<dyn WindowSystem>, &GeneralConfig) -> WindowManager>;
+pub type FdHandler =
+    Box<dyn Fn(WindowManager, Rc<dyn WindowSystem>, &GeneralConfig, RawFd) -> WindowManager>;
 
 extern "C" {
     pub fn waitpid(fd: libc::pid_t, status: *mut libc::c_int, options: libc::c_int) -> libc::pid_t;
 }
 
+/// Reap all terminated child processes, so programs
+/// spawned by handlers don't linger around as zombies.
+pub fn collect_zombies() {
+    unsafe { while waitpid(-1, std::ptr::null_mut(), libc::WNOHANG) > 0 {} }
+}
+
 /// Some default handlers for easier config scripts
 pub mod default {
     use crate::config::GeneralConfig;
     use crate::core::workspaces::Workspaces;
+    use crate::session::{session_file, Session};
     use crate::window_manager::WindowManager;
     use crate::window_system::Window;
     use crate::window_system::WindowSystem;
@@ -40,6 +52,10 @@ pub mod default {
         _: Rc<dyn WindowSystem>,
         config: &GeneralConfig,
     ) -> WindowManager {
+        if config.replaying {
+            info!("not starting the terminal while replaying");
+            return window_manager;
+        }
         let (terminal, args) = config.terminal.clone();
         let arguments: Vec<String> = if args.is_empty() {
             Vec::new()
@@ -68,6 +84,10 @@ pub mod default {
         _: Rc<dyn WindowSystem>,
         config: &GeneralConfig,
     ) -> WindowManager {
+        if config.replaying {
+            info!("not starting the launcher while replaying");
+            return window_manager;
+        }
         let launcher = config.launcher.clone();
         spawn(move || {
             debug!("spawning launcher");
@@ -100,39 +120,56 @@ pub mod default {
 
     /// Restart the window manager by calling execvp and replacing the current binary
     /// with the new one in memory.
-    /// Pass a list of all windows to it via command line arguments
-    /// so it may resume work as usual.
+    /// The complete state is saved to the session file and a list of all windows
+    /// is passed via command line arguments, so it may resume work as usual even
+    /// if the session can't be restored.
     pub fn restart(
         window_manager: WindowManager,
         _: Rc<dyn WindowSystem>,
         c: &GeneralConfig,
     ) -> Result<WindowManager> {
+        if c.replaying {
+            info!("not restarting while replaying");
+            return Ok(window_manager);
+        }
         // Get absolute path to binary
         let filename = env::current_dir()?.join(&env::current_exe()?);
+        // Save the complete state for the new instance
+        if let Err(e) = Session::from_workspaces(&window_manager.workspaces).save(session_file()) {
+            error!("unable to save session: {}", e);
+        }
         // Collect all managed windows
         let window_ids: String =
             json!(&window_manager.workspaces.all_windows_with_workspaces()).to_string();
 
-        // Create arguments
-        let resume = &"--resume";
-        let windows = window_ids;
-        let filename_c = CString::new(filename.into_os_string().into_string().unwrap().as_bytes())?;
+        // Create arguments, keeping the options wtftw was started with. The window
+        // list of the last restart is replaced, and the trace isn't recorded again,
+        // as that would overwrite what was recorded so far.
+        let mut args = vec![
+            CString::new(filename.into_os_string().into_string().unwrap().as_bytes())?,
+            CString::new("--resume")?,
+            CString::new(window_ids.as_bytes())?,
+        ];
+        let mut options = env::args().skip(1);
+        while let Some(option) = options.next() {
+            if option == "-r" || option == "--resume" || option == "--record" {
+                options.next();
+            } else if !option.starts_with("-r")
+                && !option.starts_with("--resume=")
+                && !option.starts_with("--record=")
+            {
+                args.push(CString::new(option)?);
+            }
+        }
 
         for p in c.pipes.iter() {
             p.write().unwrap().wait()?;
         }
 
-        let resume_str = CString::new(resume.as_bytes())?;
-        let windows_str = CString::new(windows.as_bytes())?;
-
+        let mut pointers: Vec<*const i8> = args.iter().map(|arg| arg.as_ptr()).collect();
+        pointers.push(null());
         unsafe {
-            let slice: &mut [*const i8; 4] = &mut [
-                filename_c.as_ptr(),
-                resume_str.as_ptr(),
-                windows_str.as_ptr(),
-                null(),
-            ];
-            execvp(filename_c.as_ptr(), slice.as_mut_ptr());
+            execvp(pointers[0], pointers.as_ptr());
         }
 
         Ok(window_manager)
@@ -142,9 +179,32 @@ pub mod default {
     pub fn exit(w: WindowManager, _: Rc<dyn WindowSystem>, _: &GeneralConfig) -> WindowManager {
         WindowManager {
             running: false,
+            reload_requested: w.reload_requested,
             dragging: None,
             workspaces: w.workspaces,
             waiting_unmap: w.waiting_unmap,
+            timers: w.timers,
+            submaps: w.submaps,
+            docks: w.docks,
+            struts: w.struts,
+            desktops: w.desktops,
+        }
+    }
+
+    /// Reload the config once the current event is handled,
+    /// see `WindowManager::reload_config`
+    pub fn reload(w: WindowManager, _: Rc<dyn WindowSystem>, _: &GeneralConfig) -> WindowManager {
+        WindowManager {
+            running: w.running,
+            reload_requested: true,
+            dragging: w.dragging,
+            workspaces: w.workspaces,
+            waiting_unmap: w.waiting_unmap,
+            timers: w.timers,
+            submaps: w.submaps,
+            docks: w.docks,
+            struts: w.struts,
+            desktops: w.desktops,
         }
     }
 
@@ -0,0 +1,507 @@
+use crate::config::GeneralConfig;
+use crate::keycodes::{keysym_from_name, keysym_name};
+use crate::window_manager::WindowManager;
+use crate::window_system::*;
+use libc::c_ulong;
+use std::borrow::ToOwned;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::os::unix::io::RawFd;
+
+/// A single request that was sent to the headless window system.
+/// Every call that changes the state of a window is recorded,
+/// so tests can assert on the exact order of operations.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowSystemCall {
+    SetInitialProperties(Window),
+    SetBorderWidth(Window, u32),
+    SetBorderColor(Window, u32),
+    Resize(Window, u32, u32),
+    Move(Window, i32, i32),
+    Show(Window),
+    Hide(Window),
+    Focus(Window),
+    Configure(Window, u64, bool),
+    Restack(Vec<Window>),
+    Lower(Window),
+    WatchStruts(Window),
+    SetLockMask(KeyModifiers),
+    GrabKeys(Vec<KeyCommand>),
+    UngrabKeys,
+    GrabButton(MouseCommand),
+    UngrabButtons,
+    GrabPointer,
+    UngrabPointer,
+    GrabKeyboard,
+    UngrabKeyboard,
+    WarpPointer(Window, u32, u32),
+    Close(Window),
+    Kill(Window),
+}
+
+/// The in-memory state of a single window
+#[derive(Clone)]
+pub struct HeadlessWindow {
+    pub geometry: Rectangle,
+    pub border_width: u32,
+    pub border_color: u32,
+    pub name: String,
+    pub class: String,
+    pub role: String,
+    pub mapped: bool,
+    pub window_type: WindowType,
+    pub transient_for: Option<Window>,
+    pub override_redirect: bool,
+    pub strut: Option<Vec<u64>>,
+    pub partial_strut: Option<Vec<u64>>,
+    pub size_hint: SizeHint,
+}
+
+impl HeadlessWindow {
+    pub fn new(geometry: Rectangle) -> HeadlessWindow {
+        HeadlessWindow {
+            geometry,
+            border_width: 0,
+            border_color: 0,
+            name: "Unknown".to_owned(),
+            class: "unknown".to_owned(),
+            role: "unknown".to_owned(),
+            mapped: false,
+            window_type: WindowType::Normal,
+            transient_for: None,
+            override_redirect: false,
+            strut: None,
+            partial_strut: None,
+            size_hint: SizeHint {
+                min_size: None,
+                max_size: None,
+            },
+        }
+    }
+}
+
+/// A window system without any display connection. It keeps
+/// all windows, their geometry and the focus in memory and records
+/// every call made to it. Events are queued by hand with `push_event`.
+///
+/// Mostly used to test the window manager and layouts.
+pub struct HeadlessWindowSystem {
+    screens: Vec<Rectangle>,
+    windows: RefCell<BTreeMap<Window, HeadlessWindow>>,
+    focused: Cell<Window>,
+    pointer: Cell<(u32, u32)>,
+    events: RefCell<VecDeque<WindowSystemEvent>>,
+    calls: RefCell<Vec<WindowSystemCall>>,
+}
+
+impl HeadlessWindowSystem {
+    /// The id of the headless root window
+    pub const ROOT: Window = 1;
+
+    /// Create a new headless window system with the given physical screens
+    pub fn new(screens: Vec<Rectangle>) -> HeadlessWindowSystem {
+        HeadlessWindowSystem {
+            screens,
+            windows: RefCell::new(BTreeMap::new()),
+            focused: Cell::new(HeadlessWindowSystem::ROOT),
+            pointer: Cell::new((0, 0)),
+            events: RefCell::new(VecDeque::new()),
+            calls: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Create a new window with the given geometry. The window
+    /// is unmapped until the window manager shows it.
+    pub fn add_window(&self, window: Window, geometry: Rectangle) {
+        self.windows
+            .borrow_mut()
+            .insert(window, HeadlessWindow::new(geometry));
+    }
+
+    /// Remove the window, as if its client was closed
+    pub fn remove_window(&self, window: Window) {
+        self.windows.borrow_mut().remove(&window);
+    }
+
+    /// Retrieve a copy of the window's current state
+    pub fn window(&self, window: Window) -> Option<HeadlessWindow> {
+        self.windows.borrow().get(&window).cloned()
+    }
+
+    /// Checks if the window is currently mapped
+    pub fn is_mapped(&self, window: Window) -> bool {
+        self.window(window).is_some_and(|w| w.mapped)
+    }
+
+    /// Modify the state of the given window. Unknown windows
+    /// are created on the fly.
+    pub fn modify_window<F>(&self, window: Window, f: F)
+    where
+        F: FnOnce(&mut HeadlessWindow),
+    {
+        let mut windows = self.windows.borrow_mut();
+        f(windows
+            .entry(window)
+            .or_insert_with(|| HeadlessWindow::new(Rectangle(0, 0, 0, 0))));
+    }
+
+    pub fn set_window_name(&self, window: Window, name: &str) {
+        self.modify_window(window, |w| w.name = name.to_owned());
+    }
+
+    pub fn set_class_name(&self, window: Window, class: &str) {
+        self.modify_window(window, |w| w.class = class.to_owned());
+    }
+
+    pub fn set_role_name(&self, window: Window, role: &str) {
+        self.modify_window(window, |w| w.role = role.to_owned());
+    }
+
+    pub fn set_dock(&self, window: Window, dock: bool) {
+        let window_type = if dock {
+            WindowType::Dock
+        } else {
+            WindowType::Normal
+        };
+        self.set_window_type(window, window_type);
+    }
+
+    pub fn set_window_type(&self, window: Window, window_type: WindowType) {
+        self.modify_window(window, |w| w.window_type = window_type);
+    }
+
+    pub fn set_transient_for(&self, window: Window, parent: Option<Window>) {
+        self.modify_window(window, |w| w.transient_for = parent);
+    }
+
+    pub fn set_override_redirect(&self, window: Window, override_redirect: bool) {
+        self.modify_window(window, |w| w.override_redirect = override_redirect);
+    }
+
+    pub fn set_strut(&self, window: Window, strut: Option<Vec<u64>>) {
+        self.modify_window(window, |w| w.strut = strut);
+    }
+
+    pub fn set_partial_strut(&self, window: Window, strut: Option<Vec<u64>>) {
+        self.modify_window(window, |w| w.partial_strut = strut);
+    }
+
+    pub fn set_size_hints(&self, window: Window, size_hint: SizeHint) {
+        self.modify_window(window, |w| w.size_hint = size_hint);
+    }
+
+    pub fn set_pointer(&self, x: u32, y: u32) {
+        self.pointer.set((x, y));
+    }
+
+    /// Queue an event to be returned by `get_event`
+    pub fn push_event(&self, event: WindowSystemEvent) {
+        self.events.borrow_mut().push_back(event);
+    }
+
+    /// All calls recorded so far, in order
+    pub fn calls(&self) -> Vec<WindowSystemCall> {
+        self.calls.borrow().clone()
+    }
+
+    pub fn clear_calls(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    fn record(&self, call: WindowSystemCall) {
+        self.calls.borrow_mut().push(call);
+    }
+}
+
+impl WindowSystem for HeadlessWindowSystem {
+    fn get_string_from_keycode(&self, key: u32) -> String {
+        keysym_name(key as u64).unwrap_or_default().to_owned()
+    }
+
+    fn get_keycode_from_string(&self, key: &str) -> u64 {
+        keysym_from_name(key).unwrap_or(0)
+    }
+
+    fn get_root(&self) -> Window {
+        HeadlessWindowSystem::ROOT
+    }
+
+    fn get_screen_infos(&self) -> Vec<Rectangle> {
+        self.screens.clone()
+    }
+
+    fn get_number_of_screens(&self) -> usize {
+        self.screens.len()
+    }
+
+    fn get_display_width(&self, screen: usize) -> u32 {
+        self.screens.get(screen).map_or(0, |s| s.2)
+    }
+
+    fn get_display_height(&self, screen: usize) -> u32 {
+        self.screens.get(screen).map_or(0, |s| s.3)
+    }
+
+    fn get_window_name(&self, window: Window) -> String {
+        if window == HeadlessWindowSystem::ROOT {
+            return "root".to_owned();
+        }
+        self.window(window)
+            .map_or_else(|| "Unknown".to_owned(), |w| w.name)
+    }
+
+    fn get_class_name(&self, window: Window) -> String {
+        self.window(window)
+            .map_or_else(|| "unknown".to_owned(), |w| w.class)
+    }
+
+    fn get_role_name(&self, window: Window) -> String {
+        self.window(window)
+            .map_or_else(|| "unknown".to_owned(), |w| w.role)
+    }
+
+    fn get_windows(&self) -> Vec<Window> {
+        self.windows.borrow().keys().copied().collect()
+    }
+
+    fn set_window_border_width(&self, window: Window, border_width: u32) {
+        if window == HeadlessWindowSystem::ROOT {
+            return;
+        }
+        self.record(WindowSystemCall::SetBorderWidth(window, border_width));
+        self.modify_window(window, |w| w.border_width = border_width);
+    }
+
+    fn get_window_border_width(&self, window: Window) -> u32 {
+        self.window(window).map_or(0, |w| w.border_width)
+    }
+
+    fn set_window_border_color(&self, window: Window, border_color: u32) {
+        if window == HeadlessWindowSystem::ROOT {
+            return;
+        }
+        self.record(WindowSystemCall::SetBorderColor(window, border_color));
+        self.modify_window(window, |w| w.border_color = border_color);
+    }
+
+    fn resize_window(&self, window: Window, width: u32, height: u32) {
+        self.record(WindowSystemCall::Resize(window, width, height));
+        self.modify_window(window, |w| {
+            let Rectangle(x, y, _, _) = w.geometry;
+            w.geometry = Rectangle(x, y, width, height);
+        });
+    }
+
+    fn move_window(&self, window: Window, x: i32, y: i32) {
+        self.record(WindowSystemCall::Move(window, x, y));
+        self.modify_window(window, |w| {
+            let Rectangle(_, _, width, height) = w.geometry;
+            w.geometry = Rectangle(x, y, width, height);
+        });
+    }
+
+    fn show_window(&self, window: Window) {
+        self.record(WindowSystemCall::Show(window));
+        self.modify_window(window, |w| w.mapped = true);
+    }
+
+    fn hide_window(&self, window: Window) {
+        self.record(WindowSystemCall::Hide(window));
+        self.modify_window(window, |w| w.mapped = false);
+    }
+
+    fn focus_window(&self, window: Window, _: &WindowManager) {
+        self.record(WindowSystemCall::Focus(window));
+        self.focused.set(window);
+    }
+
+    fn get_focused_window(&self) -> Window {
+        self.focused.get()
+    }
+
+    fn configure_window(
+        &self,
+        window: Window,
+        window_changes: WindowChanges,
+        mask: u64,
+        is_floating: bool,
+    ) {
+        self.record(WindowSystemCall::Configure(window, mask, is_floating));
+        // Tiled windows keep their geometry, just like with a real X server
+        if !is_floating {
+            return;
+        }
+        self.modify_window(window, |w| {
+            let Rectangle(mut x, mut y, mut width, mut height) = w.geometry;
+            if mask & 0x1 != 0 {
+                x = window_changes.x as i32;
+            }
+            if mask & 0x2 != 0 {
+                y = window_changes.y as i32;
+            }
+            if mask & 0x4 != 0 {
+                width = window_changes.width;
+            }
+            if mask & 0x8 != 0 {
+                height = window_changes.height;
+            }
+            if mask & 0x10 != 0 {
+                w.border_width = window_changes.border_width;
+            }
+            w.geometry = Rectangle(x, y, width, height);
+        });
+    }
+
+    fn event_pending(&self) -> bool {
+        !self.events.borrow().is_empty()
+    }
+
+    fn get_connection_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    fn get_event(&self) -> WindowSystemEvent {
+        self.events
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(WindowSystemEvent::UnknownEvent)
+    }
+
+    fn flush(&self) {}
+
+    fn set_lock_mask(&self, locks: KeyModifiers) {
+        self.record(WindowSystemCall::SetLockMask(locks));
+    }
+
+    fn grab_keys(&self, keys: Vec<KeyCommand>) {
+        self.record(WindowSystemCall::GrabKeys(keys));
+    }
+
+    fn ungrab_keys(&self) {
+        self.record(WindowSystemCall::UngrabKeys);
+    }
+
+    fn grab_button(&self, button: MouseCommand) {
+        self.record(WindowSystemCall::GrabButton(button));
+    }
+
+    fn ungrab_buttons(&self) {
+        self.record(WindowSystemCall::UngrabButtons);
+    }
+
+    fn remove_enter_events(&self) {}
+
+    fn remove_motion_events(&self) {}
+
+    fn get_partial_strut(&self, window: Window) -> Option<Vec<u64>> {
+        self.window(window).and_then(|w| w.partial_strut)
+    }
+
+    fn get_strut(&self, window: Window) -> Option<Vec<u64>> {
+        self.window(window).and_then(|w| w.strut)
+    }
+
+    fn watch_struts(&self, window: Window) {
+        self.record(WindowSystemCall::WatchStruts(window));
+    }
+
+    fn set_initial_properties(&self, window: Window) {
+        self.record(WindowSystemCall::SetInitialProperties(window));
+    }
+
+    fn is_dock(&self, window: Window) -> bool {
+        matches!(
+            self.get_window_type(window),
+            WindowType::Dock | WindowType::Desktop
+        )
+    }
+
+    fn get_window_type(&self, window: Window) -> WindowType {
+        self.window(window)
+            .map_or(WindowType::Normal, |w| w.window_type)
+    }
+
+    fn get_transient_for(&self, window: Window) -> Option<Window> {
+        self.window(window).and_then(|w| w.transient_for)
+    }
+
+    fn get_geometry(&self, window: Window) -> Rectangle {
+        self.window(window)
+            .map_or(Rectangle(0, 0, 0, 0), |w| w.geometry)
+    }
+
+    fn get_size_hints(&self, window: Window) -> SizeHint {
+        self.window(window).map_or(
+            SizeHint {
+                min_size: None,
+                max_size: None,
+            },
+            |w| w.size_hint,
+        )
+    }
+
+    fn restack_windows(&self, windows: Vec<Window>) {
+        self.record(WindowSystemCall::Restack(windows));
+    }
+
+    fn lower_window(&self, window: Window) {
+        self.record(WindowSystemCall::Lower(window));
+    }
+
+    /// Closing a client destroys its window, so queue
+    /// the matching event for the window manager.
+    fn close_client(&self, window: Window) {
+        self.record(WindowSystemCall::Close(window));
+        self.remove_window(window);
+        self.push_event(WindowSystemEvent::WindowDestroyed(window));
+    }
+
+    fn kill_client(&self, window: Window) {
+        self.record(WindowSystemCall::Kill(window));
+        self.remove_window(window);
+        self.push_event(WindowSystemEvent::WindowDestroyed(window));
+    }
+
+    fn grab_pointer(&self) {
+        self.record(WindowSystemCall::GrabPointer);
+    }
+
+    fn ungrab_pointer(&self) {
+        self.record(WindowSystemCall::UngrabPointer);
+    }
+
+    fn grab_keyboard(&self) {
+        self.record(WindowSystemCall::GrabKeyboard);
+    }
+
+    fn ungrab_keyboard(&self) {
+        self.record(WindowSystemCall::UngrabKeyboard);
+    }
+
+    fn get_pointer(&self, _: Window) -> (u32, u32) {
+        self.pointer.get()
+    }
+
+    fn warp_pointer(&self, window: Window, x: u32, y: u32) {
+        self.record(WindowSystemCall::WarpPointer(window, x, y));
+        let Rectangle(wx, wy, _, _) = self.get_geometry(window);
+        self.pointer
+            .set(((wx + x as i32) as u32, (wy + y as i32) as u32));
+    }
+
+    fn overrides_redirect(&self, window: Window) -> bool {
+        self.window(window).is_some_and(|w| w.override_redirect)
+    }
+
+    fn update_server_state(&self, _: &WindowManager) {}
+
+    fn process_message(
+        &self,
+        window_manager: &WindowManager,
+        _: &GeneralConfig,
+        _: Window,
+        _: c_ulong,
+    ) -> WindowManager {
+        window_manager.clone()
+    }
+}
@@ -0,0 +1,667 @@
+use crate::actions::ActionInfo;
+use crate::config::Config;
+use crate::core::workspaces::Workspaces;
+use crate::layout::{LayoutMessage, LayoutState};
+use crate::window_manager::WindowManager;
+use crate::window_system::{Rectangle, Window, WindowSystem};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{remove_file, symlink_metadata, DirBuilder};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::ops::Deref;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The longest command a client may send, anything longer is dropped
+const MAX_COMMAND_LENGTH: usize = 64 * 1024;
+
+/// How far a client may fall behind taking its output before it's dropped
+const MAX_BACKLOG: usize = 1024 * 1024;
+
+/// A command sent by a client over the socket.
+/// Every request is a single line of JSON.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum IpcCommand {
+    /// Switch to the workspace with the given index
+    View { workspace: u32 },
+    /// Move the focused window to the workspace with the given index
+    Shift { workspace: u32 },
+    /// Send a message to the current layout
+    Layout { message: LayoutMessage },
+    /// Float the given window, or the focused one
+    Float { window: Option<Window> },
+    /// Sink the given floating window, or the focused one, back into the layout
+    Sink { window: Option<Window> },
+    /// Call an action from the registry by name, e.g. `focus-down` or `view:3`
+    Action { name: String },
+    /// List all actions in the registry
+    Actions,
+    /// Ask for the current state
+    Query,
+    /// Keep the connection open and receive an `IpcEvent`
+    /// for every change, one JSON object per line
+    Subscribe,
+}
+
+/// The answer to a single command
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IpcResponse {
+    Ok,
+    State { state: StateInfo },
+    Actions { actions: Vec<ActionInfo> },
+    Error { message: String },
+}
+
+/// A change of the window manager's state, sent to all subscribers
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum IpcEvent {
+    WorkspaceSwitched {
+        from: u32,
+        to: u32,
+    },
+    FocusChanged {
+        window: Option<Window>,
+    },
+    WindowManaged {
+        window: Window,
+        workspace: u32,
+    },
+    WindowUnmanaged {
+        window: Window,
+    },
+    LayoutChanged {
+        workspace: u32,
+        layout: String,
+        state: LayoutState,
+    },
+    ScreensChanged {
+        screens: Vec<Rectangle>,
+    },
+}
+
+impl IpcEvent {
+    /// Compare the window manager before and after a change
+    /// and describe everything that changed in between
+    pub fn diff(old: &WindowManager, new: &WindowManager) -> Vec<IpcEvent> {
+        let mut events = Vec::new();
+        let (old_ws, new_ws) = (&old.workspaces, &new.workspaces);
+
+        let screens = |w: &Workspaces| {
+            let mut screens: Vec<(u32, Rectangle)> = w
+                .screens()
+                .iter()
+                .map(|s| (s.screen_id, s.screen_detail))
+                .collect();
+            screens.sort_by_key(|&(id, _)| id);
+            screens.into_iter().map(|(_, r)| r).collect::<Vec<_>>()
+        };
+        if screens(old_ws) != screens(new_ws) {
+            events.push(IpcEvent::ScreensChanged {
+                screens: screens(new_ws),
+            });
+        }
+
+        if old_ws.current_tag() != new_ws.current_tag() {
+            events.push(IpcEvent::WorkspaceSwitched {
+                from: old_ws.current_tag(),
+                to: new_ws.current_tag(),
+            });
+        }
+
+        let old_windows: BTreeMap<Window, u32> =
+            old_ws.all_windows_with_workspaces().into_iter().collect();
+        let new_windows: BTreeMap<Window, u32> =
+            new_ws.all_windows_with_workspaces().into_iter().collect();
+        for window in old_windows.keys().filter(|w| !new_windows.contains_key(w)) {
+            events.push(IpcEvent::WindowUnmanaged { window: *window });
+        }
+        for (&window, &workspace) in new_windows.iter() {
+            if !old_windows.contains_key(&window) {
+                events.push(IpcEvent::WindowManaged { window, workspace });
+            }
+        }
+
+        let old_layouts: BTreeMap<u32, LayoutState> = old_ws
+            .workspaces()
+            .iter()
+            .map(|w| (w.id, w.layout_state()))
+            .collect();
+        for workspace in new_ws.workspaces().iter() {
+            let state = workspace.layout_state();
+            if old_layouts.get(&workspace.id) != Some(&state) {
+                events.push(IpcEvent::LayoutChanged {
+                    workspace: workspace.id,
+                    layout: workspace.layout.description(),
+                    state,
+                });
+            }
+        }
+
+        if old_ws.peek() != new_ws.peek() {
+            events.push(IpcEvent::FocusChanged {
+                window: new_ws.peek(),
+            });
+        }
+
+        events
+    }
+}
+
+/// A single workspace, as reported by the query command
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspaceInfo {
+    pub id: u32,
+    pub tag: String,
+    /// The screen the workspace is shown on, if it is visible
+    pub screen: Option<u32>,
+    pub layout: String,
+    pub layout_state: LayoutState,
+    pub windows: Vec<Window>,
+    pub focused: Option<Window>,
+}
+
+/// The state of the window manager, as reported by the query command
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StateInfo {
+    /// Index of the current workspace
+    pub current: u32,
+    /// The focused window, if any
+    pub focused: Option<Window>,
+    pub workspaces: Vec<WorkspaceInfo>,
+    pub floating: Vec<Window>,
+}
+
+impl StateInfo {
+    pub fn from_window_manager(window_manager: &WindowManager) -> StateInfo {
+        let workspaces = &window_manager.workspaces;
+        let mut infos: Vec<WorkspaceInfo> = workspaces
+            .workspaces()
+            .iter()
+            .map(|w| WorkspaceInfo {
+                id: w.id,
+                tag: w.tag.clone(),
+                screen: workspaces
+                    .screens()
+                    .iter()
+                    .find(|s| s.workspace.id == w.id)
+                    .map(|s| s.screen_id),
+                layout: w.layout.description(),
+                layout_state: w.layout_state(),
+                windows: w.windows(),
+                focused: w.peek(),
+            })
+            .collect();
+        infos.sort_by_key(|w| w.id);
+
+        StateInfo {
+            current: workspaces.current_tag(),
+            focused: workspaces.peek(),
+            workspaces: infos,
+            floating: workspaces.floating.keys().copied().collect(),
+        }
+    }
+}
+
+impl IpcCommand {
+    /// Execute the command against the given window manager
+    pub fn execute(
+        self,
+        window_manager: &WindowManager,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &Config,
+    ) -> Result<(WindowManager, IpcResponse)> {
+        let actions = &config.internal.actions;
+        let config = &config.general;
+        let check_workspace = |index: u32| {
+            if (index as usize) < config.tags.len() {
+                Ok(index)
+            } else {
+                Err(anyhow!("no workspace with index {}", index))
+            }
+        };
+        let managed_window =
+            |window: Option<Window>| match window.or_else(|| window_manager.workspaces.peek()) {
+                Some(w) if window_manager.is_window_managed(w) => Ok(w),
+                Some(w) => Err(anyhow!("window {} is not managed", w)),
+                None => Err(anyhow!("no window focused")),
+            };
+
+        let result = match self {
+            IpcCommand::View { workspace } => {
+                window_manager.view(window_system.deref(), check_workspace(workspace)?, config)
+            }
+            IpcCommand::Shift { workspace } => window_manager.move_window_to_workspace(
+                window_system.deref(),
+                config,
+                check_workspace(workspace)?,
+            ),
+            IpcCommand::Layout { message } => window_manager
+                .send_layout_message(message, window_system.deref(), config)
+                .windows(window_system.deref(), config, &|x| x.clone()),
+            IpcCommand::Float { window } => {
+                window_manager.float(window_system.deref(), config, managed_window(window)?)
+            }
+            IpcCommand::Sink { window } => {
+                let window = managed_window(window)?;
+                window_manager.windows(window_system.deref(), config, &|x| x.sink(window))
+            }
+            IpcCommand::Action { name } => {
+                actions.call(&name, window_manager.clone(), window_system.clone(), config)?
+            }
+            IpcCommand::Actions => {
+                let actions = actions.list();
+                return Ok((window_manager.clone(), IpcResponse::Actions { actions }));
+            }
+            IpcCommand::Query => {
+                let state = StateInfo::from_window_manager(window_manager);
+                return Ok((window_manager.clone(), IpcResponse::State { state }));
+            }
+            IpcCommand::Subscribe => {
+                return Err(anyhow!("subscribing needs a connection to the server"))
+            }
+        };
+
+        Ok((result, IpcResponse::Ok))
+    }
+}
+
+/// The default location of the socket. It lives in `$XDG_RUNTIME_DIR`, or in
+/// a directory of its own in the temporary directory if that isn't set. Only
+/// the user may access that directory, otherwise it isn't used, so no other
+/// user can put their own socket in its place.
+pub fn socket_path() -> Result<PathBuf> {
+    let dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => return Ok(PathBuf::from(dir).join("wtftw.sock")),
+        None => env::temp_dir().join(format!("wtftw-{}", unsafe { libc::getuid() })),
+    };
+
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Err(ref e) if e.kind() != ErrorKind::AlreadyExists => {
+            return Err(anyhow!("unable to create {}: {}", dir.display(), e))
+        }
+        _ => (),
+    }
+    let metadata = symlink_metadata(&dir)?;
+    if !metadata.is_dir()
+        || metadata.uid() != unsafe { libc::getuid() }
+        || metadata.mode() & 0o077 != 0
+    {
+        return Err(anyhow!(
+            "{} is not a directory only the user may access",
+            dir.display()
+        ));
+    }
+    Ok(dir.join("wtftw.sock"))
+}
+
+/// Send a single command to a running window manager and wait for the response
+pub fn send_command<P: AsRef<Path>>(path: P, command: &IpcCommand) -> Result<IpcResponse> {
+    let mut stream = UnixStream::connect(path.as_ref())
+        .with_context(|| format!("unable to connect to {}", path.as_ref().display()))?;
+    writeln!(stream, "{}", serde_json::to_string(command)?)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).context("invalid response")
+}
+
+/// Subscribe to a running window manager and return all events it sends
+pub fn subscribe<P: AsRef<Path>>(path: P) -> Result<impl Iterator<Item = Result<IpcEvent>>> {
+    let mut stream = UnixStream::connect(path.as_ref())
+        .with_context(|| format!("unable to connect to {}", path.as_ref().display()))?;
+    writeln!(stream, "{}", serde_json::to_string(&IpcCommand::Subscribe)?)?;
+
+    let mut lines = BufReader::new(stream).lines();
+    let response = lines.next().ok_or_else(|| anyhow!("no response"))??;
+    match serde_json::from_str(&response).context("invalid response")? {
+        IpcResponse::Error { message } => Err(anyhow!(message)),
+        _ => Ok(lines.map(|line| serde_json::from_str(&line?).context("invalid event"))),
+    }
+}
+
+/// A client that hasn't sent a complete command yet
+struct PendingClient {
+    stream: UnixStream,
+    input: Vec<u8>,
+}
+
+impl PendingClient {
+    /// Read everything the client has sent so far. Returns the command
+    /// once there is a complete line, or whatever is left once the client
+    /// hung up, and `None` while the line is still incomplete.
+    fn read_command(&mut self) -> Result<Option<String>> {
+        let mut buffer = [0; 4096];
+        loop {
+            if let Some(end) = self.input.iter().position(|&b| b == b'\n') {
+                let line = self.input.drain(..=end).collect::<Vec<u8>>();
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+            if self.input.len() > MAX_COMMAND_LENGTH {
+                return Err(anyhow!(
+                    "command is longer than {} bytes",
+                    MAX_COMMAND_LENGTH
+                ));
+            }
+
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Ok(Some(String::from_utf8_lossy(&self.input).into_owned())),
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// A client waiting for the response to its command, or a subscriber,
+/// with the output it hasn't taken yet
+struct Connection {
+    stream: UnixStream,
+    output: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: UnixStream) -> Connection {
+        Connection {
+            stream,
+            output: Vec::new(),
+        }
+    }
+
+    /// Queue the value as a line of JSON, see `flush`
+    fn send<T: Serialize>(&mut self, value: &T) {
+        match serde_json::to_vec(value) {
+            Ok(line) => {
+                self.output.extend_from_slice(&line);
+                self.output.push(b'\n');
+            }
+            Err(e) => error!("unable to serialize response: {}", e),
+        }
+    }
+
+    /// Write as much of the queued output as the client takes without
+    /// blocking. Fails if it went away or fell too far behind.
+    fn flush(&mut self) -> Result<()> {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(anyhow!("client closed the connection")),
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if self.output.len() > MAX_BACKLOG {
+            Err(anyhow!(
+                "client fell behind by more than {} bytes",
+                MAX_BACKLOG
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Listens on a Unix domain socket for commands. The listener and all clients
+/// are non-blocking, so the event loop can wait on their file descriptors
+/// together with the window system's connection. Commands are read as they
+/// arrive and responses and events are written as far as the clients take
+/// them, so a slow client never holds up the window manager.
+pub struct IpcServer {
+    listener: UnixListener,
+    path: PathBuf,
+    pending: RefCell<Vec<PendingClient>>,
+    replies: RefCell<Vec<Connection>>,
+    subscribers: RefCell<Vec<Connection>>,
+}
+
+impl IpcServer {
+    /// Listen on the given path. A stale socket of a previous instance is
+    /// replaced, but a socket with a running window manager behind it isn't,
+    /// and neither is anything that belongs to another user.
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<IpcServer> {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(metadata) = symlink_metadata(&path) {
+            if metadata.uid() != unsafe { libc::getuid() } {
+                return Err(anyhow!("{} belongs to another user", path.display()));
+            }
+            if UnixStream::connect(&path).is_ok() {
+                return Err(anyhow!("{} is already in use", path.display()));
+            }
+            remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("unable to listen on {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+        Ok(IpcServer {
+            listener,
+            path,
+            pending: RefCell::new(Vec::new()),
+            replies: RefCell::new(Vec::new()),
+            subscribers: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.borrow().is_empty()
+    }
+
+    /// Whether the file descriptor is the listener or one of the clients
+    pub fn owns(&self, fd: RawFd) -> bool {
+        let connected = |c: &Connection| c.stream.as_raw_fd() == fd;
+        fd == self.as_raw_fd()
+            || self.client_fds().contains(&fd)
+            || self.replies.borrow().iter().any(connected)
+            || self.subscribers.borrow().iter().any(connected)
+    }
+
+    /// The file descriptors of the clients that are still sending their
+    /// command. The event loop needs to watch them as well as the listener.
+    pub fn client_fds(&self) -> Vec<RawFd> {
+        self.pending
+            .borrow()
+            .iter()
+            .map(|c| c.stream.as_raw_fd())
+            .collect()
+    }
+
+    /// The file descriptors of the clients that haven't taken all of their
+    /// output yet. The event loop needs to watch them for writing, so the
+    /// rest is sent once they are ready for it.
+    pub fn output_fds(&self) -> Vec<RawFd> {
+        self.replies
+            .borrow()
+            .iter()
+            .chain(self.subscribers.borrow().iter())
+            .filter(|c| !c.output.is_empty())
+            .map(|c| c.stream.as_raw_fd())
+            .collect()
+    }
+
+    /// Accept all new clients and execute the commands of every client
+    /// that has sent a complete line. The others are kept until they do.
+    /// Queued output is sent as far as the clients take it.
+    pub fn handle_clients(
+        &self,
+        window_manager: WindowManager,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+    ) -> WindowManager {
+        self.handle_clients_with(window_manager, window_system, config, &mut |_| ())
+    }
+
+    /// Like `handle_clients`, but call `executed` with every command
+    /// that was executed successfully, e.g. to record it
+    pub fn handle_clients_with(
+        &self,
+        mut window_manager: WindowManager,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+        executed: &mut dyn FnMut(&IpcCommand),
+    ) -> WindowManager {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.pending.borrow_mut().push(PendingClient {
+                        stream,
+                        input: Vec::new(),
+                    }),
+                    Err(e) => warn!("ipc client failed: {}", e),
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("unable to accept ipc client: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let mut ready = Vec::new();
+        for mut client in self.pending.replace(Vec::new()) {
+            match client.read_command() {
+                // Connecting without a command, e.g. to check if the server is up
+                Ok(Some(ref line)) if line.is_empty() => (),
+                Ok(Some(line)) => ready.push((client.stream, line)),
+                Ok(None) => self.pending.borrow_mut().push(client),
+                Err(e) => warn!("ipc client failed: {}", e),
+            }
+        }
+
+        for (stream, line) in ready {
+            window_manager = self.serve(
+                stream,
+                &line,
+                &window_manager,
+                window_system,
+                config,
+                executed,
+            );
+        }
+        self.flush();
+        window_manager
+    }
+
+    /// Send as much of the queued output as the clients take. Clients
+    /// are disconnected once they got their response, subscribers once
+    /// they went away or fell too far behind.
+    fn flush(&self) {
+        self.replies
+            .borrow_mut()
+            .retain_mut(|client| match client.flush() {
+                Ok(()) => !client.output.is_empty(),
+                Err(e) => {
+                    warn!("ipc client failed: {:#}", e);
+                    false
+                }
+            });
+        self.subscribers
+            .borrow_mut()
+            .retain_mut(|subscriber| match subscriber.flush() {
+                Ok(()) => true,
+                Err(e) => {
+                    info!("dropping ipc subscriber: {:#}", e);
+                    false
+                }
+            });
+    }
+
+    /// Send everything that changed between the two states to all subscribers.
+    /// Whatever a subscriber doesn't take right away is queued and sent once
+    /// it's writable again, see `output_fds`, or along with the next
+    /// notification. Subscribers that went away or fell too far behind are dropped.
+    pub fn notify(&self, old: &WindowManager, new: &WindowManager) {
+        if self.subscribers.borrow().is_empty() {
+            return;
+        }
+
+        let mut lines = String::new();
+        for event in IpcEvent::diff(old, new).iter() {
+            match serde_json::to_string(event) {
+                Ok(line) => {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                }
+                Err(e) => error!("unable to serialize event: {}", e),
+            }
+        }
+
+        for subscriber in self.subscribers.borrow_mut().iter_mut() {
+            subscriber.output.extend_from_slice(lines.as_bytes());
+        }
+        self.flush();
+    }
+
+    /// Execute the command the client sent and queue the response
+    fn serve(
+        &self,
+        stream: UnixStream,
+        line: &str,
+        window_manager: &WindowManager,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+        executed: &mut dyn FnMut(&IpcCommand),
+    ) -> WindowManager {
+        let mut client = Connection::new(stream);
+        let command = serde_json::from_str::<IpcCommand>(line).context("invalid command");
+        if let Ok(IpcCommand::Subscribe) = command {
+            client.send(&IpcResponse::Ok);
+            self.subscribers.borrow_mut().push(client);
+            return window_manager.clone();
+        }
+
+        let result = command.and_then(|c| {
+            c.clone()
+                .execute(window_manager, window_system, config)
+                .map(|result| (c, result))
+        });
+
+        let (new_window_manager, response) = match result {
+            Ok((command, (w, response))) => {
+                executed(&command);
+                w.publish(window_system, config);
+                self.notify(window_manager, &w);
+                (w, response)
+            }
+            Err(e) => (
+                window_manager.clone(),
+                IpcResponse::Error {
+                    message: format!("{:#}", e),
+                },
+            ),
+        };
+
+        client.send(&response);
+        self.replies.borrow_mut().push(client);
+        new_window_manager
+    }
+}
+
+impl AsRawFd for IpcServer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
@@ -1,348 +1,565 @@
-enum KeyCode {
-    KeyCode_BackSpace,
-    KeyCode_Tab,
-    KeyCode_Linefeed,
-    KeyCode_Clear,
-    KeyCode_Return,
-    KeyCode_Pause,
-    KeyCode_Scroll_Lock,
-    KeyCode_Sys_Req,
-    KeyCode_Escape,
-    KeyCode_Delete,
-    KeyCode_Multi_key,
-    KeyCode_Codeinput,
-    KeyCode_SingleCandidate,
-    KeyCode_MultipleCandidate,
-    KeyCode_PreviousCandidate,
-    KeyCode_Home,
-    KeyCode_Left,
-    KeyCode_Up,
-    KeyCode_Right,
-    KeyCode_Down,
-    KeyCode_Prior,
-    KeyCode_Page_Up,
-    KeyCode_Next,
-    KeyCode_Page_Down,
-    KeyCode_End,
-    KeyCode_Begin,
-    KeyCode_Select,
-    KeyCode_Print,
-    KeyCode_Execute,
-    KeyCode_Insert,
-    KeyCode_Undo,
-    KeyCode_Redo,
-    KeyCode_Menu,
-    KeyCode_Find,
-    KeyCode_Cancel,
-    KeyCode_Help,
-    KeyCode_Break,
-    KeyCode_Mode_switch,
-    KeyCode_script_switch,
-    KeyCode_Num_Lock,
-    KeyCode_KP_Space,
-    KeyCode_KP_Tab,
-    KeyCode_KP_Enter,
-    KeyCode_KP_F1,
-    KeyCode_KP_F2,
-    KeyCode_KP_F3,
-    KeyCode_KP_F4,
-    KeyCode_KP_Home,
-    KeyCode_KP_Left,
-    KeyCode_KP_Up,
-    KeyCode_KP_Right,
-    KeyCode_KP_Down,
-    KeyCode_KP_Prior,
-    KeyCode_KP_Page_Up,
-    KeyCode_KP_Next,
-    KeyCode_KP_Page_Down,
-    KeyCode_KP_End,
-    KeyCode_KP_Begin,
-    KeyCode_KP_Insert,
-    KeyCode_KP_Delete,
-    KeyCode_KP_Equal,
-    KeyCode_KP_Multiply,
-    KeyCode_KP_Add,
-    KeyCode_KP_Separator,
-    KeyCode_KP_Subtract,
-    KeyCode_KP_Decimal,
-    KeyCode_KP_Divide,
-    KeyCode_KP_0,
-    KeyCode_KP_1,
-    KeyCode_KP_2,
-    KeyCode_KP_3,
-    KeyCode_KP_4,
-    KeyCode_KP_5,
-    KeyCode_KP_6,
-    KeyCode_KP_7,
-    KeyCode_KP_8,
-    KeyCode_KP_9,
-    KeyCode_F1,
-    KeyCode_F2,
-    KeyCode_F3,
-    KeyCode_F4,
-    KeyCode_F5,
-    KeyCode_F6,
-    KeyCode_F7,
-    KeyCode_F8,
-    KeyCode_F9,
-    KeyCode_F10,
-    KeyCode_F11,
-    KeyCode_L1,
-    KeyCode_F12,
-    KeyCode_L2,
-    KeyCode_F13,
-    KeyCode_L3,
-    KeyCode_F14,
-    KeyCode_L4,
-    KeyCode_F15,
-    KeyCode_L5,
-    KeyCode_F16,
-    KeyCode_L6,
-    KeyCode_F17,
-    KeyCode_L7,
-    KeyCode_F18,
-    KeyCode_L8,
-    KeyCode_F19,
-    KeyCode_L9,
-    KeyCode_F20,
-    KeyCode_L10,
-    KeyCode_F21,
-    KeyCode_R1,
-    KeyCode_F22,
-    KeyCode_R2,
-    KeyCode_F23,
-    KeyCode_R3,
-    KeyCode_F24,
-    KeyCode_R4,
-    KeyCode_F25,
-    KeyCode_R5,
-    KeyCode_F26,
-    KeyCode_R6,
-    KeyCode_F27,
-    KeyCode_R7,
-    KeyCode_F28,
-    KeyCode_R8,
-    KeyCode_F29,
-    KeyCode_R9,
-    KeyCode_F30,
-    KeyCode_R10,
-    KeyCode_F31,
-    KeyCode_R11,
-    KeyCode_F32,
-    KeyCode_R12,
-    KeyCode_F33,
-    KeyCode_R13,
-    KeyCode_F34,
-    KeyCode_R14,
-    KeyCode_F35,
-    KeyCode_R15,
-    KeyCode_Shift_L,
-    KeyCode_Shift_R,
-    KeyCode_Control_L,
-    KeyCode_Control_R,
-    KeyCode_Caps_Lock,
-    KeyCode_Shift_Lock,
-    KeyCode_Meta_L,
-    KeyCode_Meta_R,
-    KeyCode_Alt_L,
-    KeyCode_Alt_R,
-    KeyCode_Super_L,
-    KeyCode_Super_R,
-    KeyCode_Hyper_L,
-    KeyCode_Hyper_R,
-    KeyCode_space,
-    KeyCode_exclam,
-    KeyCode_quotedbl,
-    KeyCode_numbersign,
-    KeyCode_dollar,
-    KeyCode_percent,
-    KeyCode_ampersand,
-    KeyCode_apostrophe,
-    KeyCode_quoteright,
-    KeyCode_parenleft,
-    KeyCode_parenright,
-    KeyCode_asterisk,
-    KeyCode_plus,
-    KeyCode_comma,
-    KeyCode_minus,
-    KeyCode_period,
-    KeyCode_slash,
-    KeyCode_0,
-    KeyCode_1,
-    KeyCode_2,
-    KeyCode_3,
-    KeyCode_4,
-    KeyCode_5,
-    KeyCode_6,
-    KeyCode_7,
-    KeyCode_8,
-    KeyCode_9,
-    KeyCode_colon,
-    KeyCode_semicolon,
-    KeyCode_less,
-    KeyCode_equal,
-    KeyCode_greater,
-    KeyCode_question,
-    KeyCode_at,
-    KeyCode_A,
-    KeyCode_B,
-    KeyCode_C,
-    KeyCode_D,
-    KeyCode_E,
-    KeyCode_F,
-    KeyCode_G,
-    KeyCode_H,
-    KeyCode_I,
-    KeyCode_J,
-    KeyCode_K,
-    KeyCode_L,
-    KeyCode_M,
-    KeyCode_N,
-    KeyCode_O,
-    KeyCode_P,
-    KeyCode_Q,
-    KeyCode_R,
-    KeyCode_S,
-    KeyCode_T,
-    KeyCode_U,
-    KeyCode_V,
-    KeyCode_W,
-    KeyCode_X,
-    KeyCode_Y,
-    KeyCode_Z,
-    KeyCode_bracketleft,
-    KeyCode_backslash,
-    KeyCode_bracketright,
-    KeyCode_asciicircum,
-    KeyCode_underscore,
-    KeyCode_grave,
-    KeyCode_quoteleft,
-    KeyCode_a,
-    KeyCode_b,
-    KeyCode_c,
-    KeyCode_d,
-    KeyCode_e,
-    KeyCode_f,
-    KeyCode_g,
-    KeyCode_h,
-    KeyCode_i,
-    KeyCode_j,
-    KeyCode_k,
-    KeyCode_l,
-    KeyCode_m,
-    KeyCode_n,
-    KeyCode_o,
-    KeyCode_p,
-    KeyCode_q,
-    KeyCode_r,
-    KeyCode_s,
-    KeyCode_t,
-    KeyCode_u,
-    KeyCode_v,
-    KeyCode_w,
-    KeyCode_x,
-    KeyCode_y,
-    KeyCode_z,
-    KeyCode_braceleft,
-    KeyCode_bar,
-    KeyCode_braceright,
-    KeyCode_asciitilde,
-    KeyCode_nobreakspace,
-    KeyCode_exclamdown,
-    KeyCode_cent,
-    KeyCode_sterling,
-    KeyCode_currency,
-    KeyCode_yen,
-    KeyCode_brokenbar,
-    KeyCode_section,
-    KeyCode_diaeresis,
-    KeyCode_copyright,
-    KeyCode_ordfeminine,
-    KeyCode_guillemotleft,
-    KeyCode_notsign,
-    KeyCode_hyphen,
-    KeyCode_registered,
-    KeyCode_macron,
-    KeyCode_degree,
-    KeyCode_plusminus,
-    KeyCode_twosuperior,
-    KeyCode_threesuperior,
-    KeyCode_acute,
-    KeyCode_mu,
-    KeyCode_paragraph,
-    KeyCode_periodcentered,
-    KeyCode_cedilla,
-    KeyCode_onesuperior,
-    KeyCode_masculine,
-    KeyCode_guillemotright,
-    KeyCode_onequarter,
-    KeyCode_onehalf,
-    KeyCode_threequarters,
-    KeyCode_questiondown,
-    KeyCode_Agrave,
-    KeyCode_Aacute,
-    KeyCode_Acircumflex,
-    KeyCode_Atilde,
-    KeyCode_Adiaeresis,
-    KeyCode_Aring,
-    KeyCode_AE,
-    KeyCode_Ccedilla,
-    KeyCode_Egrave,
-    KeyCode_Eacute,
-    KeyCode_Ecircumflex,
-    KeyCode_Ediaeresis,
-    KeyCode_Igrave,
-    KeyCode_Iacute,
-    KeyCode_Icircumflex,
-    KeyCode_Idiaeresis,
-    KeyCode_ETH,
-    KeyCode_Eth,
-    KeyCode_Ntilde,
-    KeyCode_Ograve,
-    KeyCode_Oacute,
-    KeyCode_Ocircumflex,
-    KeyCode_Otilde,
-    KeyCode_Odiaeresis,
-    KeyCode_multiply,
-    KeyCode_Ooblique,
-    KeyCode_Ugrave,
-    KeyCode_Uacute,
-    KeyCode_Ucircumflex,
-    KeyCode_Udiaeresis,
-    KeyCode_Yacute,
-    KeyCode_THORN,
-    KeyCode_Thorn,
-    KeyCode_ssharp,
-    KeyCode_agrave,
-    KeyCode_aacute,
-    KeyCode_acircumflex,
-    KeyCode_atilde,
-    KeyCode_adiaeresis,
-    KeyCode_aring,
-    KeyCode_ae,
-    KeyCode_ccedilla,
-    KeyCode_egrave,
-    KeyCode_eacute,
-    KeyCode_ecircumflex,
-    KeyCode_ediaeresis,
-    KeyCode_igrave,
-    KeyCode_iacute,
-    KeyCode_icircumflex,
-    KeyCode_idiaeresis,
-    KeyCode_eth,
-    KeyCode_ntilde,
-    KeyCode_ograve,
-    KeyCode_oacute,
-    KeyCode_ocircumflex,
-    KeyCode_otilde,
-    KeyCode_odiaeresis,
-    KeyCode_division,
-    KeyCode_oslash,
-    KeyCode_ugrave,
-    KeyCode_uacute,
-    KeyCode_ucircumflex,
-    KeyCode_udiaeresis,
-    KeyCode_yacute,
-    KeyCode_thorn,
-    KeyCode_ydiaeresis,
+/// The names and values of the keysyms that can be used in key bindings,
+/// as defined by X11's keysymdef.h and XF86keysym.h. This way key names
+/// can be resolved without a connection to the window system.
+const KEYSYMS: &[(&str, u64)] = &[
+    ("BackSpace", 0xff08),
+    ("Tab", 0xff09),
+    ("Linefeed", 0xff0a),
+    ("Clear", 0xff0b),
+    ("Return", 0xff0d),
+    ("Pause", 0xff13),
+    ("Scroll_Lock", 0xff14),
+    ("Sys_Req", 0xff15),
+    ("Escape", 0xff1b),
+    ("Delete", 0xffff),
+    ("Multi_key", 0xff20),
+    ("Codeinput", 0xff37),
+    ("SingleCandidate", 0xff3c),
+    ("MultipleCandidate", 0xff3d),
+    ("PreviousCandidate", 0xff3e),
+    ("Home", 0xff50),
+    ("Left", 0xff51),
+    ("Up", 0xff52),
+    ("Right", 0xff53),
+    ("Down", 0xff54),
+    ("Prior", 0xff55),
+    ("Page_Up", 0xff55),
+    ("Next", 0xff56),
+    ("Page_Down", 0xff56),
+    ("End", 0xff57),
+    ("Begin", 0xff58),
+    ("Select", 0xff60),
+    ("Print", 0xff61),
+    ("Execute", 0xff62),
+    ("Insert", 0xff63),
+    ("Undo", 0xff65),
+    ("Redo", 0xff66),
+    ("Menu", 0xff67),
+    ("Find", 0xff68),
+    ("Cancel", 0xff69),
+    ("Help", 0xff6a),
+    ("Break", 0xff6b),
+    ("Mode_switch", 0xff7e),
+    ("script_switch", 0xff7e),
+    ("Num_Lock", 0xff7f),
+    ("KP_Space", 0xff80),
+    ("KP_Tab", 0xff89),
+    ("KP_Enter", 0xff8d),
+    ("KP_F1", 0xff91),
+    ("KP_F2", 0xff92),
+    ("KP_F3", 0xff93),
+    ("KP_F4", 0xff94),
+    ("KP_Home", 0xff95),
+    ("KP_Left", 0xff96),
+    ("KP_Up", 0xff97),
+    ("KP_Right", 0xff98),
+    ("KP_Down", 0xff99),
+    ("KP_Prior", 0xff9a),
+    ("KP_Page_Up", 0xff9a),
+    ("KP_Next", 0xff9b),
+    ("KP_Page_Down", 0xff9b),
+    ("KP_End", 0xff9c),
+    ("KP_Begin", 0xff9d),
+    ("KP_Insert", 0xff9e),
+    ("KP_Delete", 0xff9f),
+    ("KP_Equal", 0xffbd),
+    ("KP_Multiply", 0xffaa),
+    ("KP_Add", 0xffab),
+    ("KP_Separator", 0xffac),
+    ("KP_Subtract", 0xffad),
+    ("KP_Decimal", 0xffae),
+    ("KP_Divide", 0xffaf),
+    ("KP_0", 0xffb0),
+    ("KP_1", 0xffb1),
+    ("KP_2", 0xffb2),
+    ("KP_3", 0xffb3),
+    ("KP_4", 0xffb4),
+    ("KP_5", 0xffb5),
+    ("KP_6", 0xffb6),
+    ("KP_7", 0xffb7),
+    ("KP_8", 0xffb8),
+    ("KP_9", 0xffb9),
+    ("F1", 0xffbe),
+    ("F2", 0xffbf),
+    ("F3", 0xffc0),
+    ("F4", 0xffc1),
+    ("F5", 0xffc2),
+    ("F6", 0xffc3),
+    ("F7", 0xffc4),
+    ("F8", 0xffc5),
+    ("F9", 0xffc6),
+    ("F10", 0xffc7),
+    ("F11", 0xffc8),
+    ("L1", 0xffc8),
+    ("F12", 0xffc9),
+    ("L2", 0xffc9),
+    ("F13", 0xffca),
+    ("L3", 0xffca),
+    ("F14", 0xffcb),
+    ("L4", 0xffcb),
+    ("F15", 0xffcc),
+    ("L5", 0xffcc),
+    ("F16", 0xffcd),
+    ("L6", 0xffcd),
+    ("F17", 0xffce),
+    ("L7", 0xffce),
+    ("F18", 0xffcf),
+    ("L8", 0xffcf),
+    ("F19", 0xffd0),
+    ("L9", 0xffd0),
+    ("F20", 0xffd1),
+    ("L10", 0xffd1),
+    ("F21", 0xffd2),
+    ("R1", 0xffd2),
+    ("F22", 0xffd3),
+    ("R2", 0xffd3),
+    ("F23", 0xffd4),
+    ("R3", 0xffd4),
+    ("F24", 0xffd5),
+    ("R4", 0xffd5),
+    ("F25", 0xffd6),
+    ("R5", 0xffd6),
+    ("F26", 0xffd7),
+    ("R6", 0xffd7),
+    ("F27", 0xffd8),
+    ("R7", 0xffd8),
+    ("F28", 0xffd9),
+    ("R8", 0xffd9),
+    ("F29", 0xffda),
+    ("R9", 0xffda),
+    ("F30", 0xffdb),
+    ("R10", 0xffdb),
+    ("F31", 0xffdc),
+    ("R11", 0xffdc),
+    ("F32", 0xffdd),
+    ("R12", 0xffdd),
+    ("F33", 0xffde),
+    ("R13", 0xffde),
+    ("F34", 0xffdf),
+    ("R14", 0xffdf),
+    ("F35", 0xffe0),
+    ("R15", 0xffe0),
+    ("Shift_L", 0xffe1),
+    ("Shift_R", 0xffe2),
+    ("Control_L", 0xffe3),
+    ("Control_R", 0xffe4),
+    ("Caps_Lock", 0xffe5),
+    ("Shift_Lock", 0xffe6),
+    ("Meta_L", 0xffe7),
+    ("Meta_R", 0xffe8),
+    ("Alt_L", 0xffe9),
+    ("Alt_R", 0xffea),
+    ("Super_L", 0xffeb),
+    ("Super_R", 0xffec),
+    ("Hyper_L", 0xffed),
+    ("Hyper_R", 0xffee),
+    ("space", 0x20),
+    ("exclam", 0x21),
+    ("quotedbl", 0x22),
+    ("numbersign", 0x23),
+    ("dollar", 0x24),
+    ("percent", 0x25),
+    ("ampersand", 0x26),
+    ("apostrophe", 0x27),
+    ("quoteright", 0x27),
+    ("parenleft", 0x28),
+    ("parenright", 0x29),
+    ("asterisk", 0x2a),
+    ("plus", 0x2b),
+    ("comma", 0x2c),
+    ("minus", 0x2d),
+    ("period", 0x2e),
+    ("slash", 0x2f),
+    ("0", 0x30),
+    ("1", 0x31),
+    ("2", 0x32),
+    ("3", 0x33),
+    ("4", 0x34),
+    ("5", 0x35),
+    ("6", 0x36),
+    ("7", 0x37),
+    ("8", 0x38),
+    ("9", 0x39),
+    ("colon", 0x3a),
+    ("semicolon", 0x3b),
+    ("less", 0x3c),
+    ("equal", 0x3d),
+    ("greater", 0x3e),
+    ("question", 0x3f),
+    ("at", 0x40),
+    ("A", 0x41),
+    ("B", 0x42),
+    ("C", 0x43),
+    ("D", 0x44),
+    ("E", 0x45),
+    ("F", 0x46),
+    ("G", 0x47),
+    ("H", 0x48),
+    ("I", 0x49),
+    ("J", 0x4a),
+    ("K", 0x4b),
+    ("L", 0x4c),
+    ("M", 0x4d),
+    ("N", 0x4e),
+    ("O", 0x4f),
+    ("P", 0x50),
+    ("Q", 0x51),
+    ("R", 0x52),
+    ("S", 0x53),
+    ("T", 0x54),
+    ("U", 0x55),
+    ("V", 0x56),
+    ("W", 0x57),
+    ("X", 0x58),
+    ("Y", 0x59),
+    ("Z", 0x5a),
+    ("bracketleft", 0x5b),
+    ("backslash", 0x5c),
+    ("bracketright", 0x5d),
+    ("asciicircum", 0x5e),
+    ("underscore", 0x5f),
+    ("grave", 0x60),
+    ("quoteleft", 0x60),
+    ("a", 0x61),
+    ("b", 0x62),
+    ("c", 0x63),
+    ("d", 0x64),
+    ("e", 0x65),
+    ("f", 0x66),
+    ("g", 0x67),
+    ("h", 0x68),
+    ("i", 0x69),
+    ("j", 0x6a),
+    ("k", 0x6b),
+    ("l", 0x6c),
+    ("m", 0x6d),
+    ("n", 0x6e),
+    ("o", 0x6f),
+    ("p", 0x70),
+    ("q", 0x71),
+    ("r", 0x72),
+    ("s", 0x73),
+    ("t", 0x74),
+    ("u", 0x75),
+    ("v", 0x76),
+    ("w", 0x77),
+    ("x", 0x78),
+    ("y", 0x79),
+    ("z", 0x7a),
+    ("braceleft", 0x7b),
+    ("bar", 0x7c),
+    ("braceright", 0x7d),
+    ("asciitilde", 0x7e),
+    ("nobreakspace", 0xa0),
+    ("exclamdown", 0xa1),
+    ("cent", 0xa2),
+    ("sterling", 0xa3),
+    ("currency", 0xa4),
+    ("yen", 0xa5),
+    ("brokenbar", 0xa6),
+    ("section", 0xa7),
+    ("diaeresis", 0xa8),
+    ("copyright", 0xa9),
+    ("ordfeminine", 0xaa),
+    ("guillemotleft", 0xab),
+    ("notsign", 0xac),
+    ("hyphen", 0xad),
+    ("registered", 0xae),
+    ("macron", 0xaf),
+    ("degree", 0xb0),
+    ("plusminus", 0xb1),
+    ("twosuperior", 0xb2),
+    ("threesuperior", 0xb3),
+    ("acute", 0xb4),
+    ("mu", 0xb5),
+    ("paragraph", 0xb6),
+    ("periodcentered", 0xb7),
+    ("cedilla", 0xb8),
+    ("onesuperior", 0xb9),
+    ("masculine", 0xba),
+    ("guillemotright", 0xbb),
+    ("onequarter", 0xbc),
+    ("onehalf", 0xbd),
+    ("threequarters", 0xbe),
+    ("questiondown", 0xbf),
+    ("Agrave", 0xc0),
+    ("Aacute", 0xc1),
+    ("Acircumflex", 0xc2),
+    ("Atilde", 0xc3),
+    ("Adiaeresis", 0xc4),
+    ("Aring", 0xc5),
+    ("AE", 0xc6),
+    ("Ccedilla", 0xc7),
+    ("Egrave", 0xc8),
+    ("Eacute", 0xc9),
+    ("Ecircumflex", 0xca),
+    ("Ediaeresis", 0xcb),
+    ("Igrave", 0xcc),
+    ("Iacute", 0xcd),
+    ("Icircumflex", 0xce),
+    ("Idiaeresis", 0xcf),
+    ("ETH", 0xd0),
+    ("Eth", 0xd0),
+    ("Ntilde", 0xd1),
+    ("Ograve", 0xd2),
+    ("Oacute", 0xd3),
+    ("Ocircumflex", 0xd4),
+    ("Otilde", 0xd5),
+    ("Odiaeresis", 0xd6),
+    ("multiply", 0xd7),
+    ("Ooblique", 0xd8),
+    ("Ugrave", 0xd9),
+    ("Uacute", 0xda),
+    ("Ucircumflex", 0xdb),
+    ("Udiaeresis", 0xdc),
+    ("Yacute", 0xdd),
+    ("THORN", 0xde),
+    ("Thorn", 0xde),
+    ("ssharp", 0xdf),
+    ("agrave", 0xe0),
+    ("aacute", 0xe1),
+    ("acircumflex", 0xe2),
+    ("atilde", 0xe3),
+    ("adiaeresis", 0xe4),
+    ("aring", 0xe5),
+    ("ae", 0xe6),
+    ("ccedilla", 0xe7),
+    ("egrave", 0xe8),
+    ("eacute", 0xe9),
+    ("ecircumflex", 0xea),
+    ("ediaeresis", 0xeb),
+    ("igrave", 0xec),
+    ("iacute", 0xed),
+    ("icircumflex", 0xee),
+    ("idiaeresis", 0xef),
+    ("eth", 0xf0),
+    ("ntilde", 0xf1),
+    ("ograve", 0xf2),
+    ("oacute", 0xf3),
+    ("ocircumflex", 0xf4),
+    ("otilde", 0xf5),
+    ("odiaeresis", 0xf6),
+    ("division", 0xf7),
+    ("oslash", 0xf8),
+    ("ugrave", 0xf9),
+    ("uacute", 0xfa),
+    ("ucircumflex", 0xfb),
+    ("udiaeresis", 0xfc),
+    ("yacute", 0xfd),
+    ("thorn", 0xfe),
+    ("ydiaeresis", 0xff),
+    ("XF86ModeLock", 0x1008ff01),
+    ("XF86MonBrightnessUp", 0x1008ff02),
+    ("XF86MonBrightnessDown", 0x1008ff03),
+    ("XF86KbdLightOnOff", 0x1008ff04),
+    ("XF86KbdBrightnessUp", 0x1008ff05),
+    ("XF86KbdBrightnessDown", 0x1008ff06),
+    ("XF86Standby", 0x1008ff10),
+    ("XF86AudioLowerVolume", 0x1008ff11),
+    ("XF86AudioMute", 0x1008ff12),
+    ("XF86AudioRaiseVolume", 0x1008ff13),
+    ("XF86AudioPlay", 0x1008ff14),
+    ("XF86AudioStop", 0x1008ff15),
+    ("XF86AudioPrev", 0x1008ff16),
+    ("XF86AudioNext", 0x1008ff17),
+    ("XF86HomePage", 0x1008ff18),
+    ("XF86Mail", 0x1008ff19),
+    ("XF86Start", 0x1008ff1a),
+    ("XF86Search", 0x1008ff1b),
+    ("XF86AudioRecord", 0x1008ff1c),
+    ("XF86Calculator", 0x1008ff1d),
+    ("XF86Memo", 0x1008ff1e),
+    ("XF86ToDoList", 0x1008ff1f),
+    ("XF86Calendar", 0x1008ff20),
+    ("XF86PowerDown", 0x1008ff21),
+    ("XF86ContrastAdjust", 0x1008ff22),
+    ("XF86RockerUp", 0x1008ff23),
+    ("XF86RockerDown", 0x1008ff24),
+    ("XF86RockerEnter", 0x1008ff25),
+    ("XF86Back", 0x1008ff26),
+    ("XF86Forward", 0x1008ff27),
+    ("XF86Stop", 0x1008ff28),
+    ("XF86Refresh", 0x1008ff29),
+    ("XF86PowerOff", 0x1008ff2a),
+    ("XF86WakeUp", 0x1008ff2b),
+    ("XF86Eject", 0x1008ff2c),
+    ("XF86ScreenSaver", 0x1008ff2d),
+    ("XF86WWW", 0x1008ff2e),
+    ("XF86Sleep", 0x1008ff2f),
+    ("XF86Favorites", 0x1008ff30),
+    ("XF86AudioPause", 0x1008ff31),
+    ("XF86AudioMedia", 0x1008ff32),
+    ("XF86MyComputer", 0x1008ff33),
+    ("XF86VendorHome", 0x1008ff34),
+    ("XF86LightBulb", 0x1008ff35),
+    ("XF86Shop", 0x1008ff36),
+    ("XF86History", 0x1008ff37),
+    ("XF86OpenURL", 0x1008ff38),
+    ("XF86AddFavorite", 0x1008ff39),
+    ("XF86HotLinks", 0x1008ff3a),
+    ("XF86BrightnessAdjust", 0x1008ff3b),
+    ("XF86Finance", 0x1008ff3c),
+    ("XF86Community", 0x1008ff3d),
+    ("XF86AudioRewind", 0x1008ff3e),
+    ("XF86BackForward", 0x1008ff3f),
+    ("XF86Launch0", 0x1008ff40),
+    ("XF86Launch1", 0x1008ff41),
+    ("XF86Launch2", 0x1008ff42),
+    ("XF86Launch3", 0x1008ff43),
+    ("XF86Launch4", 0x1008ff44),
+    ("XF86Launch5", 0x1008ff45),
+    ("XF86Launch6", 0x1008ff46),
+    ("XF86Launch7", 0x1008ff47),
+    ("XF86Launch8", 0x1008ff48),
+    ("XF86Launch9", 0x1008ff49),
+    ("XF86LaunchA", 0x1008ff4a),
+    ("XF86LaunchB", 0x1008ff4b),
+    ("XF86LaunchC", 0x1008ff4c),
+    ("XF86LaunchD", 0x1008ff4d),
+    ("XF86LaunchE", 0x1008ff4e),
+    ("XF86LaunchF", 0x1008ff4f),
+    ("XF86ApplicationLeft", 0x1008ff50),
+    ("XF86ApplicationRight", 0x1008ff51),
+    ("XF86Book", 0x1008ff52),
+    ("XF86CD", 0x1008ff53),
+    ("XF86Calculater", 0x1008ff54),
+    ("XF86Clear", 0x1008ff55),
+    ("XF86Close", 0x1008ff56),
+    ("XF86Copy", 0x1008ff57),
+    ("XF86Cut", 0x1008ff58),
+    ("XF86Display", 0x1008ff59),
+    ("XF86DOS", 0x1008ff5a),
+    ("XF86Documents", 0x1008ff5b),
+    ("XF86Excel", 0x1008ff5c),
+    ("XF86Explorer", 0x1008ff5d),
+    ("XF86Game", 0x1008ff5e),
+    ("XF86Go", 0x1008ff5f),
+    ("XF86iTouch", 0x1008ff60),
+    ("XF86LogOff", 0x1008ff61),
+    ("XF86Market", 0x1008ff62),
+    ("XF86Meeting", 0x1008ff63),
+    ("XF86MenuKB", 0x1008ff65),
+    ("XF86MenuPB", 0x1008ff66),
+    ("XF86MySites", 0x1008ff67),
+    ("XF86New", 0x1008ff68),
+    ("XF86News", 0x1008ff69),
+    ("XF86OfficeHome", 0x1008ff6a),
+    ("XF86Open", 0x1008ff6b),
+    ("XF86Option", 0x1008ff6c),
+    ("XF86Paste", 0x1008ff6d),
+    ("XF86Phone", 0x1008ff6e),
+    ("XF86Q", 0x1008ff70),
+    ("XF86Reply", 0x1008ff72),
+    ("XF86Reload", 0x1008ff73),
+    ("XF86RotateWindows", 0x1008ff74),
+    ("XF86RotationPB", 0x1008ff75),
+    ("XF86RotationKB", 0x1008ff76),
+    ("XF86Save", 0x1008ff77),
+    ("XF86ScrollUp", 0x1008ff78),
+    ("XF86ScrollDown", 0x1008ff79),
+    ("XF86ScrollClick", 0x1008ff7a),
+    ("XF86Send", 0x1008ff7b),
+    ("XF86Spell", 0x1008ff7c),
+    ("XF86SplitScreen", 0x1008ff7d),
+    ("XF86Support", 0x1008ff7e),
+    ("XF86TaskPane", 0x1008ff7f),
+    ("XF86Terminal", 0x1008ff80),
+    ("XF86Tools", 0x1008ff81),
+    ("XF86Travel", 0x1008ff82),
+    ("XF86UserPB", 0x1008ff84),
+    ("XF86User1KB", 0x1008ff85),
+    ("XF86User2KB", 0x1008ff86),
+    ("XF86Video", 0x1008ff87),
+    ("XF86WheelButton", 0x1008ff88),
+    ("XF86Word", 0x1008ff89),
+    ("XF86Xfer", 0x1008ff8a),
+    ("XF86ZoomIn", 0x1008ff8b),
+    ("XF86ZoomOut", 0x1008ff8c),
+    ("XF86Away", 0x1008ff8d),
+    ("XF86Messenger", 0x1008ff8e),
+    ("XF86WebCam", 0x1008ff8f),
+    ("XF86MailForward", 0x1008ff90),
+    ("XF86Pictures", 0x1008ff91),
+    ("XF86Music", 0x1008ff92),
+    ("XF86Battery", 0x1008ff93),
+    ("XF86Bluetooth", 0x1008ff94),
+    ("XF86WLAN", 0x1008ff95),
+    ("XF86UWB", 0x1008ff96),
+    ("XF86AudioForward", 0x1008ff97),
+    ("XF86AudioRepeat", 0x1008ff98),
+    ("XF86AudioRandomPlay", 0x1008ff99),
+    ("XF86Subtitle", 0x1008ff9a),
+    ("XF86AudioCycleTrack", 0x1008ff9b),
+    ("XF86CycleAngle", 0x1008ff9c),
+    ("XF86FrameBack", 0x1008ff9d),
+    ("XF86FrameForward", 0x1008ff9e),
+    ("XF86Time", 0x1008ff9f),
+    ("XF86Select", 0x1008ffa0),
+    ("XF86View", 0x1008ffa1),
+    ("XF86TopMenu", 0x1008ffa2),
+    ("XF86Red", 0x1008ffa3),
+    ("XF86Green", 0x1008ffa4),
+    ("XF86Yellow", 0x1008ffa5),
+    ("XF86Blue", 0x1008ffa6),
+    ("XF86Suspend", 0x1008ffa7),
+    ("XF86Hibernate", 0x1008ffa8),
+    ("XF86TouchpadToggle", 0x1008ffa9),
+    ("XF86TouchpadOn", 0x1008ffb0),
+    ("XF86TouchpadOff", 0x1008ffb1),
+    ("XF86AudioMicMute", 0x1008ffb2),
+    ("XF86Switch_VT_1", 0x1008fe01),
+    ("XF86Switch_VT_2", 0x1008fe02),
+    ("XF86Switch_VT_3", 0x1008fe03),
+    ("XF86Switch_VT_4", 0x1008fe04),
+    ("XF86Switch_VT_5", 0x1008fe05),
+    ("XF86Switch_VT_6", 0x1008fe06),
+    ("XF86Switch_VT_7", 0x1008fe07),
+    ("XF86Switch_VT_8", 0x1008fe08),
+    ("XF86Switch_VT_9", 0x1008fe09),
+    ("XF86Switch_VT_10", 0x1008fe0a),
+    ("XF86Switch_VT_11", 0x1008fe0b),
+    ("XF86Switch_VT_12", 0x1008fe0c),
+    ("XF86Ungrab", 0x1008fe20),
+    ("XF86ClearGrab", 0x1008fe21),
+    ("XF86Next_VMode", 0x1008fe22),
+    ("XF86Prev_VMode", 0x1008fe23),
+    ("XF86LogWindowTree", 0x1008fe24),
+    ("XF86LogGrabInfo", 0x1008fe25),
+];
+
+/// Whether the character is a printable Latin-1 character, whose
+/// keysym is the same as its code point
+fn is_latin1(c: char) -> bool {
+    matches!(c as u32, 0x21..=0x7e | 0xa1..=0xff)
+}
+
+/// The keysym with the given name, e.g. `Return`, `a` or `XF86AudioRaiseVolume`.
+/// Single printable Latin-1 characters like `-` stand for themselves.
+pub fn keysym_from_name(name: &str) -> Option<u64> {
+    KEYSYMS
+        .iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, keysym)| keysym)
+        .or_else(|| {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if is_latin1(c) => Some(c as u64),
+                _ => None,
+            }
+        })
+}
+
+/// The name of the keysym. Keysyms with several names, like `Prior`
+/// and `Page_Up`, get the first one.
+pub fn keysym_name(keysym: u64) -> Option<&'static str> {
+    KEYSYMS
+        .iter()
+        .find(|&&(_, k)| k == keysym)
+        .map(|&(name, _)| name)
+}
+
+/// Whether the keysym belongs to a modifier key like `Shift_L`,
+/// `Super_R` or `ISO_Level3_Shift`, just like Xlib's `IsModifierKey`
+pub fn is_modifier_key(keysym: u64) -> bool {
+    matches!(keysym, 0xffe1..=0xffee | 0xfe01..=0xfe13 | 0xff7e | 0xff7f)
 }
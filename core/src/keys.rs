@@ -0,0 +1,143 @@
+use crate::actions::KeyAction;
+use crate::keycodes::{keysym_from_name, keysym_name};
+use crate::window_system::{KeyCommand, KeyModifiers, WindowSystem};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// How long the rest of a key chord is waited for
+pub const CHORD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Key bindings that are only active after a prefix key, like a resize
+/// mode or the second key of a chord. While a submap is active, the
+/// keyboard is grabbed and only its keys are handled. An unbound
+/// Escape leaves all submaps.
+pub struct Submap {
+    pub keys: BTreeMap<KeyCommand, KeyAction>,
+    /// Stay in the submap after a key, until it's left explicitly.
+    /// Otherwise it's left before the action of the first key runs.
+    pub sticky: bool,
+    /// Leave the submap if no key was pressed for this long
+    pub timeout: Option<Duration>,
+}
+
+impl Submap {
+    /// A mode that stays active until it's left
+    pub fn mode() -> Submap {
+        Submap {
+            keys: BTreeMap::new(),
+            sticky: true,
+            timeout: None,
+        }
+    }
+
+    /// The rest of a key chord, left after one key or the timeout
+    pub fn chord() -> Submap {
+        Submap {
+            keys: BTreeMap::new(),
+            sticky: false,
+            timeout: Some(CHORD_TIMEOUT),
+        }
+    }
+}
+
+/// Parse modifier names like `mod4`, `super` or `alt`
+pub fn parse_modifier(name: &str) -> Result<KeyModifiers> {
+    match name.to_lowercase().as_str() {
+        "shift" => Ok(KeyModifiers::SHIFTMASK),
+        "lock" | "capslock" => Ok(KeyModifiers::LOCKMASK),
+        "control" | "ctrl" => Ok(KeyModifiers::CONTROLMASK),
+        "mod1" | "alt" => Ok(KeyModifiers::MOD1MASK),
+        "mod2" => Ok(KeyModifiers::MOD2MASK),
+        "mod3" => Ok(KeyModifiers::MOD3MASK),
+        "mod4" | "super" => Ok(KeyModifiers::MOD4MASK),
+        "mod5" => Ok(KeyModifiers::MOD5MASK),
+        _ => Err(anyhow!("unknown modifier {}", name)),
+    }
+}
+
+/// The modifier for a single prefix of a key specification
+fn prefix_modifier(prefix: &str, mod_mask: KeyModifiers) -> Option<KeyModifiers> {
+    match prefix {
+        "M" => Some(mod_mask),
+        "S" => Some(KeyModifiers::SHIFTMASK),
+        "C" => Some(KeyModifiers::CONTROLMASK),
+        "M1" => Some(KeyModifiers::MOD1MASK),
+        "M2" => Some(KeyModifiers::MOD2MASK),
+        "M3" => Some(KeyModifiers::MOD3MASK),
+        "M4" => Some(KeyModifiers::MOD4MASK),
+        "M5" => Some(KeyModifiers::MOD5MASK),
+        _ => None,
+    }
+}
+
+/// Parse emacs style key specifications like `M-S-Return`, `C-M4-1`
+/// or `<XF86AudioRaiseVolume>`. The prefixes are `M` for the configured
+/// mod key, `S` for shift, `C` for control and `M1` to `M5` for the
+/// modifiers themselves. Key names may be wrapped in angle brackets.
+pub fn parse_key(
+    spec: &str,
+    mod_mask: KeyModifiers,
+    window_system: &dyn WindowSystem,
+) -> Result<KeyCommand> {
+    let mut mask = KeyModifiers::NONEMASK;
+    let mut rest = spec;
+
+    // Everything up to the last dash is a modifier, unless the key itself is a dash
+    while let Some(i) = rest
+        .find('-')
+        .filter(|&i| i + 1 < rest.len() && !rest.starts_with('<'))
+    {
+        let prefix = &rest[..i];
+        let modifier = prefix_modifier(prefix, mod_mask)
+            .ok_or_else(|| anyhow!("unknown modifier {} in {}", prefix, spec))?;
+        mask |= modifier;
+        rest = &rest[i + 1..];
+    }
+
+    let key = rest
+        .strip_prefix('<')
+        .and_then(|k| k.strip_suffix('>'))
+        .unwrap_or(rest);
+    if key.is_empty() {
+        return Err(anyhow!("missing key in {}", spec));
+    }
+
+    // Keys missing from the table might still be known to the window system
+    match keysym_from_name(key).unwrap_or_else(|| window_system.get_keycode_from_string(key)) {
+        0 => Err(anyhow!("unknown key {} in {}", key, spec)),
+        keysym => Ok(KeyCommand::new(keysym, mask)),
+    }
+}
+
+/// Turn the key back into a specification like `M-S-Return`,
+/// e.g. to print the bindings
+pub fn format_key(key: KeyCommand, mod_mask: KeyModifiers) -> String {
+    let mut mask = key.mask;
+    let mut spec = String::new();
+    if !mod_mask.is_empty() && mask.contains(mod_mask) {
+        spec.push_str("M-");
+        mask.remove(mod_mask);
+    }
+    for &(prefix, modifier) in [
+        ("C-", KeyModifiers::CONTROLMASK),
+        ("S-", KeyModifiers::SHIFTMASK),
+        ("M1-", KeyModifiers::MOD1MASK),
+        ("M2-", KeyModifiers::MOD2MASK),
+        ("M3-", KeyModifiers::MOD3MASK),
+        ("M4-", KeyModifiers::MOD4MASK),
+        ("M5-", KeyModifiers::MOD5MASK),
+    ]
+    .iter()
+    {
+        if mask.contains(modifier) {
+            spec.push_str(prefix);
+        }
+    }
+
+    match keysym_name(key.key) {
+        Some(name) if name.starts_with("XF86") => format!("{}<{}>", spec, name),
+        Some(name) => format!("{}{}", spec, name),
+        None => format!("{}{:#x}", spec, key.key),
+    }
+}
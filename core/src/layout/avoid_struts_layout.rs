@@ -1,10 +1,11 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Direction, Layout, LayoutMessage};
+use crate::layout::{Direction, Layout, LayoutMessage, LayoutState};
 use crate::window_system::{Rectangle, Window, WindowSystem};
+use anyhow::Result;
 use num::Bounded;
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Clone, Copy)]
 pub struct Strut(Direction, u64, u64, u64);
@@ -76,6 +77,57 @@ pub fn get_strut(window_system: &dyn WindowSystem, window: Window) -> Vec<Strut>
     }
 }
 
+/// The struts of all docks, so layouts don't have to ask the window
+/// system about every window whenever they are applied. The window
+/// manager updates it when docks are mapped, moved, unmapped or
+/// change their struts, and passes it to the layouts.
+#[derive(Clone, Default)]
+pub struct StrutCache {
+    docks: BTreeMap<Window, (Rectangle, Vec<Strut>)>,
+}
+
+impl StrutCache {
+    pub fn new() -> StrutCache {
+        StrutCache {
+            docks: BTreeMap::new(),
+        }
+    }
+
+    /// Read the dock's geometry and struts again
+    pub fn update(&mut self, window_system: &dyn WindowSystem, window: Window) {
+        let entry = (
+            window_system.get_geometry(window),
+            get_strut(window_system, window),
+        );
+        self.docks.insert(window, entry);
+    }
+
+    /// Read the struts of all docks from the window system, for layouts
+    /// that are applied without the window manager's cache
+    pub fn read(window_system: &dyn WindowSystem) -> StrutCache {
+        let mut cache = StrutCache::new();
+        for window in window_system.get_windows() {
+            if window_system.is_dock(window) {
+                cache.update(window_system, window);
+            }
+        }
+        cache
+    }
+
+    pub fn remove(&mut self, window: Window) {
+        self.docks.remove(&window);
+    }
+
+    /// The struts of all docks on the given screen
+    pub fn struts(&self, screen: Rectangle) -> Vec<Strut> {
+        self.docks
+            .values()
+            .filter(|(geometry, _)| geometry.overlaps(&screen))
+            .flat_map(|(_, struts)| struts.iter().copied())
+            .collect()
+    }
+}
+
 /// A layout that avoids dock like windows (e.g. dzen, xmobar, ...)
 /// to not overlap them.
 pub struct AvoidStrutsLayout {
@@ -101,15 +153,23 @@ impl Layout for AvoidStrutsLayout {
         screen: Rectangle,
         config: &GeneralConfig,
         stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        let struts = StrutCache::read(window_system);
+        self.apply_layout_with_struts(window_system, screen, &struts, config, stack)
+    }
+
+    fn apply_layout_with_struts(
+        &mut self,
+        window_system: &dyn WindowSystem,
+        screen: Rectangle,
+        struts: &StrutCache,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
     ) -> Vec<(Window, Rectangle)> {
         let new_screen = stack.clone().map_or(screen, |_| {
-            window_system
-                .get_windows()
+            struts
+                .struts(screen)
                 .into_iter()
-                .filter(|&w| {
-                    window_system.is_dock(w) && window_system.get_geometry(w).overlaps(&screen)
-                })
-                .flat_map(|x| get_strut(window_system, x).into_iter())
                 .filter(|&Strut(s, _, _, _)| self.directions.contains(&s))
                 .fold(screen, |Rectangle(x, y, w, h), Strut(d, sw, _, _)| {
                     let s = sw as u32;
@@ -123,7 +183,7 @@ impl Layout for AvoidStrutsLayout {
         });
 
         self.layout
-            .apply_layout(window_system, new_screen, config, stack)
+            .apply_layout_with_struts(window_system, new_screen, struts, config, stack)
     }
 
     fn apply_message(
@@ -147,4 +207,18 @@ impl Layout for AvoidStrutsLayout {
             layout: self.layout.copy(),
         })
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new(
+            "AvoidStruts",
+            json!({ "directions": self.directions }),
+            vec![self.layout.state()],
+        )
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("AvoidStruts", 1)?;
+        self.directions = state.param("directions")?;
+        self.layout.set_state(&state.children[0])
+    }
 }
@@ -1,14 +1,16 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Direction, Layout, LayoutMessage};
+use crate::layout::{Direction, Layout, LayoutMessage, LayoutState};
 use crate::window_system::{Rectangle, Window, WindowSystem};
 
+use anyhow::Result;
 use log::debug;
+use serde::{Deserialize, Serialize};
 
 use std::borrow::ToOwned;
 use std::ops::Deref;
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Axis {
     Horizontal,
     Vertical,
@@ -32,7 +34,7 @@ impl Axis {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 enum Tree<T> {
     Leaf,
     Node(T, Box<Tree<T>>, Box<Tree<T>>),
@@ -47,7 +49,7 @@ impl<T> Tree<T> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Split {
     axis: Axis,
     ratio: f32,
@@ -425,7 +427,7 @@ impl Zipper {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BinarySpacePartition {
     tree: Option<Tree<Split>>,
 }
@@ -648,4 +650,14 @@ impl Layout for BinarySpacePartition {
     fn copy(&self) -> Box<dyn Layout> {
         Box::new(self.clone())
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new("BSP", json!(self), Vec::new())
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("BSP", 0)?;
+        *self = state.params()?;
+        Ok(())
+    }
 }
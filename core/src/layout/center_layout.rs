@@ -1,7 +1,9 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Layout, LayoutMessage};
+use crate::layout::{Layout, LayoutMessage, LayoutState, StrutCache};
 use crate::window_system::{Rectangle, Window, WindowSystem};
+use anyhow::Result;
+use serde_json::Value;
 
 use std::borrow::ToOwned;
 
@@ -24,12 +26,29 @@ impl Layout for CenterLayout {
         screen: Rectangle,
         config: &GeneralConfig,
         stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        let struts = StrutCache::read(window_system);
+        self.apply_layout_with_struts(window_system, screen, &struts, config, stack)
+    }
+
+    fn apply_layout_with_struts(
+        &mut self,
+        window_system: &dyn WindowSystem,
+        screen: Rectangle,
+        struts: &StrutCache,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
     ) -> Vec<(Window, Rectangle)> {
         match stack {
             &Some(ref s) => {
                 if s.len() == 1 {
-                    self.layout
-                        .apply_layout(window_system, screen, config, &Some(s.clone()))
+                    self.layout.apply_layout_with_struts(
+                        window_system,
+                        screen,
+                        struts,
+                        config,
+                        &Some(s.clone()),
+                    )
                 } else {
                     let new_stack = if s.up.len() > 0 {
                         Stack::<Window>::new(
@@ -54,7 +73,13 @@ impl Layout for CenterLayout {
                     .into_iter())
                     .chain(
                         self.layout
-                            .apply_layout(window_system, screen, config, &Some(new_stack))
+                            .apply_layout_with_struts(
+                                window_system,
+                                screen,
+                                struts,
+                                config,
+                                &Some(new_stack),
+                            )
                             .into_iter(),
                     )
                     .collect()
@@ -82,4 +107,13 @@ impl Layout for CenterLayout {
     fn copy(&self) -> Box<dyn Layout> {
         CenterLayout::new(self.layout.copy())
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new("Center", Value::Null, vec![self.layout.state()])
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("Center", 1)?;
+        self.layout.set_state(&state.children[0])
+    }
 }
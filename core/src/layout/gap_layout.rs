@@ -1,7 +1,8 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Layout, LayoutMessage};
+use crate::layout::{Layout, LayoutMessage, LayoutState, StrutCache};
 use crate::window_system::{Rectangle, Window, WindowSystem};
+use anyhow::Result;
 
 pub struct GapLayout {
     gap: u32,
@@ -25,9 +26,21 @@ impl Layout for GapLayout {
         config: &GeneralConfig,
         stack: &Option<Stack<Window>>,
     ) -> Vec<(Window, Rectangle)> {
-        let layout = self
-            .layout
-            .apply_layout(window_system, screen, config, stack);
+        let struts = StrutCache::read(window_system);
+        self.apply_layout_with_struts(window_system, screen, &struts, config, stack)
+    }
+
+    fn apply_layout_with_struts(
+        &mut self,
+        window_system: &dyn WindowSystem,
+        screen: Rectangle,
+        struts: &StrutCache,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        let layout =
+            self.layout
+                .apply_layout_with_struts(window_system, screen, struts, config, stack);
 
         let g = self.gap;
         layout
@@ -75,4 +88,14 @@ impl Layout for GapLayout {
             layout: self.layout.copy(),
         })
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new("Gap", json!({ "gap": self.gap }), vec![self.layout.state()])
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("Gap", 1)?;
+        self.gap = state.param("gap")?;
+        self.layout.set_state(&state.children[0])
+    }
 }
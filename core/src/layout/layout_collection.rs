@@ -1,7 +1,8 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Layout, LayoutMessage};
+use crate::layout::{Layout, LayoutMessage, LayoutState, StrutCache};
 use crate::window_system::{Rectangle, Window, WindowSystem};
+use anyhow::{anyhow, Result};
 
 pub struct LayoutCollection {
     pub layouts: Vec<Box<dyn Layout>>,
@@ -25,7 +26,25 @@ impl Layout for LayoutCollection {
         config: &GeneralConfig,
         stack: &Option<Stack<Window>>,
     ) -> Vec<(Window, Rectangle)> {
-        self.layouts[self.current].apply_layout(window_system, screen, config, stack)
+        let struts = StrutCache::read(window_system);
+        self.apply_layout_with_struts(window_system, screen, &struts, config, stack)
+    }
+
+    fn apply_layout_with_struts(
+        &mut self,
+        window_system: &dyn WindowSystem,
+        screen: Rectangle,
+        struts: &StrutCache,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        self.layouts[self.current].apply_layout_with_struts(
+            window_system,
+            screen,
+            struts,
+            config,
+            stack,
+        )
     }
 
     fn apply_message(
@@ -60,4 +79,25 @@ impl Layout for LayoutCollection {
             layouts: self.layouts.iter().map(|x| x.copy()).collect(),
         })
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new(
+            "LayoutCollection",
+            json!({ "current": self.current }),
+            self.layouts.iter().map(|x| x.state()).collect(),
+        )
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("LayoutCollection", self.layouts.len())?;
+        let current: usize = state.param("current")?;
+        if current >= self.layouts.len() {
+            return Err(anyhow!("layout index {} out of range", current));
+        }
+        self.current = current;
+        for (layout, state) in self.layouts.iter_mut().zip(state.children.iter()) {
+            layout.set_state(state)?;
+        }
+        Ok(())
+    }
 }
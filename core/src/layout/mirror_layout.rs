@@ -1,7 +1,9 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{mirror_rect, Layout, LayoutMessage};
+use crate::layout::{mirror_rect, Layout, LayoutMessage, LayoutState, StrutCache};
 use crate::window_system::{Rectangle, Window, WindowSystem};
+use anyhow::Result;
+use serde_json::Value;
 
 /// A simple layout container that just
 /// rotates the layout of its contained layout
@@ -24,10 +26,22 @@ impl Layout for MirrorLayout {
         screen: Rectangle,
         config: &GeneralConfig,
         stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        let struts = StrutCache::read(w);
+        self.apply_layout_with_struts(w, screen, &struts, config, stack)
+    }
+
+    fn apply_layout_with_struts(
+        &mut self,
+        w: &dyn WindowSystem,
+        screen: Rectangle,
+        struts: &StrutCache,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
     ) -> Vec<(Window, Rectangle)> {
         // Rotate the screen, apply the layout, ...
         self.layout
-            .apply_layout(w, mirror_rect(&screen), config, stack)
+            .apply_layout_with_struts(w, mirror_rect(&screen), struts, config, stack)
             .iter()
             // and then rotate all resulting windows by 90° clockwise
             .map(|&(w, r)| (w, mirror_rect(&r)))
@@ -54,4 +68,13 @@ impl Layout for MirrorLayout {
             layout: self.layout.copy(),
         })
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new("Mirror", Value::Null, vec![self.layout.state()])
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("Mirror", 1)?;
+        self.layout.set_state(&state.children[0])
+    }
 }
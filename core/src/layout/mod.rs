@@ -27,9 +27,137 @@ use crate::window_system::Rectangle;
 use crate::window_system::Window;
 use crate::window_system::WindowSystem;
 
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::borrow::ToOwned;
 
-#[derive(Clone, Copy)]
+/// The exported state of a layout. Wrapping layouts
+/// include the state of their contained layouts as children,
+/// so the whole layout tree can be inspected and restored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayoutState {
+    /// The kind of layout this state belongs to
+    pub kind: String,
+    /// The layout's own parameters
+    #[serde(default)]
+    pub params: Value,
+    /// The states of all contained layouts
+    #[serde(default)]
+    pub children: Vec<LayoutState>,
+}
+
+impl LayoutState {
+    pub fn new(kind: &str, params: Value, children: Vec<LayoutState>) -> LayoutState {
+        LayoutState {
+            kind: kind.to_owned(),
+            params,
+            children,
+        }
+    }
+
+    /// Make sure the state belongs to a layout of the given
+    /// kind with the given number of contained layouts
+    pub fn expect(&self, kind: &str, children: usize) -> Result<()> {
+        if self.kind != kind {
+            return Err(anyhow!(
+                "expected state of layout {}, found {}",
+                kind,
+                self.kind
+            ));
+        }
+        if self.children.len() != children {
+            return Err(anyhow!(
+                "layout {} contains {} layout(s), but state has {}",
+                kind,
+                children,
+                self.children.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read all parameters into the given type
+    pub fn params<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_value(self.params.clone())
+            .with_context(|| format!("invalid state for layout {}", self.kind))
+    }
+
+    /// Read a single named parameter
+    pub fn param<T: DeserializeOwned>(&self, name: &str) -> Result<T> {
+        serde_json::from_value(self.params[name].clone())
+            .with_context(|| format!("invalid parameter {} for layout {}", name, self.kind))
+    }
+
+    /// Build a new layout tree from the state, e.g. one written by hand
+    /// in the config file. Parameters that are left out keep their defaults.
+    pub fn build(&self) -> Result<Box<dyn Layout>> {
+        let mut children = self
+            .children
+            .iter()
+            .map(|x| x.build())
+            .collect::<Result<Vec<_>>>()?;
+        let count = children.len();
+        let kind = &self.kind;
+        let child = |children: &mut Vec<Box<dyn Layout>>| match (children.pop(), count) {
+            (Some(child), 1) => Ok(child),
+            _ => Err(anyhow!(
+                "layout {} contains 1 layout, but {} given",
+                kind,
+                count
+            )),
+        };
+        let leaf = |layout: Box<dyn Layout>| match count {
+            0 => Ok(layout),
+            _ => Err(anyhow!(
+                "layout {} can't contain layouts, but {} given",
+                kind,
+                count
+            )),
+        };
+
+        let mut layout = match self.kind.as_str() {
+            "Tall" => leaf(TallLayout::new())?,
+            "ResizeTall" => leaf(ResizableTallLayout::new())?,
+            "BSP" => leaf(BinarySpacePartition::new())?,
+            "Full" => leaf(Box::new(FullLayout))?,
+            "Gap" => GapLayout::new(0, child(&mut children)?),
+            "WithBorders" => WithBordersLayout::new(0, child(&mut children)?),
+            "NoBorders" => NoBordersLayout::new(child(&mut children)?),
+            "AvoidStruts" => AvoidStrutsLayout::new(Vec::new(), child(&mut children)?),
+            "Mirror" => MirrorLayout::new(child(&mut children)?),
+            "Center" => CenterLayout::new(child(&mut children)?),
+            "LayoutCollection" if count > 0 => LayoutCollection::new(children),
+            "LayoutCollection" => return Err(anyhow!("layout LayoutCollection is empty")),
+            kind => return Err(anyhow!("unknown layout {}", kind)),
+        };
+
+        // Start with the defaults and replace the given parameters
+        let mut state = layout.state();
+        match (&mut state.params, &self.params) {
+            (_, Value::Null) => (),
+            (Value::Object(defaults), Value::Object(params)) => {
+                for (name, value) in params {
+                    if !defaults.contains_key(name) {
+                        return Err(anyhow!(
+                            "unknown parameter {} for layout {}",
+                            name,
+                            self.kind
+                        ));
+                    }
+                    defaults.insert(name.clone(), value.clone());
+                }
+            }
+            _ => return Err(anyhow!("invalid parameters for layout {}", self.kind)),
+        }
+        layout.set_state(&state)?;
+
+        Ok(layout)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum LayoutMessage {
     Increase,
     Decrease,
@@ -97,6 +225,19 @@ pub trait Layout {
         config: &GeneralConfig,
         stack: &Option<Stack<Window>>,
     ) -> Vec<(Window, Rectangle)>;
+    /// Like `apply_layout`, but with the struts of the docks the window
+    /// manager keeps track of. Layouts that avoid struts, or contain other
+    /// layouts, override it and pass the struts on.
+    fn apply_layout_with_struts(
+        &mut self,
+        window_system: &dyn WindowSystem,
+        screen: Rectangle,
+        _: &StrutCache,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        self.apply_layout(window_system, screen, config, stack)
+    }
     fn apply_message(
         &mut self,
         _: LayoutMessage,
@@ -111,9 +252,20 @@ pub trait Layout {
         panic!("")
     }
     fn unhook(&self, _: &dyn WindowSystem, _: &Option<Stack<Window>>, _: &GeneralConfig) {}
+    /// Export the state of the layout (ratios, trees, ...) together
+    /// with the states of all contained layouts.
+    fn state(&self) -> LayoutState {
+        LayoutState::new(&self.description(), Value::Null, Vec::new())
+    }
+    /// Restore a state previously produced by `state`. Fails if the state
+    /// belongs to a different layout. The layout may be partially modified
+    /// on failure, so apply it to a copy.
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect(&self.description(), 0)
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct TallLayout {
     pub num_master: u32,
     pub increment_ratio: f32,
@@ -188,10 +340,20 @@ impl Layout for TallLayout {
     fn copy(&self) -> Box<dyn Layout> {
         Box::new(self.clone())
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new("Tall", json!(self), Vec::new())
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("Tall", 0)?;
+        *self = state.params()?;
+        Ok(())
+    }
 }
 
 #[repr(usize)]
-#[derive(Clone, Copy, Ord, Eq, PartialOrd, PartialEq)]
+#[derive(Clone, Copy, Debug, Ord, Eq, PartialOrd, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -1,13 +1,15 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Layout, LayoutMessage};
+use crate::layout::{Layout, LayoutMessage, LayoutState};
 use crate::window_manager::ScreenDetail;
 use crate::window_system::{Rectangle, Window, WindowSystem};
 
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::borrow::ToOwned;
 use std::iter;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ResizableTallLayout {
     pub num_master: u32,
     pub increment_ratio: f32,
@@ -215,4 +217,14 @@ impl Layout for ResizableTallLayout {
     fn copy(&self) -> Box<dyn Layout> {
         Box::new(self.clone())
     }
+
+    fn state(&self) -> LayoutState {
+        LayoutState::new("ResizeTall", json!(self), Vec::new())
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("ResizeTall", 0)?;
+        *self = state.params()?;
+        Ok(())
+    }
 }
@@ -1,7 +1,8 @@
 use crate::config::GeneralConfig;
 use crate::core::stack::Stack;
-use crate::layout::{Layout, LayoutMessage};
+use crate::layout::{Layout, LayoutMessage, LayoutState, StrutCache};
 use crate::window_system::{Rectangle, Window, WindowSystem};
+use anyhow::Result;
 
 pub struct WithBordersLayout {
     border: u32,
@@ -24,6 +25,18 @@ impl Layout for WithBordersLayout {
         screen: Rectangle,
         config: &GeneralConfig,
         stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        let struts = StrutCache::read(window_system);
+        self.apply_layout_with_struts(window_system, screen, &struts, config, stack)
+    }
+
+    fn apply_layout_with_struts(
+        &mut self,
+        window_system: &dyn WindowSystem,
+        screen: Rectangle,
+        struts: &StrutCache,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
     ) -> Vec<(Window, Rectangle)> {
         if let &Some(ref s) = stack {
             for window in s.integrate().into_iter() {
@@ -31,7 +44,7 @@ impl Layout for WithBordersLayout {
             }
         }
         self.layout
-            .apply_layout(window_system, screen, config, stack)
+            .apply_layout_with_struts(window_system, screen, struts, config, stack)
     }
 
     fn apply_message(
@@ -56,6 +69,20 @@ impl Layout for WithBordersLayout {
         })
     }
 
+    fn state(&self) -> LayoutState {
+        LayoutState::new(
+            "WithBorders",
+            json!({ "border": self.border }),
+            vec![self.layout.state()],
+        )
+    }
+
+    fn set_state(&mut self, state: &LayoutState) -> Result<()> {
+        state.expect("WithBorders", 1)?;
+        self.border = state.param("border")?;
+        self.layout.set_state(&state.children[0])
+    }
+
     fn unhook(
         &self,
         window_system: &dyn WindowSystem,
@@ -5,10 +5,21 @@ extern crate bitflags;
 #[macro_use]
 extern crate serde_json;
 
+pub mod actions;
 pub mod config;
+pub mod config_file;
 pub mod core;
+pub mod event_loop;
 pub mod handlers;
+pub mod headless_window_system;
+pub mod ipc;
+pub mod keycodes;
+pub mod keys;
 pub mod layout;
+pub mod logging;
+pub mod rules;
+pub mod session;
+pub mod trace;
 pub mod util;
 pub mod window_manager;
 pub mod window_system;
@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Context, Result};
+use log::LevelFilter;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The size at which the logfile is rotated
+pub const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// How many rotated logfiles are kept, as `logfile.1` to `logfile.N`
+pub const KEPT_LOGS: usize = 3;
+
+/// Parse log levels like `warn` or `debug`
+pub fn parse_level(name: &str) -> Result<LevelFilter> {
+    name.parse()
+        .map_err(|_| anyhow!("invalid log level {}", name))
+}
+
+struct Logfile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+}
+
+impl Logfile {
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Move `logfile` to `logfile.1`, `logfile.1` to `logfile.2` and so on.
+    /// If the logfile can't be moved, it's opened again to go on with it.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        for i in (1..KEPT_LOGS).rev() {
+            let _ = rename(numbered(&self.path, i), numbered(&self.path, i + 1));
+        }
+        let renamed = rename(&self.path, numbered(&self.path, 1));
+        self.open()?;
+        renamed
+    }
+}
+
+fn numbered(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", i));
+    PathBuf::from(name)
+}
+
+/// A logfile that's rotated once it grows beyond `MAX_LOG_SIZE`.
+/// Clones write to the same file, so the logger can get one
+/// while the path can still be changed once the config is loaded.
+#[derive(Clone)]
+pub struct RotatingLogfile {
+    inner: Arc<Mutex<Logfile>>,
+}
+
+impl RotatingLogfile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<RotatingLogfile> {
+        let mut logfile = Logfile {
+            path: path.as_ref().to_owned(),
+            file: None,
+            size: 0,
+        };
+        logfile
+            .open()
+            .with_context(|| format!("unable to open logfile {}", logfile.path.display()))?;
+        Ok(RotatingLogfile {
+            inner: Arc::new(Mutex::new(logfile)),
+        })
+    }
+
+    /// Continue in the given file, unless it's the current one already
+    pub fn set_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut logfile = self.inner.lock().unwrap();
+        if logfile.path == path.as_ref() {
+            return Ok(());
+        }
+        logfile.path = path.as_ref().to_owned();
+        logfile
+            .open()
+            .with_context(|| format!("unable to open logfile {}", logfile.path.display()))
+    }
+}
+
+impl Write for RotatingLogfile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut logfile = self.inner.lock().unwrap();
+        if logfile.size > 0 && logfile.size + buf.len() as u64 > MAX_LOG_SIZE {
+            // A full logfile is still better than none
+            let rotated = logfile.rotate();
+            if logfile.file.is_none() {
+                rotated?;
+            }
+        }
+        let written = match logfile.file {
+            Some(ref mut file) => file.write(buf)?,
+            None => return Err(io::Error::other("logfile is closed")),
+        };
+        logfile.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.lock().unwrap().file {
+            Some(ref mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::core::rational_rect::RationalRect;
+use crate::core::workspaces::Workspaces;
+use crate::handlers::ManageHook;
+use crate::window_system::{Rectangle, Window, WindowSystem, WindowType};
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// How a window property is compared to the expected value
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Exact(String),
+    Contains(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn regex(pattern: &str) -> Result<Pattern> {
+        Regex::new(pattern)
+            .map(Pattern::Regex)
+            .with_context(|| format!("invalid regex {}", pattern))
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match *self {
+            Pattern::Exact(ref expected) => expected == value,
+            Pattern::Contains(ref part) => value.contains(part.as_str()),
+            Pattern::Regex(ref regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// A property a window needs to have for a rule to apply
+#[derive(Clone, Debug)]
+pub enum Condition {
+    Class(Pattern),
+    Role(Pattern),
+    Title(Pattern),
+    /// Docks, notifications and desktops aren't managed,
+    /// so rules never see them
+    Type(WindowType),
+}
+
+impl Condition {
+    pub fn matches(&self, window_system: &dyn WindowSystem, window: Window) -> bool {
+        match *self {
+            Condition::Class(ref p) => p.matches(&window_system.get_class_name(window)),
+            Condition::Role(ref p) => p.matches(&window_system.get_role_name(window)),
+            Condition::Title(ref p) => p.matches(&window_system.get_window_name(window)),
+            Condition::Type(window_type) => window_system.get_window_type(window) == window_type,
+        }
+    }
+}
+
+/// What happens to a matching window
+#[derive(Clone, Debug)]
+pub enum RuleAction {
+    /// Float the window at its requested position
+    Float,
+    /// Float the window at the given part of the screen
+    FloatAt(RationalRect),
+    /// Float the window in the middle of the screen, keeping its size
+    Center,
+    /// Move the window to the workspace with the given index
+    Shift(u32),
+    /// Move the window to the workspace with the given tag
+    ShiftToTag(String),
+    /// Cover the whole screen, without a border
+    Fullscreen,
+    /// Keep the focus on the previously focused window
+    SkipFocus,
+    BorderWidth(u32),
+    /// Don't manage the window at all
+    Ignore,
+}
+
+impl RuleAction {
+    pub fn apply(
+        &self,
+        workspaces: Workspaces,
+        window_system: &dyn WindowSystem,
+        window: Window,
+    ) -> Workspaces {
+        match *self {
+            RuleAction::Float => {
+                let rect = float_location(&workspaces, window_system, window);
+                workspaces.float(window, rect)
+            }
+            RuleAction::FloatAt(rect) => workspaces.float(window, rect),
+            RuleAction::Center => {
+                let RationalRect(_, _, w, h) = workspaces
+                    .floating
+                    .get(&window)
+                    .copied()
+                    .unwrap_or_else(|| float_location(&workspaces, window_system, window));
+                workspaces.float(window, RationalRect(0.5 - w / 2.0, 0.5 - h / 2.0, w, h))
+            }
+            RuleAction::Shift(index) => workspaces.shift_window(index, window),
+            RuleAction::ShiftToTag(ref tag) => {
+                match workspaces.workspaces().iter().find(|w| w.tag == *tag) {
+                    Some(workspace) => workspaces.shift_window(workspace.id, window),
+                    None => workspaces,
+                }
+            }
+            RuleAction::Fullscreen => workspaces
+                .float(window, RationalRect(0.0, 0.0, 1.0, 1.0))
+                .set_border_width(window, 0),
+            RuleAction::SkipFocus => {
+                if workspaces.peek() == Some(window) {
+                    workspaces.focus_down()
+                } else {
+                    workspaces
+                }
+            }
+            RuleAction::BorderWidth(width) => workspaces.set_border_width(window, width),
+            RuleAction::Ignore => workspaces.delete(window).forget(window),
+        }
+    }
+
+    fn is_shift(&self) -> bool {
+        matches!(*self, RuleAction::Shift(_) | RuleAction::ShiftToTag(_))
+    }
+}
+
+/// Applies its actions to windows that meet all of its conditions
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<RuleAction>,
+}
+
+impl Rule {
+    pub fn new(conditions: Vec<Condition>, actions: Vec<RuleAction>) -> Rule {
+        Rule {
+            conditions,
+            actions,
+        }
+    }
+
+    pub fn matches(&self, window_system: &dyn WindowSystem, window: Window) -> bool {
+        self.conditions
+            .iter()
+            .all(|c| c.matches(window_system, window))
+    }
+
+    pub fn apply(
+        &self,
+        workspaces: Workspaces,
+        window_system: &dyn WindowSystem,
+        window: Window,
+    ) -> Workspaces {
+        // Shifting sinks the window, so it has to come first
+        let shifts = self.actions.iter().filter(|a| a.is_shift());
+        let others = self.actions.iter().filter(|a| !a.is_shift());
+
+        shifts.chain(others).fold(workspaces, |w, action| {
+            if w.contains(window) {
+                action.apply(w, window_system, window)
+            } else {
+                w
+            }
+        })
+    }
+}
+
+/// Turn the rules into a manage hook. Every matching rule is applied,
+/// in order, until one of them ignores the window.
+pub fn rules_manage_hook(rules: Vec<Rule>) -> ManageHook {
+    Box::new(move |workspaces, window_system, window| {
+        rules
+            .iter()
+            .filter(|rule| rule.matches(&*window_system, window))
+            .fold(workspaces, |w, rule| rule.apply(w, &*window_system, window))
+    })
+}
+
+fn float_location(
+    workspaces: &Workspaces,
+    window_system: &dyn WindowSystem,
+    window: Window,
+) -> RationalRect {
+    let Rectangle(sx, sy, sw, sh) = workspaces.current.screen_detail;
+    let Rectangle(rx, ry, rw, rh) = window_system.get_geometry(window);
+
+    RationalRect(
+        (rx as f32 - sx as f32) / sw as f32,
+        (ry as f32 - sy as f32) / sh as f32,
+        rw as f32 / sw as f32,
+        rh as f32 / sh as f32,
+    )
+}
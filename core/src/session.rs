@@ -0,0 +1,242 @@
+use crate::config::GeneralConfig;
+use crate::core::rational_rect::RationalRect;
+use crate::core::screen::Screen;
+use crate::core::stack::Stack;
+use crate::core::workspace::Workspace;
+use crate::core::workspaces::Workspaces;
+use crate::layout::LayoutState;
+use crate::window_manager::{ScreenDetail, WindowManager};
+use crate::window_system::{Window, WindowSystem};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, rename, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Version of the session file format. Sessions with a different
+/// version are rejected, so `--resume` falls back to only
+/// re-managing the windows passed on the command line.
+pub const SESSION_VERSION: u32 = 2;
+
+/// The saved state of a single workspace, including its layout
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorkspaceState {
+    pub id: u32,
+    pub tag: String,
+    pub layout: LayoutState,
+    pub stack: Option<Stack<Window>>,
+}
+
+/// The saved state of a screen and the workspace shown on it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScreenState {
+    pub workspace: WorkspaceState,
+    pub screen_id: u32,
+    pub screen_detail: ScreenDetail,
+}
+
+/// Everything needed to bring the window manager back into the
+/// same state after a restart: the workspaces with their stacks and
+/// layouts, which workspace is on which screen and all floating windows.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub current: ScreenState,
+    pub visible: Vec<ScreenState>,
+    pub hidden: Vec<WorkspaceState>,
+    pub floating: BTreeMap<Window, RationalRect>,
+    /// Windows with their own border width
+    #[serde(default)]
+    pub borders: BTreeMap<Window, u32>,
+    /// Transient windows mapped to their parents
+    #[serde(default)]
+    pub transients: BTreeMap<Window, Window>,
+}
+
+/// The default location of the session file, next to the config
+pub fn session_file() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("./"))
+        .join(".wtftw")
+        .join("session.json")
+}
+
+impl WorkspaceState {
+    fn from_workspace(workspace: &Workspace) -> WorkspaceState {
+        WorkspaceState {
+            id: workspace.id,
+            tag: workspace.tag.clone(),
+            layout: workspace.layout_state(),
+            stack: workspace.stack.clone(),
+        }
+    }
+
+    /// Turn the state back into a workspace. The layout is taken from
+    /// the configured workspace with the same id, so only its state
+    /// is restored. If the layout was reconfigured in the meantime,
+    /// it keeps its defaults. Windows that no longer exist are dropped.
+    fn restore(self, configured: &[Workspace], existing: &BTreeSet<Window>) -> Result<Workspace> {
+        let workspace = configured
+            .iter()
+            .find(|w| w.id == self.id && w.tag == self.tag)
+            .ok_or_else(|| {
+                anyhow!(
+                    "workspace {} ({}) is no longer configured",
+                    self.id,
+                    self.tag
+                )
+            })?;
+
+        let workspace = Workspace::new(
+            self.id,
+            self.tag,
+            workspace.layout.copy(),
+            self.stack.and_then(|s| s.filter(|w| existing.contains(w))),
+        );
+
+        Ok(match workspace.with_layout_state(&self.layout) {
+            Ok(w) => w,
+            Err(e) => {
+                warn!("unable to restore layout of {}: {}", workspace.tag, e);
+                workspace
+            }
+        })
+    }
+}
+
+impl ScreenState {
+    fn from_screen(screen: &Screen) -> ScreenState {
+        ScreenState {
+            workspace: WorkspaceState::from_workspace(&screen.workspace),
+            screen_id: screen.screen_id,
+            screen_detail: screen.screen_detail,
+        }
+    }
+
+    fn restore(self, configured: &[Workspace], existing: &BTreeSet<Window>) -> Result<Screen> {
+        Ok(Screen::new(
+            self.workspace.restore(configured, existing)?,
+            self.screen_id,
+            self.screen_detail,
+        ))
+    }
+}
+
+impl Session {
+    /// Capture the complete state of the given workspaces
+    pub fn from_workspaces(workspaces: &Workspaces) -> Session {
+        Session {
+            version: SESSION_VERSION,
+            current: ScreenState::from_screen(&workspaces.current),
+            visible: workspaces
+                .visible
+                .iter()
+                .map(ScreenState::from_screen)
+                .collect(),
+            hidden: workspaces
+                .hidden
+                .iter()
+                .map(WorkspaceState::from_workspace)
+                .collect(),
+            floating: workspaces.floating.clone(),
+            borders: workspaces.borders.clone(),
+            transients: workspaces.transients.clone(),
+        }
+    }
+
+    /// Write the session to the given file. The file is replaced
+    /// atomically, so a crash never leaves half a session behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)
+            .with_context(|| format!("unable to create session {}", tmp.display()))?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        file.sync_all()?;
+        rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Load a session written by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Session> {
+        let content = read_to_string(path.as_ref())
+            .with_context(|| format!("unable to read session {}", path.as_ref().display()))?;
+        let value: Value = serde_json::from_str(&content).context("invalid session")?;
+
+        // Check the version first, so a changed format gives a clear error
+        let version = value["version"].as_u64().unwrap_or(0);
+        if version != SESSION_VERSION as u64 {
+            return Err(anyhow!(
+                "unsupported session version {} (expected {})",
+                version,
+                SESSION_VERSION
+            ));
+        }
+
+        serde_json::from_value(value).context("invalid session")
+    }
+
+    /// Restore the session on top of a freshly configured window manager.
+    /// The workspaces have to match the configured ones, otherwise the
+    /// session is rejected. Windows that vanished during the restart are
+    /// dropped and the screens are adjusted to the current setup.
+    pub fn restore(
+        self,
+        window_manager: &WindowManager,
+        window_system: &dyn WindowSystem,
+        config: &GeneralConfig,
+    ) -> Result<WindowManager> {
+        let configured = window_manager.workspaces.workspaces();
+        if configured.len() != 1 + self.visible.len() + self.hidden.len() {
+            return Err(anyhow!("number of workspaces changed"));
+        }
+
+        let existing: BTreeSet<Window> = window_system.get_windows().into_iter().collect();
+
+        let current = self.current.restore(&configured, &existing)?;
+        let visible = self
+            .visible
+            .into_iter()
+            .map(|s| s.restore(&configured, &existing))
+            .collect::<Result<Vec<_>>>()?;
+        let hidden = self
+            .hidden
+            .into_iter()
+            .map(|w| w.restore(&configured, &existing))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut workspaces = Workspaces {
+            current,
+            visible,
+            hidden,
+            floating: BTreeMap::new(),
+            borders: BTreeMap::new(),
+            transients: BTreeMap::new(),
+        };
+        let managed: BTreeSet<Window> = workspaces.all_windows().into_iter().collect();
+        workspaces.floating = self
+            .floating
+            .into_iter()
+            .filter(|(w, _)| managed.contains(w))
+            .collect();
+        workspaces.borders = self
+            .borders
+            .into_iter()
+            .filter(|(w, _)| managed.contains(w))
+            .collect();
+        workspaces.transients = self
+            .transients
+            .into_iter()
+            .filter(|(w, p)| managed.contains(w) && managed.contains(p))
+            .collect();
+
+        // Adjust to the current screens before anything is shown
+        let restored = window_manager
+            .modify_workspaces(|_| workspaces.clone())
+            .rescreen(window_system);
+
+        Ok(window_manager.windows(window_system, config, &|_| restored.workspaces.clone()))
+    }
+}
@@ -0,0 +1,341 @@
+use crate::config::Config;
+use crate::headless_window_system::HeadlessWindowSystem;
+use crate::ipc::IpcCommand;
+use crate::session::Session;
+use crate::window_manager::WindowManager;
+use crate::window_system::{
+    Rectangle, SizeHint, Window, WindowSystem, WindowSystemEvent, WindowType,
+};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::ops::Deref;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Version of the trace file format. Traces with a
+/// different version are rejected when loading.
+pub const TRACE_VERSION: u32 = 3;
+
+/// The first line of every trace file. It describes the screen
+/// setup, the config and the state the recording started with.
+#[derive(Serialize, Deserialize)]
+struct TraceHeader {
+    version: u32,
+    screens: Vec<Rectangle>,
+    /// See `Config::describe`
+    config: Vec<String>,
+    /// All windows that existed when the recording started
+    windows: Vec<(Window, WindowProperties)>,
+    session: Session,
+    waiting_unmap: BTreeMap<Window, Window>,
+}
+
+/// Everything the window manager asks the window system about a window
+/// when managing it. Recorded along with the events, so a replay sees
+/// the same windows as the recorded session did.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WindowProperties {
+    pub name: String,
+    pub class: String,
+    pub role: String,
+    pub geometry: Rectangle,
+    pub window_type: WindowType,
+    pub transient_for: Option<Window>,
+    pub override_redirect: bool,
+    pub size_hint: SizeHint,
+    pub strut: Option<Vec<u64>>,
+    pub partial_strut: Option<Vec<u64>>,
+}
+
+impl WindowProperties {
+    /// Take a snapshot of the window's current properties
+    pub fn query(window_system: &dyn WindowSystem, window: Window) -> WindowProperties {
+        WindowProperties {
+            name: window_system.get_window_name(window),
+            class: window_system.get_class_name(window),
+            role: window_system.get_role_name(window),
+            geometry: window_system.get_geometry(window),
+            window_type: window_system.get_window_type(window),
+            transient_for: window_system.get_transient_for(window),
+            override_redirect: window_system.overrides_redirect(window),
+            size_hint: window_system.get_size_hints(window),
+            strut: window_system.get_strut(window),
+            partial_strut: window_system.get_partial_strut(window),
+        }
+    }
+
+    /// Give the window these properties in the headless window system
+    pub fn apply(&self, window_system: &HeadlessWindowSystem, window: Window) {
+        window_system.modify_window(window, |w| {
+            w.name = self.name.clone();
+            w.class = self.class.clone();
+            w.role = self.role.clone();
+            w.geometry = self.geometry;
+            w.window_type = self.window_type;
+            w.transient_for = self.transient_for;
+            w.override_redirect = self.override_redirect;
+            w.size_hint = self.size_hint;
+            w.strut = self.strut.clone();
+            w.partial_strut = self.partial_strut.clone();
+        });
+    }
+}
+
+/// A line of a trace file after the header
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceEntry {
+    Event(WindowSystemEvent),
+    /// The properties of the window at the time of the events that follow
+    Window(Window, WindowProperties),
+    /// The timers with these names were due and ran, in this order
+    Timers(Vec<String>),
+    /// The handler of the file descriptor ran. What it read isn't
+    /// recorded, so traces with these can't be replayed.
+    Readable(RawFd),
+    /// An ipc client sent the command, and it was executed
+    Command(IpcCommand),
+}
+
+/// Writes everything the main loop handles into a file, one JSON
+/// object per line: the events coming from the window system, the
+/// timers that ran and the commands of ipc clients. Each line is
+/// flushed immediately, so the trace survives a crash of the window manager.
+pub struct EventRecorder {
+    file: LineWriter<File>,
+}
+
+impl EventRecorder {
+    /// Create a new trace file at the given path, overwriting any existing
+    /// one, and write the header. It holds everything a replay has to start
+    /// from: the screens, the existing windows, the current state of the
+    /// window manager and a description of the config to check against.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        window_manager: &WindowManager,
+        window_system: &dyn WindowSystem,
+        config: &Config,
+    ) -> Result<EventRecorder> {
+        let file = File::create(path.as_ref())
+            .with_context(|| format!("unable to create trace {}", path.as_ref().display()))?;
+        let mut recorder = EventRecorder {
+            file: LineWriter::new(file),
+        };
+        let header = TraceHeader {
+            version: TRACE_VERSION,
+            screens: window_system.get_screen_infos(),
+            config: config.describe(),
+            windows: window_system
+                .get_windows()
+                .into_iter()
+                .map(|w| (w, WindowProperties::query(window_system, w)))
+                .collect(),
+            session: Session::from_workspaces(&window_manager.workspaces),
+            waiting_unmap: window_manager.waiting_unmap.clone(),
+        };
+        writeln!(recorder.file, "{}", serde_json::to_string(&header)?)?;
+        Ok(recorder)
+    }
+
+    /// Record the event. New windows and windows with changed struts
+    /// get a snapshot of their properties first, taken right now.
+    pub fn record(
+        &mut self,
+        event: &WindowSystemEvent,
+        window_system: &dyn WindowSystem,
+    ) -> Result<()> {
+        match *event {
+            WindowSystemEvent::WindowCreated(window) | WindowSystemEvent::StrutsChanged(window) => {
+                let properties = WindowProperties::query(window_system, window);
+                self.write(&TraceEntry::Window(window, properties))?;
+            }
+            _ => (),
+        }
+        self.write(&TraceEntry::Event(*event))
+    }
+
+    /// Record that the timers with the given names ran
+    pub fn record_timers(&mut self, names: &[String]) -> Result<()> {
+        self.write(&TraceEntry::Timers(names.to_vec()))
+    }
+
+    /// Record that the handler of the file descriptor ran
+    pub fn record_readable(&mut self, fd: RawFd) -> Result<()> {
+        self.write(&TraceEntry::Readable(fd))
+    }
+
+    /// Record an executed ipc command
+    pub fn record_command(&mut self, command: &IpcCommand) -> Result<()> {
+        self.write(&TraceEntry::Command(command.clone()))
+    }
+
+    fn write(&mut self, entry: &TraceEntry) -> Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+}
+
+/// A recorded session, ready to be replayed against a headless window system
+pub struct Trace {
+    pub screens: Vec<Rectangle>,
+    /// The config the session was recorded with, see `Config::describe`
+    pub config: Vec<String>,
+    pub windows: Vec<(Window, WindowProperties)>,
+    pub session: Session,
+    pub waiting_unmap: BTreeMap<Window, Window>,
+    pub entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    /// Load a trace file written by an `EventRecorder`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Trace> {
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("unable to open trace {}", path.as_ref().display()))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header: TraceHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).context("invalid trace header")?,
+            None => return Err(anyhow!("trace is empty")),
+        };
+
+        if header.version != TRACE_VERSION {
+            return Err(anyhow!(
+                "unsupported trace version {} (expected {})",
+                header.version,
+                TRACE_VERSION
+            ));
+        }
+
+        let mut entries = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // The header is line 1
+            entries.push(
+                serde_json::from_str(&line)
+                    .with_context(|| format!("invalid entry on line {}", i + 2))?,
+            );
+        }
+
+        Ok(Trace {
+            screens: header.screens,
+            config: header.config,
+            windows: header.windows,
+            session: header.session,
+            waiting_unmap: header.waiting_unmap,
+            entries,
+        })
+    }
+
+    /// Replay the trace against a headless window system, starting from the
+    /// recorded state and running everything through the same methods as
+    /// the main loop. Window properties are set before the events recorded
+    /// after them. The config has to be the one the trace was recorded with,
+    /// see `Config::for_replay`. Fails if it isn't, or once the trace needs
+    /// something a replay can't reproduce, like input of a file descriptor,
+    /// a timer or command that doesn't exist or a reload of the config.
+    pub fn replay(&self, config: &mut Config) -> Result<(WindowManager, Rc<HeadlessWindowSystem>)> {
+        self.check_config(config)?;
+
+        let headless = Rc::new(HeadlessWindowSystem::new(self.screens.clone()));
+        let window_system: Rc<dyn WindowSystem> = headless.clone();
+        for (window, properties) in self.windows.iter() {
+            properties.apply(&headless, *window);
+        }
+        let mut window_manager = self
+            .session
+            .clone()
+            .restore(
+                &WindowManager::new(window_system.deref(), &config.general),
+                window_system.deref(),
+                &config.general,
+            )
+            .context("unable to restore the recorded state")?
+            .adopt_docks(window_system.deref(), &config.general);
+        window_manager.waiting_unmap = self.waiting_unmap.clone();
+
+        for entry in self.entries.iter() {
+            if !window_manager.running {
+                break;
+            }
+            window_manager = match *entry {
+                TraceEntry::Window(window, ref properties) => {
+                    properties.apply(&headless, window);
+                    window_manager
+                }
+                TraceEntry::Event(event) => {
+                    if let WindowSystemEvent::WindowDestroyed(window) = event {
+                        headless.remove_window(window);
+                    }
+                    headless.push_event(event);
+                    // Handle the events the window system queues in response, too
+                    while window_manager.running && window_system.event_pending() {
+                        let event = window_system.get_event();
+                        window_manager = window_manager.handle_event(event, &window_system, config);
+                    }
+                    window_manager
+                }
+                TraceEntry::Timers(ref names) => {
+                    if let Some(name) = names
+                        .iter()
+                        .find(|n| !window_manager.timers.contains_key(*n))
+                    {
+                        return Err(anyhow!("timer {} isn't scheduled in the replay", name));
+                    }
+                    window_manager.run_named_timers(names, &window_system, config)
+                }
+                TraceEntry::Readable(fd) => {
+                    return Err(anyhow!(
+                        "the handler of file descriptor {} read input, which isn't recorded",
+                        fd
+                    ))
+                }
+                TraceEntry::Command(ref command) => {
+                    let (w, _) = command
+                        .clone()
+                        .execute(&window_manager, &window_system, config)
+                        .with_context(|| format!("command {:?} failed in the replay", command))?;
+                    w.publish(&window_system, config);
+                    w
+                }
+            };
+            if window_manager.reload_requested {
+                return Err(anyhow!("the config was reloaded, which can't be replayed"));
+            }
+        }
+
+        Ok((window_manager, headless))
+    }
+
+    /// Make sure the config is the one the trace was recorded with
+    fn check_config(&self, config: &Config) -> Result<()> {
+        let current = config.describe();
+        let mut differences: Vec<String> = self
+            .config
+            .iter()
+            .filter(|line| !current.contains(line))
+            .map(|line| format!("recorded {}", line))
+            .collect();
+        differences.extend(
+            current
+                .iter()
+                .filter(|line| !self.config.contains(line))
+                .map(|line| format!("replaying with {}", line)),
+        );
+
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "the config differs from the recorded one:\n  {}",
+                differences.join("\n  ")
+            ))
+        }
+    }
+}
@@ -8,18 +8,83 @@ use std::process::Command;
 use std::process::Stdio;
 use std::rc::Rc;
 use std::sync::RwLock;
+use std::thread::spawn;
 
 #[macro_export]
 macro_rules! add_key_handler_str(
+    ($config: expr, $w:expr, $key:expr, $modkey:expr, $action:literal) => (
+        $config.add_key_handler($w.get_keycode_from_string($key), $modkey, $action);
+    );
     ($config: expr, $w:expr, $key:expr, $modkey:expr, $inp:expr) => (
-        $config.add_key_handler($w.get_keycode_from_string($key), $modkey, Box::new($inp));
+        $config.add_key_handler($w.get_keycode_from_string($key), $modkey,
+            $crate::actions::KeyAction::Handler(Box::new($inp)));
     )
 );
 
 #[macro_export]
 macro_rules! add_key_handler_code(
+    ($config: expr, $key:expr, $modkey:expr, $action:literal) => (
+        $config.add_key_handler($key, $modkey, $action);
+    );
     ($config: expr, $key:expr, $modkey:expr, $inp:expr) => (
-        $config.add_key_handler($key, $modkey, Box::new($inp));
+        $config.add_key_handler($key, $modkey,
+            $crate::actions::KeyAction::Handler(Box::new($inp)));
+    )
+);
+
+/// Bind a key given as emacs style specification, e.g. `"M-S-Return"`,
+/// to a named action, a handler or entering a submap with `submap "name"`.
+/// Errors end up in the config's error list.
+#[macro_export]
+macro_rules! add_key_binding(
+    ($config: expr, $w:expr, $spec:expr, submap $name:expr) => (
+        if let Err(e) = $config.add_key_binding($w, $spec,
+            $crate::actions::KeyAction::Submap(String::from($name))) {
+            $config.internal.errors.push(format!("{:#}", e));
+        }
+    );
+    ($config: expr, $w:expr, $spec:expr, $action:literal) => (
+        if let Err(e) = $config.add_key_binding($w, $spec, $action) {
+            $config.internal.errors.push(format!("{:#}", e));
+        }
+    );
+    ($config: expr, $w:expr, $spec:expr, $inp:expr) => (
+        if let Err(e) = $config.add_key_binding($w, $spec,
+            $crate::actions::KeyAction::Handler(Box::new($inp))) {
+            $config.internal.errors.push(format!("{:#}", e));
+        }
+    )
+);
+
+/// Like `add_key_binding!`, but for the keys of a submap
+#[macro_export]
+macro_rules! add_submap_binding(
+    ($config: expr, $w:expr, $submap:expr, $spec:expr, $action:literal) => (
+        if let Err(e) = $config.add_submap_binding($w, $submap, $spec, $action) {
+            $config.internal.errors.push(format!("{:#}", e));
+        }
+    );
+    ($config: expr, $w:expr, $submap:expr, $spec:expr, $inp:expr) => (
+        if let Err(e) = $config.add_submap_binding($w, $submap, $spec,
+            $crate::actions::KeyAction::Handler(Box::new($inp))) {
+            $config.internal.errors.push(format!("{:#}", e));
+        }
+    )
+);
+
+/// Like `add_key_binding!`, but the action runs when the key is released
+#[macro_export]
+macro_rules! add_key_release_binding(
+    ($config: expr, $w:expr, $spec:expr, $action:literal) => (
+        if let Err(e) = $config.add_key_release_binding($w, $spec, $action) {
+            $config.internal.errors.push(format!("{:#}", e));
+        }
+    );
+    ($config: expr, $w:expr, $spec:expr, $inp:expr) => (
+        if let Err(e) = $config.add_key_release_binding($w, $spec,
+            $crate::actions::KeyAction::Handler(Box::new($inp))) {
+            $config.internal.errors.push(format!("{:#}", e));
+        }
     )
 );
 
@@ -30,6 +95,13 @@ macro_rules! add_mouse_handler(
     )
 );
 
+#[macro_export]
+macro_rules! add_mouse_release_handler(
+    ($config: expr, $button:expr, $modkey:expr, $inp:expr) => (
+        $config.add_mouse_release_handler($button, $modkey, Box::new($inp));
+    )
+);
+
 #[macro_export]
 macro_rules! send_layout_message(
     ($message: expr) => (
@@ -40,7 +112,12 @@ macro_rules! send_layout_message(
 #[macro_export]
 macro_rules! run(
     ($command: expr, $options: expr) => (
-        |w, _, _| { run($command, String::from($options).split(' ').map(String::from).collect()); w }
+        |w, _, c: &$crate::config::GeneralConfig| {
+            if !c.replaying {
+                run($command, String::from($options).split(' ').map(String::from).collect());
+            }
+            w
+        }
     )
 );
 
@@ -48,6 +125,13 @@ pub fn run<S: AsRef<OsStr>>(program: S, args: Vec<String>) {
     Command::new(program).args(&args).spawn().unwrap();
 }
 
+/// Show the message in an xmessage window, without waiting for it to be closed
+pub fn show_message(message: String) {
+    spawn(move || {
+        let _ = Command::new("xmessage").arg(message).status();
+    });
+}
+
 pub fn spawn_pipe<S: AsRef<OsStr>>(
     config: &mut Config,
     program: S,
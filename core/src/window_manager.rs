@@ -1,27 +1,67 @@
-use crate::config::GeneralConfig;
+use crate::actions::KeyAction;
+use crate::config::{Config, GeneralConfig};
 use crate::core::rational_rect::RationalRect;
 use crate::core::screen::Screen;
 use crate::core::workspace::Workspace;
 use crate::core::workspaces::Workspaces;
-use crate::layout::LayoutMessage;
+use crate::handlers::{collect_zombies, TimerHandler};
+use crate::keycodes::is_modifier_key;
+use crate::layout::{LayoutMessage, StrutCache};
+use crate::window_system::KeyCommand;
+use crate::window_system::KeyModifiers;
+use crate::window_system::Placement;
 use crate::window_system::Rectangle;
 use crate::window_system::Window;
 use crate::window_system::WindowSystem;
+use crate::window_system::WindowSystemEvent;
+use crate::window_system::WindowType;
 
+use anyhow::{Context, Result};
 use std::cmp;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::ops::Deref;
+use std::os::unix::io::RawFd;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 pub type ScreenDetail = Rectangle;
+
+/// The name of the timer that leaves the submaps after their timeout
+const SUBMAP_TIMER: &str = "submap-timeout";
+
+/// Escape leaves submaps, unless they bind it themselves
+fn is_escape(window_system: &dyn WindowSystem, key: KeyCommand) -> bool {
+    let escape = window_system.get_keycode_from_string("Escape");
+    escape != 0 && key == KeyCommand::new(escape, KeyModifiers::NONEMASK)
+}
 pub type MouseDrag = Box<dyn Fn(u32, u32, WindowManager, &dyn WindowSystem) -> WindowManager>;
 
+/// An action that runs once its deadline has passed
+#[derive(Clone)]
+pub struct Timer {
+    pub deadline: Instant,
+    pub action: Rc<TimerHandler>,
+}
+
 #[derive(Clone)]
 pub struct WindowManager {
     pub running: bool,
+    /// Set to have the main loop reload the config, see `reload_config`
+    pub reload_requested: bool,
     pub dragging: Option<Rc<MouseDrag>>,
     pub workspaces: Workspaces,
     pub waiting_unmap: BTreeMap<Window, Window>,
+    /// Actions scheduled to run later, by name
+    pub timers: BTreeMap<String, Timer>,
+    /// The names of the active submaps, the innermost one last
+    pub submaps: Vec<String>,
+    /// Docks like status bars, which are shown as they are instead of being managed
+    pub docks: BTreeSet<Window>,
+    /// The geometry and struts of the docks, for the layouts to avoid
+    pub struts: StrutCache,
+    /// Desktop windows, kept below all other windows
+    pub desktops: BTreeSet<Window>,
 }
 
 impl WindowManager {
@@ -29,6 +69,7 @@ impl WindowManager {
     pub fn new(window_system: &dyn WindowSystem, config: &GeneralConfig) -> WindowManager {
         WindowManager {
             running: true,
+            reload_requested: false,
             dragging: None,
             workspaces: Workspaces::new(
                 config.layout.copy(),
@@ -36,6 +77,11 @@ impl WindowManager {
                 window_system.get_screen_infos(),
             ),
             waiting_unmap: BTreeMap::new(),
+            timers: BTreeMap::new(),
+            submaps: Vec::new(),
+            docks: BTreeSet::new(),
+            struts: StrutCache::new(),
+            desktops: BTreeSet::new(),
         }
     }
 
@@ -44,6 +90,81 @@ impl WindowManager {
         self.workspaces.contains(window)
     }
 
+    /// Checks if the given window is a dock or desktop window we keep track of
+    pub fn is_dock(&self, window: Window) -> bool {
+        self.docks.contains(&window) || self.desktops.contains(&window)
+    }
+
+    /// Keep track of the docks and desktops that were there before
+    /// the window manager started, and make room for them
+    pub fn adopt_docks(
+        &self,
+        window_system: &dyn WindowSystem,
+        config: &GeneralConfig,
+    ) -> WindowManager {
+        window_system
+            .get_windows()
+            .into_iter()
+            .fold(self.clone(), |m, window| {
+                let window_type = window_system.get_window_type(window);
+                m.track_dock(window_system, window, window_type)
+            })
+            .windows(window_system, config, &|x| x.clone())
+    }
+
+    /// Remember docks and desktops, and the struts of docks
+    fn track_dock(
+        &self,
+        window_system: &dyn WindowSystem,
+        window: Window,
+        window_type: WindowType,
+    ) -> WindowManager {
+        let mut m = self.clone();
+        match window_type {
+            WindowType::Dock => {
+                window_system.watch_struts(window);
+                m.struts.update(window_system, window);
+                m.docks.insert(window);
+            }
+            WindowType::Desktop => {
+                m.desktops.insert(window);
+            }
+            _ => (),
+        }
+        m
+    }
+
+    /// Read the geometry and struts of the dock again and lay the
+    /// windows out around them. Other windows are left alone.
+    fn update_struts(
+        &self,
+        window_system: &dyn WindowSystem,
+        window: Window,
+        config: &GeneralConfig,
+    ) -> WindowManager {
+        if !self.docks.contains(&window) {
+            return self.clone();
+        }
+        let mut m = self.clone();
+        m.struts.update(window_system, window);
+        m.windows(window_system, config, &|x| x.clone())
+    }
+
+    /// Stop tracking a dock or desktop window that was closed,
+    /// and give its space back to the layout
+    pub fn forget_dock(
+        &self,
+        window_system: &dyn WindowSystem,
+        window: Window,
+        config: &GeneralConfig,
+    ) -> WindowManager {
+        let mut m = self.clone();
+        m.struts.remove(window);
+        m.docks.remove(&window);
+        m.desktops.remove(&window);
+        m.windows(window_system, config, &|x| x.clone())
+    }
+
     /// Switch to the workspace given by index. If index is out of bounds,
     /// just do nothing and return.
     /// Then, reapply the layout to show the changes.
@@ -117,9 +238,10 @@ impl WindowManager {
             .screens()
             .into_iter()
             .map(|mut s| {
-                s.workspace.layout.apply_layout(
+                s.workspace.layout.apply_layout_with_struts(
                     window_system,
                     s.screen_detail,
+                    &self.struts,
                     config,
                     &self
                         .workspaces
@@ -135,12 +257,18 @@ impl WindowManager {
 
         WindowManager {
             running: self.running,
+            reload_requested: self.reload_requested,
             dragging: self.dragging.clone(),
             workspaces: self
                 .workspaces
                 .from_current(screens[0].clone())
                 .from_visible(screens.into_iter().skip(1).collect()),
             waiting_unmap: self.waiting_unmap.clone(),
+            timers: self.timers.clone(),
+            submaps: self.submaps.clone(),
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
         }
     }
 
@@ -158,6 +286,21 @@ impl WindowManager {
         window: Window,
         config: &GeneralConfig,
     ) -> WindowManager {
+        self.manage_with(window_system, window, config, &|x| x.clone())
+    }
+
+    /// Manage a new window and run the manage hook on it, before it's laid out
+    /// for the first time. The hook still sees the window's requested geometry.
+    pub fn manage_with<F>(
+        &self,
+        window_system: &dyn WindowSystem,
+        window: Window,
+        config: &GeneralConfig,
+        manage_hook: &F,
+    ) -> WindowManager
+    where
+        F: Fn(&Workspaces) -> Workspaces,
+    {
         fn adjust(RationalRect(x, y, w, h): RationalRect) -> RationalRect {
             if x + w > 1.0 || y + h > 1.0 || x < 0.0 || y < 0.0 {
                 RationalRect(0.5 - w / 2.0, 0.5 - h / 2.0, w, h)
@@ -166,26 +309,60 @@ impl WindowManager {
             }
         }
 
+        let window_type = window_system.get_window_type(window);
+        let placement = window_type.placement();
+        if placement == Placement::Ignore || placement == Placement::KeepBelow {
+            let m =
+                self.reveal(window_system, window)
+                    .track_dock(window_system, window, window_type);
+            if !m.is_dock(window) {
+                return m;
+            }
+            // Docks change the space that's left for the layout
+            return m.windows(window_system, config, &|x| x.clone());
+        }
+
         let size_hints = window_system.get_size_hints(window);
 
-        let is_transient = false;
+        let transient_for = window_system.get_transient_for(window);
+        let parent = transient_for
+            .and_then(|parent| self.workspaces.find_tag(parent).map(|tag| (parent, tag)));
         let is_fixed_size =
             size_hints.min_size.is_some() && size_hints.min_size == size_hints.max_size;
 
         debug!("setting focus to newly managed window {}", window);
 
-        let result = if is_transient || is_fixed_size {
+        let result = if let Some((parent, tag)) = parent {
+            // Dialogs go to the workspace of their parent, on top of it
+            let r = adjust(self.transient_location(window_system, window, parent));
+            self.windows(window_system, config, &|x| {
+                manage_hook(
+                    &x.insert_up(window)
+                        .shift_window(tag, window)
+                        .float(window, r)
+                        .set_transient_for(window, parent),
+                )
+            })
+        } else if placement == Placement::Float || is_fixed_size || transient_for.is_some() {
+            // Dialogs of unmanaged windows still float, where they asked to be
             let r = adjust(self.float_location(window_system, window));
             self.windows(window_system, config, &|x| {
-                x.insert_up(window).float(window, r)
+                manage_hook(&x.insert_up(window).float(window, r))
             })
-            .focus(window, window_system, config)
         } else {
-            self.windows(window_system, config, &|x| x.insert_up(window))
-                .focus(window, window_system, config)
+            self.windows(window_system, config, &|x| {
+                manage_hook(&x.insert_up(window))
+            })
         };
 
-        debug!("focus is set to {}", window);
+        // The manage hook ignored the window, so it stays as it is
+        if !result.is_window_managed(window) {
+            return result
+                .remove_from_unmap(window)
+                .reveal(window_system, window);
+        }
+
+        debug!("focus is set to {:?}", result.workspaces.peek());
 
         result
     }
@@ -199,7 +376,18 @@ impl WindowManager {
     ) -> WindowManager {
         if self.workspaces.contains(window) {
             debug!("unmanaging window {}", window);
-            self.windows(window_system, config, &|x| x.delete(window))
+            let tag = self.workspaces.find_tag(window);
+            let parent = self.workspaces.transients.get(&window).copied();
+            self.windows(window_system, config, &|x| {
+                let w = x.delete(window).forget(window);
+                // Closing a dialog gives the focus back to its parent
+                match (tag, parent) {
+                    (Some(tag), Some(parent)) if w.find_tag(parent) == Some(tag) => {
+                        w.on_workspace(tag, Box::new(move |w| w.focus_window(parent)))(w.clone())
+                    }
+                    _ => w,
+                }
+            })
         } else {
             self.clone()
         }
@@ -237,9 +425,15 @@ impl WindowManager {
     {
         WindowManager {
             running: self.running,
+            reload_requested: self.reload_requested,
             dragging: self.dragging.clone(),
             workspaces: f(&self.workspaces),
             waiting_unmap: self.waiting_unmap.clone(),
+            timers: self.timers.clone(),
+            submaps: self.submaps.clone(),
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
         }
     }
 
@@ -274,7 +468,7 @@ impl WindowManager {
         // Initialize all new windows
         for &window in new_windows.iter() {
             window_system.set_initial_properties(window);
-            window_system.set_window_border_width(window, config.border_width);
+            window_system.set_window_border_width(window, ws.border_width(window, config));
         }
 
         let all_screens = ws.screens();
@@ -306,21 +500,22 @@ impl WindowManager {
                     .and_then(|x| x.filter(|win| !vis.contains(win)));
                 let view_rect = w.screen_detail;
 
-                let rs = wsp
-                    .layout
-                    .apply_layout(window_system, view_rect, config, &tiled);
+                let rs = wsp.layout.apply_layout_with_struts(
+                    window_system,
+                    view_rect,
+                    &self.struts,
+                    config,
+                    &tiled,
+                );
 
                 let flt = this
                     .with(Vec::new(), |x| x.integrate())
                     .into_iter()
-                    .filter(|x| self.workspaces.floating.contains_key(x))
+                    .filter(|x| ws.floating.contains_key(x))
                     .map(|x| {
                         (
                             x,
-                            WindowManager::scale_rational_rect(
-                                view_rect,
-                                self.workspaces.floating[&x],
-                            ),
+                            WindowManager::scale_rational_rect(view_rect, ws.floating[&x]),
                         )
                     })
                     .collect::<Vec<_>>();
@@ -332,17 +527,27 @@ impl WindowManager {
             })
             .collect::<Vec<_>>();
 
+        // Desktop windows stay below everything else
+        for &desktop in self.desktops.iter() {
+            window_system.lower_window(desktop);
+        }
+
         let visible = rects.iter().map(|x| x.0).collect::<Vec<_>>();
 
         for &(window, rect) in rects.iter() {
-            WindowManager::tile_window(window_system, config, window, rect);
+            WindowManager::tile_window(
+                window_system,
+                ws.border_width(window, config),
+                window,
+                rect,
+            );
         }
 
         visible.iter().fold((), |_, &x| {
             window_system.set_window_border_color(x, config.border_color)
         });
         visible.iter().fold((), |_, &x| {
-            window_system.set_window_border_width(x, config.border_width)
+            window_system.set_window_border_width(x, ws.border_width(x, config))
         });
 
         for &win in visible.iter() {
@@ -382,6 +587,473 @@ impl WindowManager {
             .fold(modified, |a, x| a.insert_or_update_unmap(x))
     }
 
+    /// Handle a single event coming from the window system and return
+    /// the updated window manager. This is what the main loop runs for
+    /// every event, but any backend, test or embedding program can drive
+    /// the window manager one event at a time with it.
+    pub fn handle_event(
+        &self,
+        event: WindowSystemEvent,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+    ) -> WindowManager {
+        let window_manager = match event {
+            WindowSystemEvent::PropertyMessageEvent(true, window, atom) => {
+                window_system.process_message(self, &config.general, window, atom)
+            }
+            // A dock like a status bar resized itself, so make room for it again
+            WindowSystemEvent::StrutsChanged(window) => {
+                self.update_struts(window_system.deref(), window, &config.general)
+            }
+            // The X11/Wayland configuration changed, so we need to readjust the
+            // screen configurations. Docks that moved might be on another screen now.
+            WindowSystemEvent::ConfigurationNotification(window) => {
+                if window_system.get_root() == window {
+                    debug!("screen configuration changed. rescreen");
+                    self.rescreen(window_system.deref())
+                } else {
+                    self.update_struts(window_system.deref(), window, &config.general)
+                }
+            }
+            // A window asked to be reconfigured (i.e. resized, border change, etc.)
+            WindowSystemEvent::ConfigurationRequest(window, window_changes, mask) => {
+                let floating = self.workspaces.floating.iter().any(|(&x, _)| x == window)
+                    || !self.workspaces.contains(window);
+                window_system.configure_window(window, window_changes, mask, floating);
+                self.windows(window_system.deref(), &config.general, &|x| x.clone())
+            }
+            // A new window was created, so we need to manage
+            // it unless it is already managed by us.
+            WindowSystemEvent::WindowCreated(window) => {
+                if self.is_window_managed(window) || window_system.overrides_redirect(window) {
+                    self.clone()
+                } else {
+                    self.manage_with(window_system.deref(), window, &config.general, &|x| {
+                        (config.internal.manage_hook)(x.clone(), window_system.clone(), window)
+                    })
+                }
+            }
+            WindowSystemEvent::WindowUnmapped(window, synthetic) => {
+                let window_manager = if self.is_dock(window) {
+                    self.forget_dock(window_system.deref(), window, &config.general)
+                } else if synthetic && self.is_window_managed(window) {
+                    if synthetic || !self.is_waiting_unmap(window) {
+                        self.unmanage(window_system.deref(), window, &config.general)
+                    } else {
+                        self.update_unmap(window)
+                    }
+                } else {
+                    self.clone()
+                };
+                collect_zombies();
+                window_manager
+            }
+            WindowSystemEvent::WindowDestroyed(window) => {
+                if self.is_window_managed(window) {
+                    self.unmanage(window_system.deref(), window, &config.general)
+                        .remove_from_unmap(window)
+                } else if self.is_dock(window) {
+                    self.forget_dock(window_system.deref(), window, &config.general)
+                } else {
+                    self.clone()
+                }
+            }
+            // The mouse pointer entered a window's region. If focus following
+            // is enabled, we need to set focus to it.
+            WindowSystemEvent::Enter(window) => {
+                if config.general.focus_follows_mouse && self.is_window_managed(window) {
+                    self.focus(window, window_system.deref(), &config.general)
+                } else {
+                    self.clone()
+                }
+            }
+            // Mouse button has been pressed. We need to check if there is a mouse handler
+            // associated and if necessary, call it. Otherwise it results in a focus action.
+            WindowSystemEvent::ButtonPressed(window, subwindow, button, _, _) => {
+                let is_root = window_system.get_root() == window;
+                let is_sub_root = window_system.get_root() == subwindow || subwindow == 0;
+
+                match config.internal.mouse_handlers.get(&button) {
+                    // If it's a root window, then it's an event we grabbed
+                    Some(action) if is_root && !is_sub_root => action(
+                        self.clone(),
+                        window_system.clone(),
+                        &config.general,
+                        subwindow,
+                    ),
+                    // Otherwise just click to focus
+                    None if !is_root => self.focus(window, window_system.deref(), &config.general),
+                    _ => self.clone(),
+                }
+            }
+            WindowSystemEvent::ButtonReleased(window, subwindow, button, _, _) => {
+                // If we were dragging, release the pointer and
+                // reset the dragging closure
+                let window_manager = if self.dragging.is_some() {
+                    window_system.ungrab_pointer();
+                    WindowManager {
+                        running: self.running,
+                        reload_requested: self.reload_requested,
+                        dragging: None,
+                        workspaces: self.workspaces.clone(),
+                        waiting_unmap: self.waiting_unmap.clone(),
+                        timers: self.timers.clone(),
+                        submaps: self.submaps.clone(),
+                        docks: self.docks.clone(),
+                        struts: self.struts.clone(),
+                        desktops: self.desktops.clone(),
+                    }
+                } else {
+                    self.clone()
+                };
+
+                // Releases of grabbed buttons are reported for the root window
+                let target = if window_system.get_root() == window {
+                    subwindow
+                } else {
+                    window
+                };
+                match config.internal.mouse_release_handlers.get(&button) {
+                    Some(action) => action(
+                        window_manager,
+                        window_system.clone(),
+                        &config.general,
+                        target,
+                    ),
+                    None => window_manager,
+                }
+            }
+            WindowSystemEvent::KeyPressed(_, key) => self.handle_key(key, window_system, config),
+            WindowSystemEvent::KeyReleased(_, key) => {
+                match config.internal.key_release_handlers.get(&key) {
+                    Some(action) => config.internal.actions.run(
+                        action,
+                        self.clone(),
+                        window_system.clone(),
+                        &config.general,
+                    ),
+                    None => self.clone(),
+                }
+            }
+            WindowSystemEvent::KeyboardMappingChanged => {
+                config.grab_keys(window_system.deref());
+                self.clone()
+            }
+            WindowSystemEvent::MouseMotion(x, y) => match self.dragging {
+                Some(ref drag) => {
+                    let window_manager = drag(x, y, self.clone(), window_system.deref());
+                    window_system.remove_motion_events();
+                    window_manager
+                }
+                None => self.clone(),
+            },
+            _ => self.clone(),
+        };
+
+        window_manager.publish(window_system, config);
+        window_manager
+    }
+
+    /// Run the log hook and update the state exposed by the window
+    /// system (e.g. EWMH properties). Needs to be called after every
+    /// change that didn't come through `handle_event`.
+    pub fn publish(&self, window_system: &Rc<dyn WindowSystem>, config: &mut Config) {
+        if let Some(ref mut loghook) = config.internal.loghook {
+            loghook(self.clone(), window_system.clone());
+        }
+
+        window_system.update_server_state(self);
+    }
+
+    /// Reload the config and swap in its bindings, hooks and settings.
+    /// Workspaces still using the old default layout get the new one, the
+    /// others keep the layout they were changed to. If the new config fails
+    /// to build, the old one stays in place and the error is returned, for
+    /// the caller to show. Tags only change on a restart. A config module is
+    /// compiled on the spot, so this blocks until cargo is done.
+    pub fn reload_config(
+        &self,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+    ) -> Result<WindowManager> {
+        let mut window_manager = self.clone();
+        window_manager.reload_requested = false;
+
+        let old_layout = config.general.layout.state();
+        let old_tags = config.general.tags.clone();
+        config
+            .reload(&window_manager, window_system.deref())
+            .context("unable to reload config, keeping the old one")?;
+        info!("config reloaded");
+        if config.general.tags != old_tags {
+            warn!("changed tags only take effect after a restart");
+        }
+
+        window_system.set_lock_mask(config.general.lock_mask);
+        config.grab_keys(window_system.deref());
+        config.grab_buttons(window_system.deref());
+
+        let layout = &config.general.layout;
+        let window_manager = window_manager
+            .leave_submaps(window_system.deref())
+            .modify_workspaces(|w| {
+                w.map_workspaces(|workspace| {
+                    if workspace.layout_state() == old_layout {
+                        Workspace::new(workspace.id, workspace.tag, layout.copy(), workspace.stack)
+                    } else {
+                        workspace
+                    }
+                })
+            })
+            .windows(window_system.deref(), &config.general, &|x| x.clone());
+        window_manager.publish(window_system, config);
+        Ok(window_manager)
+    }
+
+    /// Look the key up in the innermost active submap, or the
+    /// top level bindings if there is none, and run its action
+    fn handle_key(
+        &self,
+        key: KeyCommand,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &Config,
+    ) -> WindowManager {
+        let submap = self
+            .submaps
+            .last()
+            .and_then(|name| config.internal.submaps.get(name));
+        // While the keyboard is grabbed, the modifiers of the next key
+        // arrive as keys of their own. They neither end chords nor miss.
+        if submap.is_some_and(|s| !s.keys.contains_key(&key)) && is_modifier_key(key.key) {
+            return self.clone();
+        }
+        let (window_manager, action) = match submap {
+            None => (self.clone(), config.internal.key_handlers.get(&key)),
+            Some(submap) if submap.sticky => (self.clone(), submap.keys.get(&key)),
+            // Chords are left before their action runs
+            Some(submap) => {
+                let mut submaps = self.submaps.clone();
+                submaps.pop();
+                (
+                    self.with_submaps(submaps).cancel(SUBMAP_TIMER),
+                    submap.keys.get(&key),
+                )
+            }
+        };
+
+        // The keyboard stays grabbed when going from one key of a chord to the next
+        if let Some(KeyAction::Submap(ref name)) = action {
+            if config.internal.submaps.contains_key(name) {
+                return window_manager.enter_submap(window_system.deref(), name, config);
+            }
+        }
+        // Release it before the action runs, as it might want to grab it itself
+        if !self.submaps.is_empty() && window_manager.submaps.is_empty() {
+            window_system.ungrab_keyboard();
+        }
+
+        match action {
+            Some(action) => config
+                .internal
+                .actions
+                .run(
+                    action,
+                    window_manager,
+                    window_system.clone(),
+                    &config.general,
+                )
+                .reset_submap_timeout(config),
+            None if submap.is_some() && is_escape(window_system.deref(), key) => {
+                window_manager.leave_submaps(window_system.deref())
+            }
+            None => window_manager.reset_submap_timeout(config),
+        }
+    }
+
+    /// Enter the submap with the given name. The keyboard is
+    /// grabbed until all submaps are left again.
+    pub fn enter_submap(
+        &self,
+        window_system: &dyn WindowSystem,
+        name: &str,
+        config: &Config,
+    ) -> WindowManager {
+        if !config.internal.submaps.contains_key(name) {
+            error!("unknown submap {}", name);
+            return self.clone();
+        }
+
+        if self.submaps.is_empty() {
+            window_system.grab_keyboard();
+        }
+        let mut submaps = self.submaps.clone();
+        submaps.push(name.to_owned());
+        self.with_submaps(submaps).reset_submap_timeout(config)
+    }
+
+    /// Leave the innermost submap
+    pub fn leave_submap(&self, window_system: &dyn WindowSystem) -> WindowManager {
+        let mut submaps = self.submaps.clone();
+        if submaps.pop().is_some() && submaps.is_empty() {
+            window_system.ungrab_keyboard();
+        }
+        self.with_submaps(submaps).cancel(SUBMAP_TIMER)
+    }
+
+    /// Leave all submaps and go back to the top level bindings
+    pub fn leave_submaps(&self, window_system: &dyn WindowSystem) -> WindowManager {
+        if !self.submaps.is_empty() {
+            window_system.ungrab_keyboard();
+        }
+        self.with_submaps(Vec::new()).cancel(SUBMAP_TIMER)
+    }
+
+    /// Restart the timeout of the innermost submap
+    fn reset_submap_timeout(&self, config: &Config) -> WindowManager {
+        let timeout = self
+            .submaps
+            .last()
+            .and_then(|name| config.internal.submaps.get(name))
+            .and_then(|submap| submap.timeout);
+        match timeout {
+            Some(timeout) => self.schedule(
+                SUBMAP_TIMER,
+                timeout,
+                Box::new(|m, w, _| m.leave_submaps(w.deref())),
+            ),
+            None => self.cancel(SUBMAP_TIMER),
+        }
+    }
+
+    fn with_submaps(&self, submaps: Vec<String>) -> WindowManager {
+        WindowManager {
+            running: self.running,
+            reload_requested: self.reload_requested,
+            dragging: self.dragging.clone(),
+            workspaces: self.workspaces.clone(),
+            waiting_unmap: self.waiting_unmap.clone(),
+            timers: self.timers.clone(),
+            submaps,
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
+        }
+    }
+
+    /// Call the handler registered for the given file descriptor,
+    /// once the event loop reports it as readable
+    pub fn handle_readable(
+        &self,
+        fd: RawFd,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+    ) -> WindowManager {
+        let window_manager = match config.internal.fd_handlers.get(&fd) {
+            Some(handler) => handler(self.clone(), window_system.clone(), &config.general, fd),
+            None => self.clone(),
+        };
+
+        window_manager.publish(window_system, config);
+        window_manager
+    }
+
+    /// Run the given action once the delay has passed. Scheduling another
+    /// action under the same name replaces the pending one, so repeated
+    /// requests like "re-layout in 200ms" collapse into a single one.
+    pub fn schedule(&self, name: &str, delay: Duration, action: TimerHandler) -> WindowManager {
+        let mut timers = self.timers.clone();
+        timers.insert(
+            name.to_owned(),
+            Timer {
+                deadline: Instant::now() + delay,
+                action: Rc::new(action),
+            },
+        );
+        self.with_timers(timers)
+    }
+
+    /// Remove the pending action with the given name
+    pub fn cancel(&self, name: &str) -> WindowManager {
+        let mut timers = self.timers.clone();
+        timers.remove(name);
+        self.with_timers(timers)
+    }
+
+    /// The point in time the next action is due, if there is any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers.values().map(|t| t.deadline).min()
+    }
+
+    /// The names of the actions that are due, in the order of their deadlines
+    pub fn due_timers(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mut due: Vec<(&String, &Timer)> = self
+            .timers
+            .iter()
+            .filter(|(_, t)| t.deadline <= now)
+            .collect();
+        due.sort_by_key(|(_, t)| t.deadline);
+        due.into_iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Run all actions that are due, in the order of their deadlines
+    pub fn run_timers(
+        &self,
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+    ) -> WindowManager {
+        self.run_named_timers(&self.due_timers(), window_system, config)
+    }
+
+    /// Run the actions with the given names in the given order, whether
+    /// they are due or not, e.g. the ones `due_timers` returned.
+    /// Names without a scheduled action are skipped.
+    pub fn run_named_timers(
+        &self,
+        names: &[String],
+        window_system: &Rc<dyn WindowSystem>,
+        config: &mut Config,
+    ) -> WindowManager {
+        let due: Vec<(&String, Timer)> = names
+            .iter()
+            .filter_map(|name| self.timers.get(name).map(|t| (name, t.clone())))
+            .collect();
+
+        if due.is_empty() {
+            return self.clone();
+        }
+
+        // Remove the due timers first, so actions can schedule themselves again
+        let mut pending = self.timers.clone();
+        for (name, _) in due.iter() {
+            pending.remove(*name);
+        }
+        let window_manager = due
+            .into_iter()
+            .fold(self.with_timers(pending), |w, (name, timer)| {
+                debug!("running timer {}", name);
+                (timer.action)(w, window_system.clone(), &config.general)
+            });
+
+        window_manager.publish(window_system, config);
+        window_manager
+    }
+
+    fn with_timers(&self, timers: BTreeMap<String, Timer>) -> WindowManager {
+        WindowManager {
+            running: self.running,
+            reload_requested: self.reload_requested,
+            dragging: self.dragging.clone(),
+            workspaces: self.workspaces.clone(),
+            waiting_unmap: self.waiting_unmap.clone(),
+            timers,
+            submaps: self.submaps.clone(),
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
+        }
+    }
+
     /// Send the given message to the current layout
     pub fn send_layout_message(
         &self,
@@ -423,15 +1095,11 @@ impl WindowManager {
 
     fn tile_window(
         window_system: &dyn WindowSystem,
-        config: &GeneralConfig,
+        border_width: u32,
         window: Window,
         Rectangle(x, y, w, h): Rectangle,
     ) {
-        window_system.resize_window(
-            window,
-            w - 2 * config.border_width,
-            h - 2 * config.border_width,
-        );
+        window_system.resize_window(window, w - 2 * border_width, h - 2 * border_width);
         window_system.move_window(window, x, y);
         window_system.show_window(window);
     }
@@ -448,6 +1116,28 @@ impl WindowManager {
         )
     }
 
+    /// Where a transient window floats, centered over its parent
+    fn transient_location(
+        &self,
+        window_system: &dyn WindowSystem,
+        window: Window,
+        parent: Window,
+    ) -> RationalRect {
+        let Rectangle(sx, sy, sw, sh) = self
+            .workspaces
+            .find_screen(parent)
+            .map_or(self.workspaces.current.screen_detail, |s| s.screen_detail);
+        let Rectangle(px, py, pw, ph) = window_system.get_geometry(parent);
+        let Rectangle(_, _, w, h) = window_system.get_geometry(window);
+
+        RationalRect(
+            (px as f32 + (pw as f32 - w as f32) / 2.0 - sx as f32) / sw as f32,
+            (py as f32 + (ph as f32 - h as f32) / 2.0 - sy as f32) / sh as f32,
+            w as f32 / sw as f32,
+            h as f32 / sh as f32,
+        )
+    }
+
     pub fn float(
         &self,
         window_system: &dyn WindowSystem,
@@ -473,9 +1163,15 @@ impl WindowManager {
 
         WindowManager {
             running: self.running,
+            reload_requested: self.reload_requested,
             dragging: Some(motion),
             workspaces: self.workspaces.clone(),
             waiting_unmap: self.waiting_unmap.clone(),
+            timers: self.timers.clone(),
+            submaps: self.submaps.clone(),
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
         }
     }
 
@@ -553,9 +1249,15 @@ impl WindowManager {
 
         WindowManager {
             running: self.running,
+            reload_requested: self.reload_requested,
             dragging: self.dragging.clone(),
             workspaces: self.workspaces.clone(),
             waiting_unmap: new_map,
+            timers: self.timers.clone(),
+            submaps: self.submaps.clone(),
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
         }
     }
 
@@ -566,9 +1268,15 @@ impl WindowManager {
 
         WindowManager {
             running: self.running,
+            reload_requested: self.reload_requested,
             dragging: self.dragging.clone(),
             workspaces: self.workspaces.clone(),
             waiting_unmap: new_map,
+            timers: self.timers.clone(),
+            submaps: self.submaps.clone(),
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
         }
     }
 
@@ -579,9 +1287,15 @@ impl WindowManager {
         }
         WindowManager {
             running: self.running,
+            reload_requested: self.reload_requested,
             dragging: self.dragging.clone(),
             workspaces: self.workspaces.clone(),
             waiting_unmap: new_map,
+            timers: self.timers.clone(),
+            submaps: self.submaps.clone(),
+            docks: self.docks.clone(),
+            struts: self.struts.clone(),
+            desktops: self.desktops.clone(),
         }
     }
 }
@@ -1,11 +1,14 @@
 use crate::config::GeneralConfig;
 use crate::window_manager::WindowManager;
 use libc::{c_int, c_ulong};
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{Debug, Error, Formatter};
+use std::os::unix::io::RawFd;
 
 pub type Window = u64;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Rectangle(pub i32, pub i32, pub u32, pub u32);
 
 impl Rectangle {
@@ -24,7 +27,51 @@ impl Rectangle {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// What a window is for, from its `_NET_WM_WINDOW_TYPE`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowType {
+    Normal,
+    Dialog,
+    Utility,
+    Splash,
+    Toolbar,
+    Menu,
+    Notification,
+    Desktop,
+    Dock,
+}
+
+/// How the window manager treats a new window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// Put it into the layout
+    Tile,
+    /// Float it at its requested position
+    Float,
+    /// Leave it unmanaged, as it is
+    Ignore,
+    /// Leave it unmanaged, below all other windows
+    KeepBelow,
+}
+
+impl WindowType {
+    /// How windows of this type are placed when they are managed
+    pub fn placement(self) -> Placement {
+        match self {
+            WindowType::Normal => Placement::Tile,
+            WindowType::Dialog
+            | WindowType::Utility
+            | WindowType::Splash
+            | WindowType::Toolbar
+            | WindowType::Menu => Placement::Float,
+            WindowType::Notification | WindowType::Dock => Placement::Ignore,
+            WindowType::Desktop => Placement::KeepBelow,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct WindowChanges {
     pub x: u32,
     pub y: u32,
@@ -38,7 +85,7 @@ pub struct WindowChanges {
 /// Represents a keyboard input
 /// with an abstracted modifier mask
 /// and the key represented as a string
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct KeyCommand {
     pub mask: KeyModifiers,
     pub key: u64,
@@ -57,7 +104,7 @@ impl Debug for KeyCommand {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct MouseCommand {
     pub mask: KeyModifiers,
     pub button: MouseButton,
@@ -101,15 +148,55 @@ impl KeyModifiers {
     pub fn get_mask(&self) -> u32 {
         self.bits()
     }
+
+    /// The modifiers of an event's state, without the lock modifiers
+    /// and anything else that isn't a modifier, like mouse buttons
+    pub fn from_state(state: u32, locks: KeyModifiers) -> KeyModifiers {
+        KeyModifiers::from_bits_truncate(state) - locks
+    }
+
+    /// Every combination of the given lock modifiers. A binding has
+    /// to be grabbed with each of them to work no matter which locks are on.
+    pub fn lock_combinations(locks: KeyModifiers) -> Vec<KeyModifiers> {
+        let bits: Vec<u32> = (0..8)
+            .map(|i| 1 << i)
+            .filter(|&bit| locks.bits() & bit != 0)
+            .collect();
+        (0..1u32 << bits.len())
+            .map(|subset| {
+                let mask = bits
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| subset & (1 << i) != 0)
+                    .fold(0, |mask, (_, &bit)| mask | bit);
+                KeyModifiers::from_bits_truncate(mask)
+            })
+            .collect()
+    }
+}
+
+/// Modifiers are stored as their raw bit mask
+impl Serialize for KeyModifiers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.bits())
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyModifiers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<KeyModifiers, D::Error> {
+        let bits = u32::deserialize(deserializer)?;
+        KeyModifiers::from_bits(bits)
+            .ok_or_else(|| de::Error::custom(format!("invalid modifier mask {}", bits)))
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SizeHint {
     pub min_size: Option<(u32, u32)>,
     pub max_size: Option<(u32, u32)>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum WindowSystemEvent {
     ConfigurationNotification(Window),
     ConfigurationRequest(Window, WindowChanges, u64),
@@ -125,11 +212,19 @@ pub enum WindowSystemEvent {
     /// for mousefollow focus.
     Leave(Window),
     ButtonPressed(Window, Window, MouseCommand, u32, u32),
-    ButtonReleased,
+    ButtonReleased(Window, Window, MouseCommand, u32, u32),
     MouseMotion(u32, u32),
     KeyPressed(Window, KeyCommand),
+    /// A key has been released. The modifiers don't include
+    /// the released key itself, if it is a modifier.
+    KeyReleased(Window, KeyCommand),
     ClientMessageEvent(Window, c_ulong, c_int, [i32; 5]),
     PropertyMessageEvent(bool, Window, c_ulong),
+    /// A window changed its `_NET_WM_STRUT` or `_NET_WM_STRUT_PARTIAL`
+    StrutsChanged(Window),
+    /// The keyboard mapping changed, e.g. by switching the layout,
+    /// so the keys need to be grabbed again.
+    KeyboardMappingChanged,
     /// The underlying event by xlib or wayland is unknown
     /// and can be ignored.
     UnknownEvent,
@@ -179,22 +274,43 @@ pub trait WindowSystem {
     fn event_pending(&self) -> bool;
     /// Get the next event from the queue
     fn get_event(&self) -> WindowSystemEvent;
+    /// The file descriptor of the connection to the window system, if any.
+    /// Lets the event loop wait on it together with other sources.
+    fn get_connection_fd(&self) -> Option<RawFd>;
     fn flush(&self);
+    /// Set the modifiers that are ignored in key and button bindings,
+    /// like CapsLock and NumLock. Needs to be called before grabbing.
+    fn set_lock_mask(&self, locks: KeyModifiers);
     fn grab_keys(&self, keys: Vec<KeyCommand>);
+    /// Release all keys grabbed with `grab_keys`
+    fn ungrab_keys(&self);
     fn grab_button(&self, button: MouseCommand);
+    /// Release all buttons grabbed with `grab_button`
+    fn ungrab_buttons(&self);
     fn remove_enter_events(&self);
     fn remove_motion_events(&self);
     fn get_partial_strut(&self, window: Window) -> Option<Vec<u64>>;
     fn get_strut(&self, window: Window) -> Option<Vec<u64>>;
+    /// Report changes of the dock's struts with `StrutsChanged` events
+    fn watch_struts(&self, window: Window);
     fn set_initial_properties(&self, window: Window);
     fn is_dock(&self, window: Window) -> bool;
+    /// The window's type, `Normal` if it doesn't have a known one
+    fn get_window_type(&self, window: Window) -> WindowType;
+    /// The window a transient window, like a dialog, belongs to
+    fn get_transient_for(&self, window: Window) -> Option<Window>;
     fn get_geometry(&self, window: Window) -> Rectangle;
     fn get_size_hints(&self, window: Window) -> SizeHint;
     fn restack_windows(&self, windows: Vec<Window>);
+    /// Put the window below all other windows
+    fn lower_window(&self, window: Window);
     fn close_client(&self, window: Window);
     fn kill_client(&self, window: Window);
     fn grab_pointer(&self);
     fn ungrab_pointer(&self);
+    /// Send all key presses to the window manager, e.g. while a submap is active
+    fn grab_keyboard(&self);
+    fn ungrab_keyboard(&self);
     fn get_pointer(&self, window: Window) -> (u32, u32);
     fn warp_pointer(&self, window: Window, x: u32, y: u32);
     fn overrides_redirect(&self, window: Window) -> bool;
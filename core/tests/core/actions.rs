@@ -0,0 +1,102 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::actions::{parse_layout_message, ActionRegistry};
+use self::wtftw_core::config::GeneralConfig;
+use self::wtftw_core::layout::{Direction, LayoutMessage};
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::{KeyCommand, KeyModifiers, WindowSystem, WindowSystemEvent};
+use super::setup;
+use std::rc::Rc;
+
+#[test]
+fn actions_are_called_by_name() {
+    let (ws, config) = setup();
+    let c = &config.general;
+    let registry = &config.internal.actions;
+    let m = WindowManager::new(&*ws, c)
+        .manage(&*ws, 10, c)
+        .manage(&*ws, 11, c);
+    assert!(m.workspaces.peek() == Some(11));
+
+    let m = registry.call("focus-down", m, ws.clone(), c).unwrap();
+    assert!(m.workspaces.peek() == Some(10));
+
+    let m = registry.call("shift:2", m, ws.clone(), c).unwrap();
+    assert!(m.workspaces.find_tag(10) == Some(2));
+
+    let m = registry.call("view:2", m, ws.clone(), c).unwrap();
+    assert!(m.workspaces.current_tag() == 2);
+
+    let m = registry.call("view:42", m, ws.clone(), c).unwrap();
+    assert!(m.workspaces.current_tag() == 2);
+
+    assert!(registry.call("view:two", m.clone(), ws.clone(), c).is_err());
+    assert!(registry
+        .call("no-such-action", m.clone(), ws.clone(), c)
+        .is_err());
+    assert!(registry.call("layout:sideways", m, ws, c).is_err());
+}
+
+#[test]
+fn actions_have_descriptions() {
+    let mut registry = ActionRegistry::empty();
+    registry.register("noop", "do nothing", Box::new(|m, _, _| m));
+
+    assert!(registry.describe("noop") == Some("do nothing".to_owned()));
+    assert!(registry.describe("focus-down").is_none());
+
+    let registry = ActionRegistry::new();
+    let list = registry.list();
+    assert!(list.iter().any(|a| a.name == "swap-master"));
+    assert!(list.iter().any(|a| a.name == "view:ARG"));
+    assert!(registry.describe("view:3").is_some());
+}
+
+#[test]
+fn keys_are_bound_to_names_and_handlers() {
+    let (ws, mut config) = setup();
+    config.add_key_handler(40, KeyModifiers::MOD1MASK, "view:1");
+    config.add_key_handler(41, KeyModifiers::MOD1MASK, "no-such-action");
+    config.add_key_handler(
+        42,
+        KeyModifiers::MOD1MASK,
+        Box::new(|m: WindowManager, w: Rc<dyn WindowSystem>, c: &GeneralConfig| m.view(&*w, 3, c)),
+    );
+
+    let m = WindowManager::new(&*ws, &config.general);
+    let press =
+        |key| WindowSystemEvent::KeyPressed(0, KeyCommand::new(key, KeyModifiers::MOD1MASK));
+
+    let m = m.handle_event(press(40), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 1);
+    let m = m.handle_event(press(41), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 1);
+    let m = m.handle_event(press(42), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 3);
+
+    let bindings = config.key_bindings();
+    assert!(bindings.len() == 3);
+    assert!(bindings[0].1.as_ref().unwrap().name == "view:1");
+    assert!(bindings[2].1.is_none());
+}
+
+#[test]
+fn layout_messages_are_parsed() {
+    assert!(matches!(
+        parse_layout_message("next"),
+        Ok(LayoutMessage::Next)
+    ));
+    assert!(matches!(
+        parse_layout_message("increase-master"),
+        Ok(LayoutMessage::IncreaseMaster)
+    ));
+    assert!(matches!(
+        parse_layout_message("IncreaseGap"),
+        Ok(LayoutMessage::IncreaseGap)
+    ));
+    assert!(matches!(
+        parse_layout_message("tree-expand-towards:left"),
+        Ok(LayoutMessage::TreeExpandTowards(Direction::Left))
+    ));
+    assert!(parse_layout_message("sideways").is_err());
+}
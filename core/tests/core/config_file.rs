@@ -0,0 +1,298 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::config::{config_manifest, Config};
+use self::wtftw_core::config_file::ConfigFile;
+use self::wtftw_core::headless_window_system::{HeadlessWindowSystem, WindowSystemCall};
+use self::wtftw_core::layout::LayoutMessage;
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::{
+    KeyCommand, KeyModifiers, Rectangle, WindowSystem, WindowSystemEvent,
+};
+use log::LevelFilter;
+use std::env::temp_dir;
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+use std::rc::Rc;
+use std::time::Duration;
+
+const CONFIG: &str = r##"
+focus_follows_mouse = false
+border_color = "#404040"
+focus_border_color = 0xebebeb
+border_width = 3
+terminal = "urxvt -e tmux"
+tags = ["web", "code", "chat"]
+mod_mask = "mod4"
+lock_mask = ["lock", "mod2", "mod5"]
+log_level = "debug"
+
+[layout]
+kind = "Gap"
+params = { gap = 8 }
+[[layout.children]]
+kind = "Tall"
+params = { ratio = 0.6 }
+
+[keys]
+"M-j" = "focus-down"
+"M-S-c" = "kill"
+"M-2" = "view:1"
+"M-x t" = "terminal"
+
+[release_keys]
+"Super_L" = "view:0"
+
+[submaps.resize]
+enter = "M-r"
+timeout = 5
+keys = { h = "layout:decrease", l = "layout:increase" }
+
+[[rules]]
+class = "Gimp"
+float = true
+workspace = 2
+
+[[rules]]
+title = "panel"
+ignore = true
+
+[[rules]]
+title = { regex = "^mpv - " }
+tag = "chat"
+border_width = 0
+"##;
+
+#[test]
+fn settings_are_applied() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    let mut config = Config::initialize().unwrap();
+    ConfigFile::parse(CONFIG)
+        .unwrap()
+        .apply(&mut config, &ws)
+        .unwrap();
+
+    let c = &config.general;
+    assert!(!c.focus_follows_mouse);
+    assert!(c.border_color == 0x404040);
+    assert!(c.focus_border_color == 0xebebeb);
+    assert!(c.border_width == 3);
+    assert!(c.terminal == ("urxvt".to_owned(), "-e tmux".to_owned()));
+    assert!(c.tags.len() == 3);
+    assert!(c.mod_mask == KeyModifiers::MOD4MASK);
+    assert!(
+        c.lock_mask == KeyModifiers::LOCKMASK | KeyModifiers::MOD2MASK | KeyModifiers::MOD5MASK
+    );
+    assert!(c.layout.state().params["gap"] == 8);
+    assert!(c.log_level == LevelFilter::Debug);
+
+    let bindings = config.internal.key_handlers;
+    let key = |k: char, mask| KeyCommand::new(k as u64, mask);
+    assert!(bindings.contains_key(&key('j', KeyModifiers::MOD4MASK)));
+    assert!(bindings.contains_key(&key('c', KeyModifiers::MOD4MASK | KeyModifiers::SHIFTMASK)));
+    assert!(bindings[&key('2', KeyModifiers::MOD4MASK)].name() == Some("view:1"));
+
+    assert!(config.internal.key_release_handlers.len() == 1);
+
+    let submaps = config.internal.submaps;
+    assert!(submaps["M-x"].keys[&key('t', KeyModifiers::NONEMASK)].name() == Some("terminal"));
+    let resize = &submaps["resize"];
+    assert!(resize.sticky && resize.timeout == Some(Duration::from_secs(5)));
+    assert!(resize.keys.len() == 2);
+}
+
+#[test]
+fn rules_place_windows() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    ws.add_window(10, Rectangle(100, 100, 200, 200));
+    ws.set_class_name(10, "Gimp");
+    ws.add_window(11, Rectangle(0, 0, 800, 20));
+    ws.set_window_name(11, "panel");
+    ws.add_window(12, Rectangle(0, 0, 640, 480));
+    ws.set_window_name(12, "mpv - video.mkv");
+    let ws: Rc<dyn WindowSystem> = Rc::new(ws);
+
+    let mut config = Config::initialize().unwrap();
+    ConfigFile::parse(CONFIG)
+        .unwrap()
+        .apply(&mut config, &*ws)
+        .unwrap();
+
+    let m = WindowManager::new(&*ws, &config.general)
+        .handle_event(WindowSystemEvent::WindowCreated(10), &ws, &mut config)
+        .handle_event(WindowSystemEvent::WindowCreated(11), &ws, &mut config)
+        .handle_event(WindowSystemEvent::WindowCreated(12), &ws, &mut config);
+
+    assert!(m.workspaces.find_tag(10) == Some(2));
+    assert!(m.workspaces.floating.contains_key(&10));
+    assert!(!m.workspaces.contains(11));
+    assert!(m.workspaces.find_tag(12) == Some(2));
+    assert!(m.workspaces.border_width(12, &config.general) == 0);
+}
+
+#[test]
+fn invalid_entries_are_reported() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    let mut config = Config::initialize().unwrap();
+
+    assert!(ConfigFile::parse("border_colour = 1").is_err());
+    assert!(ConfigFile::parse("border_width = \"wide\"").is_err());
+
+    let file = ConfigFile::parse(
+        r##"
+        border_color = "#4040"
+        border_width = 5
+        mod_mask = "hyper"
+
+        [keys]
+        "M-j" = "focus-sideways"
+        "X-k" = "focus-up"
+        "M-Escpae" = "focus-up"
+        "M-k" = "focus-up"
+
+        [[rules]]
+        float = true
+
+        [[rules]]
+        class = { regex = "(Gimp" }
+
+        [[rules]]
+        type = "dock"
+        workspace = 1
+        "##,
+    )
+    .unwrap();
+
+    let message = format!("{:#}", file.apply(&mut config, &ws).unwrap_err());
+    assert!(message.contains("border_color: invalid color #4040"));
+    assert!(message.contains("mod_mask: unknown modifier hyper"));
+    assert!(message.contains("keys.M-j: unknown action focus-sideways"));
+    assert!(message.contains("keys.X-k: unknown modifier X"));
+    assert!(message.contains("keys.M-Escpae: unknown key Escpae"));
+    assert!(message.contains("rules[0]"));
+    assert!(message.contains("rules[1]: invalid regex (Gimp"));
+    assert!(message.contains("rules[2]: windows of type Dock aren't managed"));
+
+    // Valid entries are applied nevertheless
+    assert!(config.general.border_width == 5);
+    let key = KeyCommand::new('k' as u64, KeyModifiers::MOD1MASK);
+    assert!(config.internal.key_handlers.contains_key(&key));
+}
+
+#[test]
+fn example_config_is_valid() {
+    let file = ConfigFile::parse(include_str!("../../../config/config.toml")).unwrap();
+    assert!(file.layout.unwrap().build().is_ok());
+    assert!(file.keys.len() > 10);
+}
+
+#[test]
+fn config_is_reloaded() {
+    let dir = temp_dir().join(format!("wtftw-reload-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    let window_system = Rc::new(HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]));
+    let ws: Rc<dyn WindowSystem> = window_system.clone();
+    let mut config = Config::initialize().unwrap();
+    config.internal.wtftw_dir = dir.to_str().unwrap().to_owned();
+
+    // Workspace 0 gets its layout changed at runtime
+    let m = WindowManager::new(&*ws, &config.general).send_layout_message(
+        LayoutMessage::IncreaseMaster,
+        &*ws,
+        &config.general,
+    );
+    let changed = m.workspaces.current.workspace.layout_state();
+    let m = config
+        .internal
+        .actions
+        .call("reload", m, ws.clone(), &config.general)
+        .unwrap();
+    assert!(m.reload_requested);
+
+    write(
+        dir.join("config.toml"),
+        r##"
+        border_width = 5
+
+        [layout]
+        kind = "Full"
+
+        [keys]
+        "M-j" = "focus-down"
+        "##,
+    )
+    .unwrap();
+    let m = m.reload_config(&ws, &mut config).unwrap();
+    assert!(!m.reload_requested);
+    assert!(config.general.border_width == 5);
+    let key = KeyCommand::new('j' as u64, KeyModifiers::MOD1MASK);
+    assert!(config.internal.key_handlers.contains_key(&key));
+    assert!(m.workspaces.current.workspace.layout_state() == changed);
+    assert!(m.workspaces.hidden[0].layout_state().kind == "Full");
+    let calls = window_system.calls();
+    assert!(calls.contains(&WindowSystemCall::UngrabKeys));
+    assert!(calls.contains(&WindowSystemCall::UngrabButtons));
+
+    // A broken config leaves the old one in place
+    write(dir.join("config.toml"), "border_width = \"wide\"").unwrap();
+    assert!(m.reload_config(&ws, &mut config).is_err());
+    assert!(config.general.border_width == 5);
+    assert!(config.internal.key_handlers.contains_key(&key));
+    assert!(m.workspaces.hidden[0].layout_state().kind == "Full");
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn config_module_uses_this_wtftw() {
+    let manifest = config_manifest();
+    assert!(manifest.contains("package = \"wtftw_core\""));
+    assert!(manifest.contains(&format!("path = \"{}\"", env!("CARGO_MANIFEST_DIR"))));
+    assert!(!manifest.contains("git"));
+}
+
+#[test]
+fn old_config_manifest_is_kept() {
+    let dir = temp_dir().join(format!("wtftw-manifest-{}", std::process::id()));
+    create_dir_all(&dir).unwrap();
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    let mut config = Config::initialize().unwrap();
+    config.internal.wtftw_dir = dir.to_str().unwrap().to_owned();
+
+    // As written by older versions, indentation and all
+    let old = "[project]\nname = \"config\"\nversion = \"0.0.0\"\nauthors = [\"wtftw\"]\n\n\
+         [dependencies.wtftw_contrib]\n                                     \
+         git = \"https://github.com/Kintaro/wtftw-contrib.git\"\n\n                                     \
+         [dependencies.wtftw]\ngit = \"https://github.com/Kintaro/wtftw.git\"\n\n\
+         [lib]\nname = \"config\"\ncrate-type = [\"dylib\"]";
+    write(dir.join("Cargo.toml"), old).unwrap();
+    let mut m = WindowManager::new(&ws, &config.general);
+    config.compile_and_call(&mut m, &ws).unwrap();
+    assert!(read_to_string(dir.join("Cargo.toml")).unwrap() == old);
+    assert!(read_to_string(dir.join("Cargo.toml.new")).unwrap() == config_manifest());
+
+    // Changed manifests are left alone
+    let changed = config_manifest() + "\n[dependencies]\nrand = \"0.8\"\n";
+    write(dir.join("Cargo.toml"), &changed).unwrap();
+    config.compile_and_call(&mut m, &ws).unwrap();
+    assert!(read_to_string(dir.join("Cargo.toml")).unwrap() == changed);
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn config_problems_are_found() {
+    let screen = Rectangle(0, 0, 800, 600);
+    let mut config = Config::initialize().unwrap();
+    assert!(config.check(vec![screen; 2]).is_empty());
+
+    config.general.tags.truncate(1);
+    config.add_key_handler(0, KeyModifiers::MOD1MASK, "terminal");
+    config.add_key_handler('x' as u64, KeyModifiers::MOD1MASK, "focus-sideways");
+    config.add_key_handler('j' as u64, KeyModifiers::MOD1MASK, "focus-down");
+    config.add_key_handler('j' as u64, KeyModifiers::MOD1MASK, "focus-up");
+    let problems = config.check(vec![screen; 2]);
+    assert!(problems.len() == 4);
+    assert!(problems[0] == "1 tags for 2 screens, every screen needs a workspace");
+    assert!(problems.contains(&"terminal is bound to an unknown key".to_owned()));
+    assert!(problems.contains(&"unknown action focus-sideways".to_owned()));
+    assert!(problems.contains(&"M-j was bound to focus-down, now to focus-up".to_owned()));
+}
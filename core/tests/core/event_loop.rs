@@ -0,0 +1,128 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::event_loop::{EventLoop, Wakeup};
+use self::wtftw_core::headless_window_system::HeadlessWindowSystem;
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::{Rectangle, WindowSystemEvent};
+use super::setup;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn timers_fire_after_their_delay() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&*ws, &config.general)
+        .schedule(
+            "view",
+            Duration::from_millis(20),
+            Box::new(|m, ws, c| m.view(&*ws, 2, c)),
+        )
+        .schedule(
+            "manage",
+            Duration::from_millis(10),
+            Box::new(|m, ws, c| m.manage(&*ws, 10, c)),
+        );
+
+    let start = Instant::now();
+    let mut event_loop = EventLoop::new(ws.clone());
+    let mut m = m;
+    while !m.timers.is_empty() {
+        match event_loop.next(&m) {
+            Wakeup::Timers => m = m.run_timers(&ws, &mut config),
+            _ => panic!("expected timers"),
+        }
+    }
+
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert!(m.workspaces.current_tag() == 2);
+    assert!(m.workspaces.find_tag(10) == Some(0));
+}
+
+#[test]
+fn timers_are_replaced_and_cancelled_by_name() {
+    let (ws, config) = setup();
+    let c = &config.general;
+    let m = WindowManager::new(&*ws, c)
+        .schedule("a", Duration::from_secs(60), Box::new(|m, _, _| m))
+        .schedule("a", Duration::from_secs(1), Box::new(|m, _, _| m))
+        .schedule("b", Duration::from_secs(5), Box::new(|m, _, _| m));
+
+    assert!(m.timers.len() == 2);
+    assert!(m.next_deadline().unwrap() < Instant::now() + Duration::from_secs(2));
+
+    let m = m.cancel("a").cancel("b");
+    assert!(m.timers.is_empty());
+    assert!(m.next_deadline().is_none());
+}
+
+#[test]
+fn timers_only_run_when_due() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&*ws, &config.general).schedule(
+        "later",
+        Duration::from_secs(60),
+        Box::new(|m, ws, c| m.view(&*ws, 1, c)),
+    );
+
+    let m = m.run_timers(&ws, &mut config);
+    assert!(m.timers.len() == 1);
+    assert!(m.workspaces.current_tag() == 0);
+}
+
+#[test]
+fn watched_fds_and_events_wake_up_the_loop() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    let (mut writer, mut reader) = UnixStream::pair().unwrap();
+    let fd = reader.as_raw_fd();
+    config.add_fd_handler(fd, Box::new(|m, ws, c, _| m.view(&*ws, 3, c)));
+
+    let mut event_loop = EventLoop::new(ws.clone());
+    event_loop.watch(fd);
+    writer.write_all(b"x").unwrap();
+
+    let m = match event_loop.next(&m) {
+        Wakeup::Readable(x) if x == fd => m.handle_readable(fd, &ws, &mut config),
+        _ => panic!("expected readable fd"),
+    };
+    assert!(m.workspaces.current_tag() == 3);
+
+    let mut buffer = [0; 1];
+    reader.read_exact(&mut buffer).unwrap();
+    event_loop.unwatch(fd);
+
+    let headless = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    headless.push_event(WindowSystemEvent::Enter(10));
+    let mut event_loop = EventLoop::new(Rc::new(headless));
+    match event_loop.next(&m) {
+        Wakeup::Event(WindowSystemEvent::Enter(10)) => (),
+        _ => panic!("expected event"),
+    }
+}
+
+#[test]
+fn writable_fds_wake_up_the_loop() {
+    let (ws, config) = setup();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    let (writer, _reader) = UnixStream::pair().unwrap();
+    let fd = writer.as_raw_fd();
+    let mut event_loop = EventLoop::new(ws.clone());
+    event_loop.watch_writable(fd);
+    match event_loop.next(&m) {
+        Wakeup::Writable(x) if x == fd => (),
+        _ => panic!("expected writable fd"),
+    }
+
+    // Nothing else is watched, so only the timer is left
+    event_loop.unwatch(fd);
+    let m = m.schedule("a", Duration::from_millis(1), Box::new(|m, _, _| m));
+    match event_loop.next(&m) {
+        Wakeup::Timers => (),
+        _ => panic!("expected timers"),
+    }
+}
@@ -0,0 +1,288 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::config::Config;
+use self::wtftw_core::ipc::{IpcCommand, IpcEvent, IpcResponse, IpcServer};
+use self::wtftw_core::layout::LayoutMessage;
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::WindowSystem;
+use super::setup;
+use std::env::temp_dir;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+
+fn execute(
+    command: &str,
+    m: &WindowManager,
+    ws: &Rc<dyn WindowSystem>,
+    config: &Config,
+) -> (WindowManager, IpcResponse) {
+    serde_json::from_str::<IpcCommand>(command)
+        .unwrap()
+        .execute(m, ws, config)
+        .unwrap()
+}
+
+#[test]
+fn view_and_shift() {
+    let (ws, config) = setup();
+    let m = WindowManager::new(&*ws, &config.general)
+        .manage(&*ws, 10, &config.general)
+        .manage(&*ws, 11, &config.general);
+
+    let (m, _) = execute(r#"{"command": "shift", "workspace": 2}"#, &m, &ws, &config);
+    assert!(m.workspaces.find_tag(11) == Some(2));
+
+    let (m, _) = execute(r#"{"command": "view", "workspace": 2}"#, &m, &ws, &config);
+    assert!(m.workspaces.current_tag() == 2);
+    assert!(m.workspaces.peek() == Some(11));
+}
+
+#[test]
+fn float_and_sink_focused_window() {
+    let (ws, config) = setup();
+    let m = WindowManager::new(&*ws, &config.general).manage(&*ws, 10, &config.general);
+
+    let (m, _) = execute(r#"{"command": "float", "window": null}"#, &m, &ws, &config);
+    assert!(m.workspaces.floating.contains_key(&10));
+
+    let (m, _) = execute(r#"{"command": "sink", "window": 10}"#, &m, &ws, &config);
+    assert!(!m.workspaces.floating.contains_key(&10));
+}
+
+#[test]
+fn layout_message_and_query() {
+    let (ws, config) = setup();
+    let m = WindowManager::new(&*ws, &config.general).manage(&*ws, 10, &config.general);
+
+    let (m, _) = execute(
+        r#"{"command": "layout", "message": "IncreaseMaster"}"#,
+        &m,
+        &ws,
+        &config,
+    );
+
+    match execute(r#"{"command": "query"}"#, &m, &ws, &config).1 {
+        IpcResponse::State { state } => {
+            assert!(state.current == 0);
+            assert!(state.focused == Some(10));
+            assert!(state.workspaces.len() == config.general.tags.len());
+            assert!(state.workspaces[0].screen == Some(0));
+            assert!(state.workspaces[0].layout_state.params["num_master"] == 2);
+        }
+        _ => panic!("expected state"),
+    }
+}
+
+#[test]
+fn actions_are_called_and_listed() {
+    let (ws, config) = setup();
+    let m = WindowManager::new(&*ws, &config.general)
+        .manage(&*ws, 10, &config.general)
+        .manage(&*ws, 11, &config.general);
+
+    let (m, _) = execute(
+        r#"{"command": "action", "name": "focus-down"}"#,
+        &m,
+        &ws,
+        &config,
+    );
+    assert!(m.workspaces.peek() == Some(10));
+
+    match execute(r#"{"command": "actions"}"#, &m, &ws, &config).1 {
+        IpcResponse::Actions { actions } => assert!(actions.iter().any(|a| a.name == "exit")),
+        _ => panic!("expected actions"),
+    }
+}
+
+#[test]
+fn invalid_commands_are_rejected() {
+    let (ws, config) = setup();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    let view = IpcCommand::View { workspace: 42 };
+    assert!(view.execute(&m, &ws, &config).is_err());
+
+    let float = IpcCommand::Float { window: Some(10) };
+    assert!(float.execute(&m, &ws, &config).is_err());
+
+    let action = IpcCommand::Action {
+        name: "no-such-action".to_owned(),
+    };
+    assert!(action.execute(&m, &ws, &config).is_err());
+
+    let message = serde_json::to_string(&IpcCommand::Layout {
+        message: LayoutMessage::IncreaseGap,
+    })
+    .unwrap();
+    assert!(message == r#"{"command":"layout","message":"IncreaseGap"}"#);
+}
+
+#[test]
+fn server_answers_clients() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    let path = temp_dir().join(format!("wtftw-test-{}.sock", std::process::id()));
+    let server = IpcServer::bind(&path).unwrap();
+    assert!(IpcServer::bind(&path).is_err());
+
+    let mut client = UnixStream::connect(&path).unwrap();
+    writeln!(client, r#"{{"command": "view", "workspace": 1}}"#).unwrap();
+    let m = server.handle_clients(m, &ws, &mut config);
+
+    let mut line = String::new();
+    BufReader::new(&client).read_line(&mut line).unwrap();
+    assert!(m.workspaces.current_tag() == 1);
+    assert!(line.trim() == r#"{"status":"ok"}"#);
+
+    let mut client = UnixStream::connect(&path).unwrap();
+    writeln!(client, "garbage").unwrap();
+    server.handle_clients(m, &ws, &mut config);
+
+    let mut line = String::new();
+    BufReader::new(&client).read_line(&mut line).unwrap();
+    assert!(line.contains(r#""status":"error""#));
+
+    drop(server);
+    assert!(!path.exists());
+}
+
+#[test]
+fn slow_clients_are_waited_for() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    let path = temp_dir().join(format!("wtftw-slow-{}.sock", std::process::id()));
+    let server = IpcServer::bind(&path).unwrap();
+
+    // Nothing sent yet, so nothing to wait for
+    let mut client = UnixStream::connect(&path).unwrap();
+    let m = server.handle_clients(m, &ws, &mut config);
+    assert!(server.client_fds().len() == 1);
+    assert!(server.owns(server.client_fds()[0]));
+
+    write!(client, r#"{{"command": "view", "#).unwrap();
+    let m = server.handle_clients(m, &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 0);
+    assert!(server.client_fds().len() == 1);
+
+    writeln!(client, r#""workspace": 2}}"#).unwrap();
+    let m = server.handle_clients(m, &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 2);
+    assert!(server.client_fds().is_empty());
+
+    let mut line = String::new();
+    BufReader::new(&client).read_line(&mut line).unwrap();
+    assert!(line.trim() == r#"{"status":"ok"}"#);
+}
+
+#[test]
+fn diff_describes_changes() {
+    let (ws, config) = setup();
+    let c = &config.general;
+    let old = WindowManager::new(&*ws, c).manage(&*ws, 10, c);
+    let new = old
+        .manage(&*ws, 11, c)
+        .unmanage(&*ws, 10, c)
+        .send_layout_message(LayoutMessage::IncreaseMaster, &*ws, c)
+        .view(&*ws, 1, c);
+
+    let events = IpcEvent::diff(&old, &new);
+
+    assert!(events.contains(&IpcEvent::WorkspaceSwitched { from: 0, to: 1 }));
+    assert!(events.contains(&IpcEvent::WindowManaged {
+        window: 11,
+        workspace: 0
+    }));
+    assert!(events.contains(&IpcEvent::WindowUnmanaged { window: 10 }));
+    assert!(events.contains(&IpcEvent::FocusChanged { window: None }));
+    assert!(events.iter().any(|e| match e {
+        IpcEvent::LayoutChanged { workspace, .. } => *workspace == 0,
+        _ => false,
+    }));
+    assert!(IpcEvent::diff(&new, &new).is_empty());
+}
+
+#[test]
+fn subscribers_receive_events() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    let path = temp_dir().join(format!("wtftw-subscribe-{}.sock", std::process::id()));
+    let server = IpcServer::bind(&path).unwrap();
+
+    let subscriber = UnixStream::connect(&path).unwrap();
+    writeln!(&subscriber, r#"{{"command": "subscribe"}}"#).unwrap();
+    let m = server.handle_clients(m, &ws, &mut config);
+    assert!(server.has_subscribers());
+
+    let client = UnixStream::connect(&path).unwrap();
+    writeln!(&client, r#"{{"command": "view", "workspace": 3}}"#).unwrap();
+    let m = server.handle_clients(m, &ws, &mut config);
+
+    let new = m.manage(&*ws, 10, &config.general);
+    server.notify(&m, &new);
+
+    let mut lines = BufReader::new(&subscriber).lines();
+    assert!(lines.next().unwrap().unwrap() == r#"{"status":"ok"}"#);
+    let event: IpcEvent = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    assert!(event == IpcEvent::WorkspaceSwitched { from: 0, to: 3 });
+    let event: IpcEvent = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    assert!(
+        event
+            == IpcEvent::WindowManaged {
+                window: 10,
+                workspace: 3
+            }
+    );
+
+    drop(lines);
+    drop(subscriber);
+    server.notify(&new, &m);
+    assert!(!server.has_subscribers());
+}
+
+#[test]
+fn slow_subscribers_get_every_event() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    let path = temp_dir().join(format!("wtftw-backlog-{}.sock", std::process::id()));
+    let server = IpcServer::bind(&path).unwrap();
+
+    let subscriber = UnixStream::connect(&path).unwrap();
+    writeln!(&subscriber, r#"{{"command": "subscribe"}}"#).unwrap();
+    let m = server.handle_clients(m, &ws, &mut config);
+    let other = m.view(&*ws, 1, &config.general);
+
+    // Far more than the socket takes at once, while nobody reads
+    let switches = 15000;
+    for i in 0..switches {
+        if i % 2 == 0 {
+            server.notify(&m, &other);
+        } else {
+            server.notify(&other, &m);
+        }
+    }
+    assert!(server.has_subscribers());
+    // The rest waits for the subscriber to become writable
+    assert!(server.output_fds().len() == 1);
+    assert!(server.owns(server.output_fds()[0]));
+
+    let reader = std::thread::spawn(move || {
+        let mut lines = BufReader::new(&subscriber).lines();
+        assert!(lines.next().unwrap().unwrap() == r#"{"status":"ok"}"#);
+        for _ in 0..switches {
+            let line = lines.next().unwrap().unwrap();
+            serde_json::from_str::<IpcEvent>(&line).unwrap();
+        }
+    });
+    // Notifications without changes send what is still queued
+    while !reader.is_finished() {
+        server.notify(&m, &m);
+    }
+    reader.join().unwrap();
+    assert!(server.has_subscribers());
+    assert!(server.output_fds().is_empty());
+}
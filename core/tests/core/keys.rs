@@ -0,0 +1,287 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::actions::KeyAction;
+use self::wtftw_core::add_key_binding;
+use self::wtftw_core::config::Config;
+use self::wtftw_core::headless_window_system::{HeadlessWindowSystem, WindowSystemCall};
+use self::wtftw_core::keycodes::{keysym_from_name, keysym_name};
+use self::wtftw_core::keys::{format_key, parse_key, Submap};
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::{
+    KeyCommand, KeyModifiers, Rectangle, WindowSystem, WindowSystemEvent,
+};
+use std::rc::Rc;
+
+fn press(key: char, mask: KeyModifiers) -> WindowSystemEvent {
+    WindowSystemEvent::KeyPressed(0, KeyCommand::new(key as u64, mask))
+}
+
+#[test]
+fn key_specifications_are_parsed() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    let modm = KeyModifiers::MOD4MASK;
+
+    assert!(
+        parse_key("M-S-x", modm, &ws).unwrap()
+            == KeyCommand::new('x' as u64, modm | KeyModifiers::SHIFTMASK)
+    );
+    assert!(
+        parse_key("C-M1-1", modm, &ws).unwrap()
+            == KeyCommand::new(
+                '1' as u64,
+                KeyModifiers::CONTROLMASK | KeyModifiers::MOD1MASK
+            )
+    );
+    assert!(
+        parse_key("<a>", modm, &ws).unwrap() == KeyCommand::new('a' as u64, KeyModifiers::NONEMASK)
+    );
+    assert!(parse_key("M--", modm, &ws).unwrap() == KeyCommand::new('-' as u64, modm));
+    assert!(
+        parse_key("-", modm, &ws).unwrap() == KeyCommand::new('-' as u64, KeyModifiers::NONEMASK)
+    );
+
+    assert!(parse_key("H-x", modm, &ws).is_err());
+    assert!(parse_key("M-", modm, &ws).is_err());
+    assert!(parse_key("M-<>", modm, &ws).is_err());
+    assert!(parse_key("M-Retrun", modm, &ws).is_err());
+}
+
+#[test]
+fn key_names_are_resolved_without_a_display() {
+    assert!(keysym_from_name("Return") == Some(0xff0d));
+    assert!(keysym_from_name("XF86AudioRaiseVolume") == Some(0x1008ff13));
+    assert!(keysym_from_name("a") == Some('a' as u64));
+    assert!(keysym_from_name("minus") == keysym_from_name("-"));
+    assert!(keysym_from_name("Retrun").is_none());
+    assert!(keysym_name(0xff1b) == Some("Escape"));
+    assert!(keysym_name(0x1008ff02) == Some("XF86MonBrightnessUp"));
+
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    let modm = KeyModifiers::MOD4MASK;
+    for &spec in [
+        "M-S-Return",
+        "C-M1-1",
+        "<XF86AudioMute>",
+        "M-C-space",
+        "minus",
+    ]
+    .iter()
+    {
+        let key = parse_key(spec, modm, &ws).unwrap();
+        assert!(format_key(key, modm) == spec);
+    }
+}
+
+#[test]
+fn duplicate_bindings_are_rejected() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    let mut config = Config::initialize().unwrap();
+    config.general.mod_mask = KeyModifiers::MOD1MASK;
+
+    assert!(config.add_key_binding(&ws, "M-j", "focus-down").is_ok());
+    let error = config.add_key_binding(&ws, "M1-j", "focus-up").unwrap_err();
+    assert!(format!("{}", error) == "M1-j is already bound to focus-down");
+    assert!(config.add_key_binding(&ws, "M-S-j", "swap-down").is_ok());
+
+    add_key_binding!(config, &ws, "M-S-j", "swap-up");
+    add_key_binding!(config, &ws, "M-Escpae", |m, _, _| m);
+    assert!(config.internal.errors.len() == 2);
+    assert!(config.internal.key_handlers.len() == 2);
+}
+
+#[test]
+fn submaps_stay_active_until_left() {
+    let headless = Rc::new(HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]));
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+    let mut config = Config::initialize().unwrap();
+    let modm = config.general.mod_mask;
+
+    config.add_submap("workspaces", Submap::mode());
+    config
+        .add_key_binding(&*ws, "M-w", KeyAction::Submap("workspaces".to_owned()))
+        .unwrap();
+    config.add_key_binding(&*ws, "n", "view:3").unwrap();
+    config
+        .add_submap_binding(&*ws, "workspaces", "n", "view:1")
+        .unwrap();
+    config
+        .add_submap_binding(&*ws, "workspaces", "p", "view:2")
+        .unwrap();
+    config
+        .add_submap_binding(&*ws, "workspaces", "q", "leave-submap")
+        .unwrap();
+    config
+        .add_submap_binding(&*ws, "workspaces", "S-Escape", "view:0")
+        .unwrap();
+    assert!(config
+        .add_submap_binding(&*ws, "nowhere", "n", "view:1")
+        .is_err());
+
+    let m = WindowManager::new(&*ws, &config.general);
+    let m = m.handle_event(press('w', modm), &ws, &mut config);
+    assert!(m.submaps == vec!["workspaces".to_owned()]);
+    assert!(headless.calls().contains(&WindowSystemCall::GrabKeyboard));
+    assert!(m.next_deadline().is_none());
+
+    let m = m.handle_event(press('n', KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 1);
+    let m = m.handle_event(press('x', KeyModifiers::NONEMASK), &ws, &mut config);
+    let m = m.handle_event(press('p', KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 2);
+    assert!(m.submaps.len() == 1);
+
+    let m = m.handle_event(press('q', KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.submaps.is_empty());
+    assert!(headless.calls().contains(&WindowSystemCall::UngrabKeyboard));
+
+    let m = m.handle_event(press('n', KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 3);
+
+    let escape = |mask| {
+        let key = KeyCommand::new(keysym_from_name("Escape").unwrap(), mask);
+        WindowSystemEvent::KeyPressed(0, key)
+    };
+    let m = m.handle_event(press('w', modm), &ws, &mut config);
+    let m = m.handle_event(escape(KeyModifiers::SHIFTMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 0);
+    let m = m.handle_event(escape(KeyModifiers::CONTROLMASK), &ws, &mut config);
+    assert!(m.submaps.len() == 1);
+    let m = m.handle_event(escape(KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.submaps.is_empty());
+}
+
+#[test]
+fn chords_are_left_after_one_key() {
+    let headless = Rc::new(HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]));
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+    let mut config = Config::initialize().unwrap();
+    let modm = config.general.mod_mask;
+
+    config.add_key_binding(&*ws, "M-x 1", "view:1").unwrap();
+    config.add_key_binding(&*ws, "M-x 2", "view:2").unwrap();
+    config.add_key_binding(&*ws, "M-x S-x 3", "view:3").unwrap();
+    config.add_key_binding(&*ws, "M-x S-t", "view:0").unwrap();
+    let error = config
+        .add_key_binding(&*ws, "M-x 1 2", "view:2")
+        .unwrap_err();
+    assert!(format!("{}", error) == "M-x 1 is already bound to view:1");
+    let error = config.add_key_binding(&*ws, "M-x", "view:2").unwrap_err();
+    assert!(format!("{}", error) == "M-x is already bound to submap M-x");
+
+    let m = WindowManager::new(&*ws, &config.general);
+    let m = m.handle_event(press('x', modm), &ws, &mut config);
+    assert!(m.submaps == vec!["M-x".to_owned()]);
+    assert!(m.next_deadline().is_some());
+
+    let m = m.handle_event(press('2', KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 2);
+    assert!(m.submaps.is_empty());
+    assert!(m.next_deadline().is_none());
+
+    let m = m.handle_event(press('x', modm), &ws, &mut config);
+    let m = m.handle_event(press('x', KeyModifiers::SHIFTMASK), &ws, &mut config);
+    assert!(m.submaps.len() == 1);
+    let m = m.handle_event(press('3', KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 3);
+
+    let m = m.handle_event(press('x', modm), &ws, &mut config);
+    let m = m.handle_event(press('9', KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 3);
+    assert!(m.submaps.is_empty());
+
+    // Holding shift for the next key doesn't end the chord
+    let shift = KeyCommand::new(keysym_from_name("Shift_L").unwrap(), KeyModifiers::NONEMASK);
+    let m = m.handle_event(press('x', modm), &ws, &mut config);
+    let m = m.handle_event(WindowSystemEvent::KeyPressed(0, shift), &ws, &mut config);
+    assert!(m.submaps.len() == 1);
+    let m = m.handle_event(press('t', KeyModifiers::SHIFTMASK), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 0);
+    assert!(m.submaps.is_empty());
+
+    // Going from one key of a chord to the next keeps the keyboard grabbed
+    let grabs: Vec<bool> = headless
+        .calls()
+        .into_iter()
+        .filter_map(|call| match call {
+            WindowSystemCall::GrabKeyboard => Some(true),
+            WindowSystemCall::UngrabKeyboard => Some(false),
+            _ => None,
+        })
+        .collect();
+    assert!(grabs == vec![true, false, true, true, false, true, false, true, false]);
+}
+
+#[test]
+fn lock_modifiers_are_ignored() {
+    let locks = KeyModifiers::LOCKMASK | KeyModifiers::MOD2MASK;
+    let combinations = KeyModifiers::lock_combinations(locks);
+    assert!(combinations.len() == 4);
+    assert!(combinations.contains(&KeyModifiers::NONEMASK));
+    assert!(combinations.contains(&KeyModifiers::LOCKMASK));
+    assert!(combinations.contains(&KeyModifiers::MOD2MASK));
+    assert!(combinations.contains(&locks));
+    assert!(
+        KeyModifiers::lock_combinations(KeyModifiers::NONEMASK) == vec![KeyModifiers::NONEMASK]
+    );
+
+    // CapsLock, NumLock and the first mouse button are held down
+    let state = 0x100 | 0x10 | 0x02 | 0x40 | 0x01;
+    assert!(
+        KeyModifiers::from_state(state, locks) == KeyModifiers::MOD4MASK | KeyModifiers::SHIFTMASK
+    );
+    assert!(KeyModifiers::from_state(state, KeyModifiers::NONEMASK).contains(locks));
+}
+
+#[test]
+fn keys_are_grabbed_again_when_the_mapping_changes() {
+    let headless = Rc::new(HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]));
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+    let mut config = Config::initialize().unwrap();
+    config.add_key_binding(&*ws, "M-j", "focus-down").unwrap();
+    config.add_key_binding(&*ws, "M-k", "focus-up").unwrap();
+
+    let m = WindowManager::new(&*ws, &config.general);
+    headless.clear_calls();
+    m.handle_event(WindowSystemEvent::KeyboardMappingChanged, &ws, &mut config);
+
+    let keys: Vec<KeyCommand> = config.internal.key_handlers.keys().cloned().collect();
+    let calls = headless.calls();
+    assert!(calls[0] == WindowSystemCall::UngrabKeys);
+    assert!(calls[1] == WindowSystemCall::GrabKeys(keys));
+}
+
+#[test]
+fn key_release_bindings() {
+    let headless = Rc::new(HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]));
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+    let mut config = Config::initialize().unwrap();
+    config.add_key_binding(&*ws, "Super_L", "view:1").unwrap();
+    config
+        .add_key_release_binding(&*ws, "Super_L", "view:0")
+        .unwrap();
+    config
+        .add_key_release_binding(&*ws, "M-a", "view:2")
+        .unwrap();
+    let error = config
+        .add_key_release_binding(&*ws, "Super_L", "view:3")
+        .unwrap_err();
+    assert!(format!("{}", error) == "release of Super_L is already bound to view:0");
+
+    let super_l = KeyCommand::new(keysym_from_name("Super_L").unwrap(), KeyModifiers::NONEMASK);
+    let m = WindowManager::new(&*ws, &config.general);
+    let m = m.handle_event(WindowSystemEvent::KeyPressed(0, super_l), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 1);
+    let m = m.handle_event(WindowSystemEvent::KeyReleased(0, super_l), &ws, &mut config);
+    assert!(m.workspaces.current_tag() == 0);
+
+    // Keys only bound to their release are grabbed as well
+    headless.clear_calls();
+    config.grab_keys(&*ws);
+    let a = KeyCommand::new('a' as u64, config.general.mod_mask);
+    match headless.calls()[1] {
+        WindowSystemCall::GrabKeys(ref keys) => {
+            assert!(keys.len() == 2 && keys.contains(&a) && keys.contains(&super_l))
+        }
+        _ => panic!("keys weren't grabbed"),
+    }
+}
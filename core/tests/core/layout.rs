@@ -0,0 +1,162 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::config::{Config, GeneralConfig};
+use self::wtftw_core::core::stack::Stack;
+use self::wtftw_core::core::workspace::Workspace;
+use self::wtftw_core::headless_window_system::HeadlessWindowSystem;
+use self::wtftw_core::layout::{
+    AvoidStrutsLayout, BinarySpacePartition, Direction, GapLayout, Layout, LayoutCollection,
+    LayoutMessage, LayoutState, MirrorLayout, ResizableTallLayout, TallLayout,
+};
+use self::wtftw_core::window_system::{Rectangle, Window, WindowSystem};
+
+fn nested() -> Box<dyn Layout> {
+    GapLayout::new(
+        2,
+        AvoidStrutsLayout::new(
+            vec![Direction::Up],
+            MirrorLayout::new(LayoutCollection::new(vec![
+                TallLayout::new(),
+                ResizableTallLayout::new(),
+                BinarySpacePartition::new(),
+            ])),
+        ),
+    )
+}
+
+#[test]
+fn state_composes_through_wrappers() {
+    let state = nested().state();
+
+    assert!(state.kind == "Gap");
+    assert!(state.children[0].kind == "AvoidStruts");
+    assert!(state.children[0].children[0].kind == "Mirror");
+
+    let collection = &state.children[0].children[0].children[0];
+    assert!(collection.kind == "LayoutCollection");
+    assert!(collection.children.len() == 3);
+    assert!(collection.children[0].kind == "Tall");
+}
+
+#[test]
+fn state_roundtrips() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    let config = Config::initialize().unwrap();
+    let mut layout = nested();
+    for &message in [
+        LayoutMessage::IncreaseGap,
+        LayoutMessage::Next,
+        LayoutMessage::Increase,
+        LayoutMessage::IncreaseMaster,
+    ]
+    .iter()
+    {
+        layout.apply_message(message, &ws, &None, &config.general);
+    }
+
+    let state = layout.state();
+    let json = serde_json::to_string(&state).unwrap();
+    let mut restored = nested();
+    restored
+        .set_state(&serde_json::from_str::<LayoutState>(&json).unwrap())
+        .unwrap();
+
+    assert!(restored.state() == state);
+    assert!(restored.state() != nested().state());
+}
+
+#[test]
+fn set_state_rejects_other_layouts() {
+    let mut layout = BinarySpacePartition::new();
+    assert!(layout.set_state(&TallLayout::new().state()).is_err());
+
+    let mut gap = GapLayout::new(2, TallLayout::new());
+    let mut state = gap.state();
+    state.children.clear();
+    assert!(gap.set_state(&state).is_err());
+
+    let mut state = gap.state();
+    state.params = serde_json::json!({ "gap": "wide" });
+    assert!(gap.set_state(&state).is_err());
+}
+
+#[test]
+fn with_layout_state_keeps_workspace_on_error() {
+    let workspace = Workspace::new(0, "1".to_owned(), TallLayout::new(), None);
+    let mut state = workspace.layout_state();
+    state.params["ratio"] = serde_json::json!(0.75);
+
+    let changed = workspace.with_layout_state(&state).unwrap();
+    assert!(changed.layout_state().params["ratio"] == 0.75);
+    assert!(workspace.layout_state().params["ratio"] == 0.5);
+
+    state.kind = "BSP".to_owned();
+    assert!(workspace.with_layout_state(&state).is_err());
+}
+
+#[test]
+fn build_from_partial_state() {
+    let state: LayoutState = serde_json::from_str(
+        r#"{"kind": "Gap", "params": {"gap": 8}, "children": [
+            {"kind": "LayoutCollection", "children": [
+                {"kind": "Tall", "params": {"ratio": 0.6}},
+                {"kind": "Mirror", "children": [{"kind": "BSP"}]},
+                {"kind": "NoBorders", "children": [{"kind": "Full"}]}]}]}"#,
+    )
+    .unwrap();
+
+    let built = state.build().unwrap().state();
+    assert!(built.params["gap"] == 8);
+    let collection = &built.children[0];
+    assert!(collection.children.len() == 3);
+    assert!((collection.children[0].params["ratio"].as_f64().unwrap() - 0.6).abs() < 1e-6);
+    assert!(collection.children[0].params["num_master"] == 1);
+    assert!(collection.children[2].kind == "WithBorders");
+
+    let unknown: LayoutState = serde_json::from_str(r#"{"kind": "Spiral"}"#).unwrap();
+    assert!(unknown.build().is_err());
+    let missing: LayoutState = serde_json::from_str(r#"{"kind": "Mirror"}"#).unwrap();
+    assert!(missing.build().is_err());
+    let extra: LayoutState =
+        serde_json::from_str(r#"{"kind": "Full", "children": [{"kind": "Tall"}]}"#).unwrap();
+    assert!(extra.build().is_err());
+    let typo: LayoutState =
+        serde_json::from_str(r#"{"kind": "Tall", "params": {"raito": 0.6}}"#).unwrap();
+    assert!(typo.build().is_err());
+}
+
+/// A layout written outside of wtftw, which only knows about `apply_layout`
+struct Outside(Box<dyn Layout>);
+
+impl Layout for Outside {
+    fn apply_layout(
+        &mut self,
+        window_system: &dyn WindowSystem,
+        screen: Rectangle,
+        config: &GeneralConfig,
+        stack: &Option<Stack<Window>>,
+    ) -> Vec<(Window, Rectangle)> {
+        self.0.apply_layout(window_system, screen, config, stack)
+    }
+
+    fn description(&self) -> String {
+        "Outside".to_owned()
+    }
+}
+
+#[test]
+fn struts_are_avoided_inside_other_layouts() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    ws.add_window(30, Rectangle(0, 0, 800, 20));
+    ws.set_dock(30, true);
+    ws.set_strut(30, Some(vec![0, 0, 20, 0]));
+    let config = Config::initialize().unwrap();
+    let mut layout = Outside(AvoidStrutsLayout::new(
+        vec![Direction::Up],
+        TallLayout::new(),
+    ));
+
+    let stack = Some(Stack::from_element(10));
+    let windows = layout.apply_layout(&ws, Rectangle(0, 0, 800, 600), &config.general, &stack);
+    assert!(windows == vec![(10, Rectangle(0, 20, 800, 580))]);
+}
@@ -0,0 +1,67 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::logging::{parse_level, RotatingLogfile, KEPT_LOGS, MAX_LOG_SIZE};
+use log::LevelFilter;
+use std::env::temp_dir;
+use std::fs::{create_dir_all, metadata, read_to_string, remove_dir_all, remove_file, write};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn numbered(path: &Path, i: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), i))
+}
+
+#[test]
+fn logfiles_are_rotated() {
+    let path = temp_dir().join(format!("wtftw-{}.log", std::process::id()));
+    let mut logfile = RotatingLogfile::open(&path).unwrap();
+
+    let line = vec![b'x'; MAX_LOG_SIZE as usize / 2];
+    for _ in 0..(KEPT_LOGS + 1) * 2 {
+        logfile.write_all(&line).unwrap();
+    }
+    logfile.write_all(b"last line\n").unwrap();
+
+    assert!(read_to_string(&path).unwrap() == "last line\n");
+    for i in 1..=KEPT_LOGS {
+        assert!(metadata(numbered(&path, i)).unwrap().len() == MAX_LOG_SIZE);
+    }
+    assert!(metadata(numbered(&path, KEPT_LOGS + 1)).is_err());
+
+    let _ = remove_file(&path);
+    for i in 1..=KEPT_LOGS {
+        let _ = remove_file(numbered(&path, i));
+    }
+}
+
+#[test]
+fn logging_goes_on_when_rotating_fails() {
+    let path = temp_dir().join(format!("wtftw-full-{}.log", std::process::id()));
+    // Directories with something in them can't be replaced by a file
+    for i in 1..=KEPT_LOGS {
+        create_dir_all(numbered(&path, i)).unwrap();
+        write(numbered(&path, i).join("taken"), "").unwrap();
+    }
+    let mut logfile = RotatingLogfile::open(&path).unwrap();
+
+    let line = vec![b'x'; MAX_LOG_SIZE as usize];
+    logfile.write_all(&line).unwrap();
+    logfile.write_all(b"last line\n").unwrap();
+    logfile.write_all(b"last line\n").unwrap();
+
+    assert!(read_to_string(&path)
+        .unwrap()
+        .ends_with("last line\nlast line\n"));
+
+    let _ = remove_file(&path);
+    for i in 1..=KEPT_LOGS {
+        let _ = remove_dir_all(numbered(&path, i));
+    }
+}
+
+#[test]
+fn log_levels_are_parsed() {
+    assert!(parse_level("debug").unwrap() == LevelFilter::Debug);
+    assert!(parse_level("WARN").unwrap() == LevelFilter::Warn);
+    assert!(parse_level("loud").is_err());
+}
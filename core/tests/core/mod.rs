@@ -1 +1,31 @@
+pub mod actions;
+pub mod config_file;
+pub mod event_loop;
+pub mod ipc;
+pub mod keys;
+pub mod layout;
+pub mod logging;
+pub mod rules;
+pub mod session;
 pub mod stack;
+pub mod trace;
+pub mod window_manager;
+
+use std::rc::Rc;
+use wtftw_core::config::Config;
+use wtftw_core::headless_window_system::HeadlessWindowSystem;
+use wtftw_core::window_system::{Rectangle, WindowSystem};
+
+/// An 800x600 screen with the windows 10 and 11, and the default config
+pub fn headless_setup() -> (HeadlessWindowSystem, Config) {
+    let window_system = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    window_system.add_window(10, Rectangle(0, 0, 100, 100));
+    window_system.add_window(11, Rectangle(0, 0, 100, 100));
+    (window_system, Config::initialize().unwrap())
+}
+
+/// The same as `headless_setup`, for tests that only need a window system
+pub fn setup() -> (Rc<dyn WindowSystem>, Config) {
+    let (window_system, config) = headless_setup();
+    (Rc::new(window_system), config)
+}
@@ -0,0 +1,79 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::config::Config;
+use self::wtftw_core::core::rational_rect::RationalRect;
+use self::wtftw_core::headless_window_system::HeadlessWindowSystem;
+use self::wtftw_core::rules::{rules_manage_hook, Condition, Pattern, Rule, RuleAction};
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::{Rectangle, WindowSystem, WindowSystemEvent, WindowType};
+use std::rc::Rc;
+
+#[test]
+fn patterns_match() {
+    assert!(Pattern::Exact("Gimp".to_owned()).matches("Gimp"));
+    assert!(!Pattern::Exact("Gimp".to_owned()).matches("Gimp-2.10"));
+    assert!(Pattern::Contains("fox".to_owned()).matches("Firefox"));
+    assert!(Pattern::regex("^term-[0-9]+$").unwrap().matches("term-42"));
+    assert!(!Pattern::regex("^term-[0-9]+$").unwrap().matches("term-x"));
+    assert!(Pattern::regex("(unclosed").is_err());
+}
+
+#[test]
+fn rules_are_applied_in_order() {
+    let ws = HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]);
+    ws.add_window(10, Rectangle(0, 0, 100, 100));
+    ws.add_window(11, Rectangle(0, 0, 200, 100));
+    ws.set_window_name(11, "Picture-in-Picture");
+    ws.add_window(12, Rectangle(0, 0, 400, 300));
+    ws.set_window_type(12, WindowType::Splash);
+    ws.add_window(13, Rectangle(0, 0, 800, 20));
+    ws.set_class_name(13, "panel");
+    let headless = Rc::new(ws);
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+
+    let mut config = Config::initialize().unwrap();
+    let tag = config.general.tags[2].clone();
+    config.set_manage_hook(rules_manage_hook(vec![
+        Rule::new(
+            vec![Condition::Title(
+                Pattern::regex("^Picture.in.Picture$").unwrap(),
+            )],
+            vec![
+                RuleAction::FloatAt(RationalRect(0.5, 0.5, 0.25, 0.25)),
+                RuleAction::SkipFocus,
+                RuleAction::BorderWidth(0),
+            ],
+        ),
+        Rule::new(
+            vec![Condition::Type(WindowType::Splash)],
+            vec![RuleAction::Center, RuleAction::ShiftToTag(tag)],
+        ),
+        Rule::new(
+            vec![Condition::Class(Pattern::Contains("pan".to_owned()))],
+            vec![RuleAction::Ignore, RuleAction::Fullscreen],
+        ),
+    ]));
+
+    let m = [10, 11, 12, 13]
+        .iter()
+        .fold(WindowManager::new(&*ws, &config.general), |m, &window| {
+            m.handle_event(WindowSystemEvent::WindowCreated(window), &ws, &mut config)
+        });
+
+    // Floated where the rule says, without taking the focus
+    assert!(m.workspaces.peek() == Some(10));
+    assert!(ws.get_geometry(11) == Rectangle(400, 300, 200, 150));
+    assert!(headless.window(11).unwrap().border_width == 0);
+    let moved = m.workspaces.shift_window(1, 11);
+    assert!(moved.border_width(11, &config.general) == 0);
+
+    // Shifted first, then floated in the middle with its own size
+    assert!(m.workspaces.find_tag(12) == Some(2));
+    let RationalRect(x, y, w, h) = m.workspaces.floating[&12];
+    assert!(x == 0.25 && y == 0.25 && w == 0.5 && h == 0.5);
+
+    // Ignored windows stay mapped, and later actions don't apply
+    assert!(!m.is_window_managed(13));
+    assert!(headless.is_mapped(13));
+    assert!(ws.get_geometry(13) == Rectangle(0, 0, 800, 20));
+}
@@ -0,0 +1,117 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::config::Config;
+use self::wtftw_core::headless_window_system::HeadlessWindowSystem;
+use self::wtftw_core::layout::{
+    BinarySpacePartition, GapLayout, LayoutCollection, LayoutMessage, TallLayout,
+};
+use self::wtftw_core::session::{Session, SESSION_VERSION};
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::Rectangle;
+use super::headless_setup;
+use std::env::temp_dir;
+use std::fs::{remove_file, write};
+use std::path::PathBuf;
+
+/// Four windows, and layouts with some state to save
+fn setup() -> (HeadlessWindowSystem, Config) {
+    let (window_system, mut config) = headless_setup();
+    for window in 12..14 {
+        window_system.add_window(window, Rectangle(0, 0, 100, 100));
+    }
+    config.general.layout = GapLayout::new(
+        4,
+        LayoutCollection::new(vec![TallLayout::new(), BinarySpacePartition::new()]),
+    );
+    (window_system, config)
+}
+
+fn session_path(name: &str) -> PathBuf {
+    temp_dir().join(format!("wtftw-{}-{}.json", name, std::process::id()))
+}
+
+#[test]
+fn restore_brings_back_workspaces_and_layouts() {
+    let (ws, config) = setup();
+    let c = &config.general;
+    let m = WindowManager::new(&ws, c)
+        .manage(&ws, 10, c)
+        .manage(&ws, 11, c)
+        .send_layout_message(LayoutMessage::IncreaseGap, &ws, c)
+        .send_layout_message(LayoutMessage::Next, &ws, c)
+        .float(&ws, c, 11)
+        .view(&ws, 2, c)
+        .manage(&ws, 12, c);
+
+    let path = session_path("restore");
+    Session::from_workspaces(&m.workspaces).save(&path).unwrap();
+    let restored = Session::load(&path)
+        .unwrap()
+        .restore(&WindowManager::new(&ws, c), &ws, c)
+        .unwrap();
+    remove_file(&path).unwrap();
+
+    assert!(restored.workspaces.current_tag() == 2);
+    assert!(restored.workspaces.peek() == Some(12));
+    assert!(
+        restored.workspaces.all_windows_with_workspaces()
+            == m.workspaces.all_windows_with_workspaces()
+    );
+    assert!(restored.workspaces.floating.contains_key(&11));
+
+    let layout = |w: &WindowManager| {
+        w.workspaces
+            .workspaces()
+            .into_iter()
+            .find(|x| x.id == 0)
+            .unwrap()
+            .layout_state()
+    };
+    assert!(layout(&restored) == layout(&m));
+    assert!(layout(&restored).params["gap"] == 5);
+    assert!(layout(&restored).children[0].params["current"] == 1);
+}
+
+#[test]
+fn restore_drops_vanished_windows() {
+    let (ws, config) = setup();
+    let c = &config.general;
+    let m = WindowManager::new(&ws, c)
+        .manage(&ws, 10, c)
+        .manage(&ws, 11, c);
+    let session = Session::from_workspaces(&m.workspaces);
+
+    ws.remove_window(11);
+    let restored = session
+        .restore(&WindowManager::new(&ws, c), &ws, c)
+        .unwrap();
+
+    assert!(restored.is_window_managed(10));
+    assert!(!restored.is_window_managed(11));
+}
+
+#[test]
+fn load_rejects_other_versions() {
+    let path = session_path("version");
+    write(
+        &path,
+        format!("{{\"version\": {}}}", SESSION_VERSION + 1).as_bytes(),
+    )
+    .unwrap();
+    let result = Session::load(&path);
+    remove_file(&path).unwrap();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn restore_rejects_changed_workspaces() {
+    let (ws, mut config) = setup();
+    let m = WindowManager::new(&ws, &config.general).manage(&ws, 10, &config.general);
+    let session = Session::from_workspaces(&m.workspaces);
+
+    config.general.tags.pop();
+    let fresh = WindowManager::new(&ws, &config.general);
+
+    assert!(session.restore(&fresh, &ws, &config.general).is_err());
+}
@@ -0,0 +1,158 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::config::Config;
+use self::wtftw_core::headless_window_system::HeadlessWindowSystem;
+use self::wtftw_core::ipc::IpcCommand;
+use self::wtftw_core::layout::LayoutMessage;
+use self::wtftw_core::trace::{EventRecorder, Trace};
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::{
+    KeyCommand, KeyModifiers, Rectangle, WindowSystem, WindowSystemEvent, WindowType,
+};
+use super::headless_setup;
+use std::env::temp_dir;
+use std::fs::remove_file;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+fn trace_path(name: &str) -> PathBuf {
+    temp_dir().join(format!("wtftw-{}-{}.trace", name, std::process::id()))
+}
+
+#[test]
+fn replay_reproduces_recorded_session() {
+    let (headless, mut config) = headless_setup();
+    let headless = Rc::new(headless);
+    headless.add_window(12, Rectangle(0, 0, 200, 100));
+    headless.set_window_type(12, WindowType::Dialog);
+    headless.set_transient_for(12, Some(10));
+    headless.add_window(13, Rectangle(0, 0, 800, 20));
+    headless.set_window_type(13, WindowType::Dock);
+    headless.set_partial_strut(13, Some(vec![0, 0, 20, 0, 0, 0, 0, 0, 0, 800, 0, 0]));
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+
+    let mut m = WindowManager::new(&*ws, &config.general);
+    let focus_down = KeyCommand::new(ws.get_keycode_from_string("j"), KeyModifiers::MOD1MASK);
+    config.add_key_handler(focus_down.key, focus_down.mask, "focus-down");
+    let path = trace_path("replay");
+    let mut recorder = EventRecorder::create(&path, &m, &*ws, &config).unwrap();
+    let events = vec![
+        WindowSystemEvent::WindowCreated(13),
+        WindowSystemEvent::WindowCreated(10),
+        WindowSystemEvent::WindowCreated(11),
+        WindowSystemEvent::WindowCreated(12),
+        WindowSystemEvent::KeyPressed(ws.get_root(), focus_down),
+        WindowSystemEvent::WindowDestroyed(11),
+    ];
+    for event in events {
+        recorder.record(&event, &*ws).unwrap();
+        m = m.handle_event(event, &ws, &mut config);
+    }
+
+    let trace = Trace::load(&path).unwrap();
+    remove_file(&path).unwrap();
+    let mut replay_config = Config::initialize().unwrap();
+    replay_config.add_key_handler(focus_down.key, focus_down.mask, "focus-down");
+    let (replayed, replayed_ws) = trace.replay(&mut replay_config).unwrap();
+
+    assert!(
+        replayed.workspaces.all_windows_with_workspaces()
+            == m.workspaces.all_windows_with_workspaces()
+    );
+    assert!(replayed.workspaces.peek() == m.workspaces.peek());
+    assert!(replayed.docks == m.docks);
+    // The dialog only floats if its type and parent were recorded
+    assert!(replayed.workspaces.floating.contains_key(&12));
+    assert!(replayed_ws.get_geometry(10) == headless.get_geometry(10));
+    assert!(replayed_ws.get_geometry(12) == headless.get_geometry(12));
+}
+
+#[test]
+fn load_rejects_other_versions() {
+    let path = trace_path("version");
+    std::fs::write(&path, "{\"version\":1,\"screens\":[]}\n").unwrap();
+    let result = Trace::load(&path);
+    remove_file(&path).unwrap();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn replay_starts_from_the_recorded_state() {
+    let (headless, mut config) = headless_setup();
+    let headless = Rc::new(headless);
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+
+    config.add_key_binding(&*ws, "M-x t", "terminal").unwrap();
+    let c = &config.general;
+    let m = WindowManager::new(&*ws, c)
+        .manage(&*ws, 10, c)
+        .view(&*ws, 2, c)
+        .manage(&*ws, 11, c)
+        .send_layout_message(LayoutMessage::IncreaseMaster, &*ws, c);
+    let path = trace_path("state");
+    let mut recorder = EventRecorder::create(&path, &m, &*ws, &config).unwrap();
+
+    // Everything the main loop handles besides events
+    let command = IpcCommand::Shift { workspace: 3 };
+    recorder.record_command(&command).unwrap();
+    let (m, _) = command.execute(&m, &ws, &config).unwrap();
+    let chord = KeyCommand::new(ws.get_keycode_from_string("x"), KeyModifiers::MOD1MASK);
+    let event = WindowSystemEvent::KeyPressed(ws.get_root(), chord);
+    recorder.record(&event, &*ws).unwrap();
+    let m = m.handle_event(event, &ws, &mut config);
+    assert!(!m.submaps.is_empty());
+    let names = vec!["submap-timeout".to_owned()];
+    recorder.record_timers(&names).unwrap();
+    let m = m.run_named_timers(&names, &ws, &mut config);
+
+    let trace = Trace::load(&path).unwrap();
+    remove_file(&path).unwrap();
+    let mut replay_config = Config::initialize().unwrap();
+    replay_config
+        .add_key_binding(&*ws, "M-x t", "terminal")
+        .unwrap();
+    let (replayed, _) = trace.replay(&mut replay_config).unwrap();
+
+    assert!(
+        replayed.workspaces.all_windows_with_workspaces()
+            == m.workspaces.all_windows_with_workspaces()
+    );
+    assert!(replayed.workspaces.current_tag() == 2);
+    assert!(replayed.workspaces.find_tag(11) == Some(3));
+    assert!(
+        replayed.workspaces.workspaces()[2].layout_state()
+            == m.workspaces.workspaces()[2].layout_state()
+    );
+    assert!(replayed.submaps.is_empty() && m.submaps.is_empty());
+}
+
+#[test]
+fn replay_fails_when_it_cant_reproduce_the_session() {
+    let headless = Rc::new(HeadlessWindowSystem::new(vec![Rectangle(0, 0, 800, 600)]));
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+    let mut config = Config::initialize().unwrap();
+    let m = WindowManager::new(&*ws, &config.general);
+
+    // A binding the replay doesn't have
+    let path = trace_path("config");
+    let key = ws.get_keycode_from_string("j");
+    config.add_key_handler(key, KeyModifiers::MOD1MASK, "focus-down");
+    EventRecorder::create(&path, &m, &*ws, &config).unwrap();
+    let trace = Trace::load(&path).unwrap();
+    assert!(trace.replay(&mut Config::initialize().unwrap()).is_err());
+    assert!(trace.replay(&mut config).is_ok());
+
+    // Input of a file descriptor
+    let mut recorder = EventRecorder::create(&path, &m, &*ws, &config).unwrap();
+    recorder.record_readable(42).unwrap();
+    assert!(Trace::load(&path).unwrap().replay(&mut config).is_err());
+
+    // Scheduled before the recording, by something the trace doesn't know
+    let m = m.schedule("later", Duration::from_secs(60), Box::new(|m, _, _| m));
+    let mut recorder = EventRecorder::create(&path, &m, &*ws, &config).unwrap();
+    recorder.record_timers(&["later".to_owned()]).unwrap();
+    assert!(Trace::load(&path).unwrap().replay(&mut config).is_err());
+    remove_file(&path).unwrap();
+}
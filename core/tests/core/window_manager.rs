@@ -0,0 +1,235 @@
+extern crate wtftw_core;
+
+use self::wtftw_core::headless_window_system::WindowSystemCall;
+use self::wtftw_core::layout::{AvoidStrutsLayout, Direction, TallLayout};
+use self::wtftw_core::window_manager::WindowManager;
+use self::wtftw_core::window_system::{
+    KeyModifiers, MouseCommand, Rectangle, WindowSystem, WindowSystemEvent, WindowType, BUTTON2,
+};
+use super::headless_setup;
+use std::rc::Rc;
+
+#[test]
+fn manage_single_window_fills_screen() {
+    let (ws, config) = headless_setup();
+    let m = WindowManager::new(&ws, &config.general).manage(&ws, 10, &config.general);
+
+    assert!(m.is_window_managed(10));
+    assert!(ws.is_mapped(10));
+    assert!(ws.get_geometry(10) == Rectangle(0, 0, 796, 596));
+    assert!(ws.get_focused_window() == 10);
+}
+
+#[test]
+fn manage_tiles_new_window_as_master() {
+    let (ws, config) = headless_setup();
+    let m = WindowManager::new(&ws, &config.general)
+        .manage(&ws, 10, &config.general)
+        .manage(&ws, 11, &config.general);
+
+    assert!(m.workspaces.peek() == Some(11));
+    assert!(ws.get_geometry(11) == Rectangle(0, 0, 396, 596));
+    assert!(ws.get_geometry(10) == Rectangle(400, 0, 396, 596));
+}
+
+#[test]
+fn view_hides_windows_of_previous_workspace() {
+    let (ws, config) = headless_setup();
+    let m = WindowManager::new(&ws, &config.general).manage(&ws, 10, &config.general);
+    ws.clear_calls();
+
+    let m = m.view(&ws, 1, &config.general);
+
+    assert!(m.workspaces.current_tag() == 1);
+    assert!(!ws.is_mapped(10));
+    assert!(ws.calls().contains(&WindowSystemCall::Hide(10)));
+    assert!(ws.get_focused_window() == ws.get_root());
+}
+
+#[test]
+fn unmanage_gives_space_back() {
+    let (ws, config) = headless_setup();
+    let m = WindowManager::new(&ws, &config.general)
+        .manage(&ws, 10, &config.general)
+        .manage(&ws, 11, &config.general)
+        .unmanage(&ws, 11, &config.general);
+
+    assert!(!m.is_window_managed(11));
+    assert!(ws.get_geometry(10) == Rectangle(0, 0, 796, 596));
+}
+
+#[test]
+fn transients_float_over_their_parent() {
+    let (ws, config) = headless_setup();
+    ws.add_window(20, Rectangle(0, 0, 196, 96));
+    ws.set_transient_for(20, Some(10));
+    let m = WindowManager::new(&ws, &config.general)
+        .manage(&ws, 10, &config.general)
+        .manage(&ws, 11, &config.general)
+        .manage(&ws, 20, &config.general);
+
+    assert!(m.workspaces.floating.contains_key(&20));
+    assert!(m.workspaces.peek() == Some(20));
+    let Rectangle(x, y, _, _) = ws.get_geometry(20);
+    assert!(x == 500 && (249..=250).contains(&y));
+
+    // Closing the dialog focuses the parent, not the window below it
+    let m = m.unmanage(&ws, 20, &config.general);
+    assert!(m.workspaces.peek() == Some(10));
+    assert!(m.workspaces.transients.is_empty());
+}
+
+#[test]
+fn transients_go_to_the_workspace_of_their_parent() {
+    let (ws, config) = headless_setup();
+    ws.add_window(20, Rectangle(0, 0, 196, 96));
+    ws.set_transient_for(20, Some(10));
+    let m = WindowManager::new(&ws, &config.general)
+        .manage(&ws, 10, &config.general)
+        .view(&ws, 1, &config.general)
+        .manage(&ws, 11, &config.general)
+        .manage(&ws, 20, &config.general);
+
+    assert!(m.workspaces.current_tag() == 1);
+    assert!(m.workspaces.find_tag(20) == Some(0));
+    assert!(m.workspaces.peek() == Some(11));
+    assert!(!ws.is_mapped(20));
+
+    // Without a managed parent, the dialog still floats, but stays here
+    ws.add_window(21, Rectangle(0, 0, 196, 96));
+    ws.set_transient_for(21, Some(99));
+    let m = m.manage(&ws, 21, &config.general);
+    assert!(m.workspaces.find_tag(21) == Some(1));
+    assert!(m.workspaces.floating.contains_key(&21));
+    assert!(!m.workspaces.transients.contains_key(&21));
+}
+
+#[test]
+fn window_types_decide_the_placement() {
+    let (ws, config) = headless_setup();
+    ws.set_window_type(11, WindowType::Splash);
+    ws.add_window(12, Rectangle(600, 0, 200, 50));
+    ws.set_window_type(12, WindowType::Notification);
+    ws.add_window(13, Rectangle(0, 0, 800, 600));
+    ws.set_window_type(13, WindowType::Desktop);
+    let m = [10, 11, 12, 13]
+        .iter()
+        .fold(WindowManager::new(&ws, &config.general), |m, &w| {
+            m.manage(&ws, w, &config.general)
+        });
+
+    // The splash screen floats without changing the layout
+    assert!(m.workspaces.floating.contains_key(&11));
+    assert!(ws.get_geometry(10) == Rectangle(0, 0, 796, 596));
+
+    for &window in [12, 13].iter() {
+        assert!(!m.is_window_managed(window));
+        assert!(ws.is_mapped(window));
+    }
+    assert!(ws.get_geometry(12) == Rectangle(600, 0, 200, 50));
+    assert!(ws.calls().contains(&WindowSystemCall::Lower(13)));
+    assert!(!ws.calls().contains(&WindowSystemCall::Lower(12)));
+}
+
+#[test]
+fn docks_are_shown_but_not_tiled() {
+    let (ws, mut config) = headless_setup();
+    config.general.layout = AvoidStrutsLayout::new(vec![Direction::Up], TallLayout::new());
+    let headless = Rc::new(ws);
+    let ws: Rc<dyn WindowSystem> = headless.clone();
+    let m = WindowManager::new(&*ws, &config.general).handle_event(
+        WindowSystemEvent::WindowCreated(10),
+        &ws,
+        &mut config,
+    );
+
+    headless.add_window(30, Rectangle(0, 0, 800, 20));
+    headless.set_dock(30, true);
+    headless.set_strut(30, Some(vec![0, 0, 20, 0]));
+    let m = m.handle_event(WindowSystemEvent::WindowCreated(30), &ws, &mut config);
+
+    assert!(!m.is_window_managed(30));
+    assert!(m.is_dock(30));
+    assert!(headless.is_mapped(30));
+    assert!(ws.get_geometry(30) == Rectangle(0, 0, 800, 20));
+    assert!(ws.get_geometry(10) == Rectangle(0, 20, 796, 576));
+    assert!(headless
+        .calls()
+        .contains(&WindowSystemCall::WatchStruts(30)));
+
+    // Struts are cached, so a dock that grows needs to tell
+    headless.set_strut(30, Some(vec![0, 0, 30, 0]));
+    let m = m.handle_event(WindowSystemEvent::StrutsChanged(30), &ws, &mut config);
+    assert!(ws.get_geometry(10) == Rectangle(0, 30, 796, 566));
+
+    // Its geometry, too, e.g. when it moves off the screen
+    ws.move_window(30, 0, 600);
+    let m = m.handle_event(
+        WindowSystemEvent::ConfigurationNotification(30),
+        &ws,
+        &mut config,
+    );
+    assert!(ws.get_geometry(10) == Rectangle(0, 0, 796, 596));
+    ws.move_window(30, 0, 0);
+    let m = m.handle_event(
+        WindowSystemEvent::ConfigurationNotification(30),
+        &ws,
+        &mut config,
+    );
+    assert!(ws.get_geometry(10) == Rectangle(0, 30, 796, 566));
+
+    headless.remove_window(30);
+    let m = m.handle_event(WindowSystemEvent::WindowDestroyed(30), &ws, &mut config);
+
+    assert!(!m.is_dock(30));
+    assert!(ws.get_geometry(10) == Rectangle(0, 0, 796, 596));
+}
+
+#[test]
+fn handle_event_manages_and_unmanages_windows() {
+    let (ws, mut config) = headless_setup();
+    let ws: Rc<dyn WindowSystem> = Rc::new(ws);
+    let m = WindowManager::new(&*ws, &config.general)
+        .handle_event(WindowSystemEvent::WindowCreated(10), &ws, &mut config)
+        .handle_event(WindowSystemEvent::WindowCreated(11), &ws, &mut config);
+
+    assert!(m.is_window_managed(10));
+    assert!(m.is_window_managed(11));
+    assert!(ws.get_geometry(10) == Rectangle(400, 0, 396, 596));
+
+    let m = m.handle_event(WindowSystemEvent::WindowDestroyed(11), &ws, &mut config);
+
+    assert!(!m.is_window_managed(11));
+    assert!(ws.get_geometry(10) == Rectangle(0, 0, 796, 596));
+}
+
+#[test]
+fn button_release_handlers_get_the_window() {
+    let (ws, mut config) = headless_setup();
+    let ws: Rc<dyn WindowSystem> = Rc::new(ws);
+    config.add_mouse_release_handler(
+        BUTTON2,
+        KeyModifiers::MOD1MASK,
+        Box::new(|m, w, c, window| m.move_window_to_workspace(&*w, c, 2).focus(window, &*w, c)),
+    );
+    config.add_mouse_release_handler(
+        BUTTON2,
+        KeyModifiers::NONEMASK,
+        Box::new(|m, w, c, window| m.focus(window, &*w, c).move_window_to_workspace(&*w, c, 3)),
+    );
+    assert!(config.mouse_bindings().len() == 2);
+
+    let m = WindowManager::new(&*ws, &config.general)
+        .handle_event(WindowSystemEvent::WindowCreated(10), &ws, &mut config)
+        .handle_event(WindowSystemEvent::WindowCreated(11), &ws, &mut config);
+    let release = |window, subwindow, mask| {
+        WindowSystemEvent::ButtonReleased(window, subwindow, MouseCommand::new(BUTTON2, mask), 0, 0)
+    };
+
+    // Grabbed buttons are released on the root window
+    let root = ws.get_root();
+    let m = m.handle_event(release(root, 10, KeyModifiers::NONEMASK), &ws, &mut config);
+    assert!(m.workspaces.find_tag(10) == Some(3));
+    let m = m.handle_event(release(11, 0, KeyModifiers::CONTROLMASK), &ws, &mut config);
+    assert!(m.workspaces.find_tag(11) == Some(0));
+}
@@ -1,12 +1,21 @@
 #[macro_use]
 extern crate log;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use getopts::Options;
 use std::env;
+use std::fs::remove_file;
 use std::ops::Deref;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::rc::Rc;
 use wtftw_core::config::Config;
+use wtftw_core::event_loop::{EventLoop, Wakeup};
+use wtftw_core::headless_window_system::HeadlessWindowSystem;
+use wtftw_core::ipc::{socket_path, IpcServer};
+use wtftw_core::logging::RotatingLogfile;
+use wtftw_core::session::{session_file, Session};
+use wtftw_core::trace::{EventRecorder, Trace};
+use wtftw_core::util::show_message;
 use wtftw_core::window_manager::WindowManager;
 use wtftw_core::window_system::*;
 use wtftw_xlib::XlibWindowSystem;
@@ -18,18 +27,148 @@ pub fn parse_window_ids(ids: &str) -> Vec<(Window, u32)> {
     }
 }
 
-fn init_terminal_logger(verbose_mode_enabled: bool) {
-    let level = if verbose_mode_enabled {
+/// Log to the terminal and the logfile. The level is set
+/// globally, so it can be changed once the config is loaded.
+fn init_logger(logfile: Option<RotatingLogfile>, verbose_mode_enabled: bool) {
+    let mut loggers: Vec<Box<dyn simplelog::SharedLogger>> = vec![simplelog::TermLogger::new(
+        simplelog::LevelFilter::Trace,
+        simplelog::Config::default(),
+        simplelog::TerminalMode::Mixed,
+    )];
+    if let Some(logfile) = logfile {
+        loggers.push(simplelog::WriteLogger::new(
+            simplelog::LevelFilter::Trace,
+            simplelog::Config::default(),
+            logfile,
+        ));
+    }
+    simplelog::CombinedLogger::init(loggers).unwrap();
+    log::set_max_level(if verbose_mode_enabled {
         simplelog::LevelFilter::Debug
     } else {
         simplelog::LevelFilter::Warn
-    };
-    simplelog::CombinedLogger::init(vec![simplelog::TermLogger::new(
-        level,
-        simplelog::Config::default(),
-        simplelog::TerminalMode::Mixed,
-    )])
-    .unwrap();
+    });
+}
+
+/// Use the level from the config, unless running verbosely, and
+/// move the logfile to where the config wants it
+fn apply_log_config(logfile: &Option<RotatingLogfile>, config: &Config, verbose: bool) {
+    if let Some(logfile) = logfile {
+        if let Err(e) = logfile.set_path(&config.general.logfile) {
+            error!("{:#}", e);
+        }
+    }
+    log::set_max_level(if verbose {
+        simplelog::LevelFilter::Debug
+    } else {
+        config.general.log_level
+    });
+}
+
+/// Restore the session saved by a restart. If there is no usable
+/// session, fall back to managing the given windows again.
+fn resume(
+    mut window_manager: WindowManager,
+    window_system: &Rc<dyn WindowSystem>,
+    config: &Config,
+    window_ids: Vec<(Window, u32)>,
+) -> WindowManager {
+    let path = session_file();
+    let session = Session::load(&path)
+        .and_then(|s| s.restore(&window_manager, window_system.deref(), &config.general));
+    // A session is only good for a single restart
+    let _ = remove_file(&path);
+
+    match session {
+        Ok(restored) => {
+            debug!("restored session from {}", path.display());
+            return restored;
+        }
+        Err(e) => info!(
+            "unable to restore session, falling back to window list: {}",
+            e
+        ),
+    }
+
+    for (window, workspace) in window_ids {
+        debug!("re-inserting window {}", window);
+        window_manager = window_manager
+            .view(window_system.deref(), workspace, &config.general)
+            .manage_with(window_system.deref(), window, &config.general, &|x| {
+                (config.internal.manage_hook)(x.clone(), window_system.clone(), window)
+            });
+    }
+    window_manager
+}
+
+/// Replay a trace recorded with --record against a headless window system,
+/// using the same dispatch logic as the real event loop. The config is
+/// loaded like the window manager does, so it has to be the recorded one.
+fn replay(path: &str) -> Result<()> {
+    let trace = Trace::load(path)?;
+    let mut config = Config::for_replay(trace.screens.clone())?;
+    let (window_manager, _) = trace.replay(&mut config)?;
+
+    println!(
+        "{}",
+        serde_json::to_string(&window_manager.workspaces.all_windows_with_workspaces())?
+    );
+    Ok(())
+}
+
+/// Write to the trace, if there is one. A trace that can't be written
+/// anymore is given up on, the window manager keeps running without it.
+fn record<F: FnOnce(&mut EventRecorder) -> Result<()>>(recorder: &mut Option<EventRecorder>, f: F) {
+    if let Some(Err(e)) = recorder.as_mut().map(f) {
+        error!("stopped recording the trace: {:#}", e);
+        *recorder = None;
+    }
+}
+
+/// Wait on the ipc clients that are still sending their command, and on
+/// the ones that haven't taken all of their output yet. `watched` holds
+/// the clients watched so far, they are replaced.
+fn watch_clients(event_loop: &mut EventLoop, server: &IpcServer, watched: &mut Vec<RawFd>) {
+    for fd in watched.drain(..) {
+        event_loop.unwatch(fd);
+    }
+    for fd in server.client_fds() {
+        event_loop.watch(fd);
+        watched.push(fd);
+    }
+    for fd in server.output_fds() {
+        event_loop.watch_writable(fd);
+        watched.push(fd);
+    }
+}
+
+/// Build the config against a headless window system and report
+/// everything that would go wrong with it at login
+fn check_config(config: &mut Config, screens: usize) -> Result<()> {
+    if screens == 0 {
+        return Err(anyhow!("at least one screen is needed"));
+    }
+    let screens = vec![Rectangle(0, 0, 1920, 1080); screens];
+    let window_system: Rc<dyn WindowSystem> = Rc::new(HeadlessWindowSystem::new(screens.clone()));
+    // The config module gets a window manager, but its tags might not fit the screens yet
+    let mut general = config.general.clone();
+    general.tags = vec![String::new(); screens.len()];
+    let window_manager = WindowManager::new(window_system.deref(), &general);
+
+    let mut problems = Vec::new();
+    if let Err(e) = config.load(&window_manager, window_system.deref()) {
+        problems.push(format!("{:#}", e));
+    }
+    problems.extend(config.check(screens));
+
+    if problems.is_empty() {
+        println!("no problems found");
+        return Ok(());
+    }
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    Err(anyhow!("found {} problem(s) in the config", problems.len()))
 }
 
 fn main() -> Result<()> {
@@ -44,16 +183,77 @@ fn main() -> Result<()> {
         "WINDOW",
     );
     options.optflag("v", "verbose", "be verbose");
+    options.optflag(
+        "",
+        "update-config",
+        "update the dependencies of the config module before compiling it",
+    );
+    options.optflag(
+        "",
+        "release-config",
+        "compile the config module in release mode",
+    );
+    options.optopt(
+        "",
+        "record",
+        "record all window system events into a trace file",
+        "FILE",
+    );
+    options.optflag(
+        "",
+        "check-config",
+        "build the config and report its problems, without starting",
+    );
+    options.optopt(
+        "",
+        "screens",
+        "the number of screens to check the config for, 1 by default",
+        "COUNT",
+    );
+    options.optopt(
+        "",
+        "replay",
+        "replay a recorded trace against a headless window system and print the final state",
+        "FILE",
+    );
 
     let matches = match options.parse(args.into_iter().skip(1).collect::<Vec<_>>()) {
         Ok(m) => m,
         Err(f) => panic!(f.to_string()),
     };
 
-    init_terminal_logger(matches.opt_present("v"));
-
     // Create a default config.generaluration
     let mut config = Config::initialize()?;
+
+    // Checks and replays only report to the terminal
+    let verbose = matches.opt_present("v");
+    let logfile = if matches.opt_present("check-config") || matches.opt_present("replay") {
+        None
+    } else {
+        match RotatingLogfile::open(&config.general.logfile) {
+            Ok(logfile) => Some(logfile),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                None
+            }
+        }
+    };
+    init_logger(logfile.clone(), verbose);
+    config.internal.update_dependencies = matches.opt_present("update-config");
+    config.internal.release_build = matches.opt_present("release-config");
+
+    if matches.opt_present("check-config") {
+        let screens = match matches.opt_str("screens") {
+            Some(count) => count.parse()?,
+            None => 1,
+        };
+        return check_config(&mut config, screens);
+    }
+
+    if let Some(path) = matches.opt_str("replay") {
+        return replay(&path);
+    }
+
     // Initialize window system. Use xlib here for now
     debug!("initialize window system");
     let xlib = XlibWindowSystem::new();
@@ -65,6 +265,7 @@ fn main() -> Result<()> {
     // If available, compile the config.general file at ~/.wtftw/config.general.rs
     // and call the config.generalure method
     config.compile_and_call(&mut window_manager, window_system.deref())?;
+    apply_log_config(&logfile, &config, verbose);
     window_manager = WindowManager::new(window_system.deref(), &config.general);
 
     // Output some initial information
@@ -84,175 +285,123 @@ fn main() -> Result<()> {
         config.internal.key_handlers.len()
     );
 
-    for (command, _) in config.internal.key_handlers.iter() {
-        window_system.grab_keys(vec![*command]);
-    }
+    window_system.set_lock_mask(config.general.lock_mask);
+    config.grab_keys(window_system.deref());
+    config.grab_buttons(window_system.deref());
 
-    for (&command, _) in config.internal.mouse_handlers.iter() {
-        window_system.grab_button(command);
-    }
-
-    let window_ids = if matches.opt_present("r") {
+    if let Some(window_ids) = matches.opt_str("r") {
         debug!("trying to manage pre-existing windows");
-        debug!("found {}", matches.opt_str("r").unwrap());
-        parse_window_ids(&matches.opt_str("r").unwrap())
-    } else {
-        Vec::new()
-    };
-
-    for (window, workspace) in window_ids {
-        debug!("re-inserting window {}", window);
-        window_manager = window_manager
-            .view(window_system.deref(), workspace, &config.general)
-            .manage(window_system.deref(), window, &config.general)
-            .windows(window_system.deref(), &config.general, &|x| {
-                (config.internal.manage_hook)(x.clone(), window_system.clone(), window)
-            });
+        debug!("found {}", window_ids);
+        window_manager = resume(
+            window_manager,
+            &window_system,
+            &config,
+            parse_window_ids(&window_ids),
+        );
     }
 
+    window_manager = window_manager.adopt_docks(window_system.deref(), &config.general);
+
     window_manager =
         (*config.internal.startup_hook)(window_manager, window_system.clone(), &config);
 
-    // Enter the event loop and just listen for events
+    let mut recorder = match matches.opt_str("record") {
+        Some(path) => Some(EventRecorder::create(
+            &path,
+            &window_manager,
+            window_system.deref(),
+            &config,
+        )?),
+        None => None,
+    };
+
+    // Listen for commands from wtftw-msg and other clients
+    let ipc = match socket_path().and_then(IpcServer::bind) {
+        Ok(server) => {
+            info!("listening for commands on {}", server.path().display());
+            Some(server)
+        }
+        Err(e) => {
+            warn!("unable to start ipc server: {:#}", e);
+            None
+        }
+    };
+
+    // Wait on the window system, ipc clients and everything the config registered
+    let mut event_loop = EventLoop::new(window_system.clone());
+    if let Some(ref server) = ipc {
+        event_loop.watch(server.as_raw_fd());
+    }
+    for &fd in config.internal.fd_handlers.keys() {
+        event_loop.watch(fd);
+    }
+    let mut ipc_clients = Vec::new();
+
     while window_manager.running {
-        let event = window_system.clone().get_event();
-        match event {
-            WindowSystemEvent::ClientMessageEvent(_, _, _, _) => {}
-            WindowSystemEvent::PropertyMessageEvent(process, window, atom) => {
-                if process {
-                    window_manager = window_system.process_message(
-                        &window_manager,
-                        &config.general,
-                        window,
-                        atom,
-                    );
-                }
+        if window_manager.reload_requested {
+            for &fd in config.internal.fd_handlers.keys() {
+                event_loop.unwatch(fd);
             }
-            // The X11/Wayland configuration changed, so we need to readjust the
-            // screen configurations.
-            WindowSystemEvent::ConfigurationNotification(window) => {
-                if window_system.get_root() == window {
-                    debug!("screen configuration changed. rescreen");
-                    window_manager = window_manager.rescreen(window_system.deref());
+            window_manager = match window_manager.reload_config(&window_system, &mut config) {
+                Ok(window_manager) => window_manager,
+                Err(e) => {
+                    let message = format!("{:#}", e);
+                    error!("{}", message);
+                    show_message(message);
+                    window_manager.reload_requested = false;
+                    window_manager
                 }
+            };
+            apply_log_config(&logfile, &config, verbose);
+            for &fd in config.internal.fd_handlers.keys() {
+                event_loop.watch(fd);
             }
-            // A window asked to be reconfigured (i.e. resized, border change, etc.)
-            WindowSystemEvent::ConfigurationRequest(window, window_changes, mask) => {
-                let floating = window_manager
-                    .workspaces
-                    .floating
-                    .iter()
-                    .any(|(&x, _)| x == window)
-                    || !window_manager.workspaces.contains(window);
-                window_system.configure_window(window, window_changes, mask, floating);
-                window_manager =
-                    window_manager.windows(window_system.deref(), &config.general, &|x| x.clone());
-            }
-            // A new window was created, so we need to manage
-            // it unless it is already managed by us.
-            WindowSystemEvent::WindowCreated(window) => {
-                if window_manager.is_window_managed(window)
-                    || window_system.overrides_redirect(window)
-                {
-                    continue;
-                }
+        }
 
-                window_manager = window_manager
-                    .manage(window_system.deref(), window, &config.general)
-                    .windows(window_system.deref(), &config.general, &|x| {
-                        (config.internal.manage_hook)(x.clone(), window_system.clone(), window)
-                    });
-            }
-            WindowSystemEvent::WindowUnmapped(window, synthetic) => {
-                if synthetic && window_manager.is_window_managed(window) {
-                    window_manager = if synthetic || !window_manager.is_waiting_unmap(window) {
-                        window_manager.unmanage(window_system.deref(), window, &config.general)
-                    } else {
-                        window_manager.update_unmap(window)
-                    };
-                }
-                zombie::collect_zombies();
-            }
-            WindowSystemEvent::WindowDestroyed(window) => {
-                if window_manager.is_window_managed(window) {
-                    window_manager = window_manager
-                        .unmanage(window_system.deref(), window, &config.general)
-                        .remove_from_unmap(window);
-                }
-            }
-            // The mouse pointer entered a window's region. If focus following
-            // is enabled, we need to set focus to it.
-            WindowSystemEvent::Enter(window) => {
-                if config.general.focus_follows_mouse && window_manager.is_window_managed(window) {
-                    window_manager =
-                        window_manager.focus(window, window_system.deref(), &config.general);
-                }
-            }
-            // Mouse button has been pressed. We need to check if there is a mouse handler
-            // associated and if necessary, call it. Otherwise it results in a focus action.
-            WindowSystemEvent::ButtonPressed(window, subwindow, button, _, _) => {
-                let is_root = window_system.get_root() == window;
-                let is_sub_root = window_system.get_root() == subwindow || subwindow == 0;
-                let act = config.internal.mouse_handlers.get(&button);
-
-                match act {
-                    Some(ref action) => {
-                        // If it's a root window, then it's an event we grabbed
-                        if is_root && !is_sub_root {
-                            let local_window_manager = window_manager.clone();
-                            window_manager = action(
-                                local_window_manager,
-                                window_system.clone(),
-                                &config.general,
-                                subwindow,
-                            );
-                        }
-                    }
-                    None => {
-                        // Otherwise just clock to focus
-                        if !is_root {
-                            window_manager = window_manager.focus(
-                                window,
-                                window_system.deref(),
-                                &config.general,
-                            );
-                        }
-                    }
-                }
+        let wakeup = event_loop.next(&window_manager);
+
+        // The server notifies its subscribers about commands itself
+        if let (Wakeup::Readable(fd) | Wakeup::Writable(fd), Some(server)) = (&wakeup, &ipc) {
+            if server.owns(*fd) {
+                window_manager = server.handle_clients_with(
+                    window_manager,
+                    &window_system,
+                    &mut config,
+                    &mut |c| record(&mut recorder, |r| r.record_command(c)),
+                );
+                watch_clients(&mut event_loop, server, &mut ipc_clients);
+                continue;
             }
-            WindowSystemEvent::ButtonReleased => {
-                // If we were dragging, release the pointer and
-                // reset the dragging closure
-                if window_manager.dragging.is_some() {
-                    window_system.ungrab_pointer();
-                    window_manager.dragging = None;
-                }
+        }
+
+        // Only keep the old state around if somebody is interested in the changes
+        let previous = ipc
+            .as_ref()
+            .filter(|s| s.has_subscribers())
+            .map(|_| window_manager.clone());
+
+        window_manager = match wakeup {
+            Wakeup::Event(event) => {
+                record(&mut recorder, |r| r.record(&event, window_system.deref()));
+                window_manager.handle_event(event, &window_system, &mut config)
             }
-            WindowSystemEvent::KeyPressed(_, key) => {
-                if config.internal.key_handlers.contains_key(&key) {
-                    let local_window_manager = window_manager.clone();
-                    window_manager = config.internal.key_handlers[&key](
-                        local_window_manager,
-                        window_system.clone(),
-                        &config.general,
-                    );
-                }
+            Wakeup::Readable(fd) => {
+                record(&mut recorder, |r| r.record_readable(fd));
+                window_manager.handle_readable(fd, &window_system, &mut config)
             }
-            WindowSystemEvent::MouseMotion(x, y) => {
-                let local_window_manager = window_manager.clone();
-                if let Some(drag) = window_manager.dragging {
-                    window_manager = drag(x, y, local_window_manager, window_system.deref());
-                    window_system.remove_motion_events();
-                }
+            Wakeup::Timers => {
+                let due = window_manager.due_timers();
+                record(&mut recorder, |r| r.record_timers(&due));
+                window_manager.run_named_timers(&due, &window_system, &mut config)
             }
-            _ => (),
+            // Only ipc clients are watched for writing
+            Wakeup::Writable(_) => window_manager,
         };
 
-        if let Some(ref mut loghook) = config.internal.loghook {
-            loghook(window_manager.clone(), window_system.clone());
+        if let (Some(server), Some(previous)) = (&ipc, previous) {
+            server.notify(&previous, &window_manager);
+            watch_clients(&mut event_loop, server, &mut ipc_clients);
         }
-
-        window_system.update_server_state(&window_manager);
     }
 
     Ok(())
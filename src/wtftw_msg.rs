@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Context, Result};
+use getopts::Options;
+use std::env;
+use std::process::exit;
+use wtftw_core::actions::parse_layout_message;
+use wtftw_core::ipc::{send_command, socket_path, subscribe, IpcCommand, IpcResponse};
+use wtftw_core::window_system::Window;
+
+const COMMANDS: &str = "Commands:
+    view WORKSPACE          switch to the workspace with the given index
+    shift WORKSPACE         move the focused window to the given workspace
+    layout MESSAGE          send a message to the current layout,
+                            e.g. increase-gap or tree-expand-towards:left
+    action NAME             call a named action, e.g. focus-down or view:3
+    actions                 list all named actions
+    log-level LEVEL         log messages up to the given level, e.g. debug
+    float [WINDOW]          float the given or the focused window
+    sink [WINDOW]           sink the given or the focused window
+    query                   print the current state as JSON
+    subscribe               print every change as a line of JSON";
+
+/// Windows are accepted in decimal or in hex as printed by xprop and xwininfo
+fn parse_window(window: &str) -> Result<Window> {
+    let result = match window.strip_prefix("0x") {
+        Some(hex) => Window::from_str_radix(hex, 16),
+        None => window.parse(),
+    };
+    result.with_context(|| format!("invalid window {}", window))
+}
+
+fn parse_command(args: &[String]) -> Result<IpcCommand> {
+    let arg = |i: usize| {
+        args.get(i)
+            .ok_or_else(|| anyhow!("missing argument for {}", args[0]))
+    };
+    let window = || args.get(1).map(|w| parse_window(w)).transpose();
+
+    match args.first().map(|x| x.as_str()) {
+        Some("view") => Ok(IpcCommand::View {
+            workspace: arg(1)?.parse().context("invalid workspace")?,
+        }),
+        Some("shift") => Ok(IpcCommand::Shift {
+            workspace: arg(1)?.parse().context("invalid workspace")?,
+        }),
+        Some("layout") => Ok(IpcCommand::Layout {
+            message: parse_layout_message(arg(1)?)?,
+        }),
+        Some("action") => Ok(IpcCommand::Action {
+            name: arg(1)?.clone(),
+        }),
+        Some("actions") => Ok(IpcCommand::Actions),
+        Some("log-level") => Ok(IpcCommand::Action {
+            name: format!("log-level:{}", arg(1)?),
+        }),
+        Some("float") => Ok(IpcCommand::Float { window: window()? }),
+        Some("sink") => Ok(IpcCommand::Sink { window: window()? }),
+        Some("query") => Ok(IpcCommand::Query),
+        Some("subscribe") => Ok(IpcCommand::Subscribe),
+        Some(command) => Err(anyhow!("unknown command {}", command)),
+        None => Err(anyhow!("no command given")),
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut options = Options::new();
+    options.optopt("s", "socket", "path of the wtftw socket", "SOCKET");
+    options.optflag("h", "help", "print this help");
+
+    let matches = options.parse(args.iter().skip(1))?;
+    if matches.opt_present("h") {
+        let brief = format!("Usage: {} [options] COMMAND [ARGS]", args[0]);
+        println!("{}\n{}", options.usage(&brief), COMMANDS);
+        return Ok(());
+    }
+
+    let command = parse_command(&matches.free)?;
+    let socket = match matches.opt_str("s") {
+        Some(socket) => socket.into(),
+        None => socket_path()?,
+    };
+
+    if let IpcCommand::Subscribe = command {
+        for event in subscribe(&socket)? {
+            println!("{}", serde_json::to_string(&event?)?);
+        }
+        return Ok(());
+    }
+
+    match send_command(&socket, &command)? {
+        IpcResponse::Ok => Ok(()),
+        IpcResponse::State { state } => {
+            println!("{}", serde_json::to_string_pretty(&state)?);
+            Ok(())
+        }
+        IpcResponse::Actions { actions } => {
+            for action in actions {
+                println!("{:<24}{}", action.name, action.description);
+            }
+            Ok(())
+        }
+        IpcResponse::Error { message } => {
+            eprintln!("wtftw: {}", message);
+            exit(1)
+        }
+    }
+}
@@ -5,6 +5,7 @@ extern crate wtftw_core;
 extern crate x11;
 
 use std::borrow::ToOwned;
+use std::cell::{Cell, RefCell};
 use wtftw_core::config::GeneralConfig;
 use x11::xinerama;
 use x11::xlib;
@@ -14,6 +15,7 @@ use std::ffi::CStr;
 use std::ffi::CString;
 use std::io::Write;
 use std::mem;
+use std::os::unix::io::RawFd;
 use std::ptr::null;
 use std::ptr::null_mut;
 use std::slice::from_raw_parts;
@@ -24,6 +26,7 @@ use wtftw_core::window_manager::*;
 use wtftw_core::window_system::*;
 
 const KEYPRESS: usize = 2;
+const KEYRELEASE: usize = 3;
 const BUTTONPRESS: usize = 4;
 const BUTTONRELEASE: usize = 5;
 const MOTIONOTIFY: usize = 6;
@@ -36,6 +39,23 @@ const CONFIGURENOTIFY: usize = 22;
 const CONFIGUREREQUEST: usize = 23;
 const PROPERTYNOTIFY: usize = 28;
 const CLIENTMESSAGE: usize = 33;
+const MAPPINGNOTIFY: usize = 34;
+
+/// The `_NET_WM_WINDOW_TYPE` atoms and the types they stand for,
+/// in order of preference
+const WINDOW_TYPES: [(&str, WindowType); 11] = [
+    ("_NET_WM_WINDOW_TYPE_NORMAL", WindowType::Normal),
+    ("_NET_WM_WINDOW_TYPE_DIALOG", WindowType::Dialog),
+    ("_NET_WM_WINDOW_TYPE_UTILITY", WindowType::Utility),
+    ("_NET_WM_WINDOW_TYPE_SPLASH", WindowType::Splash),
+    ("_NET_WM_WINDOW_TYPE_TOOLBAR", WindowType::Toolbar),
+    ("_NET_WM_WINDOW_TYPE_MENU", WindowType::Menu),
+    ("_NET_WM_WINDOW_TYPE_DROPDOWN_MENU", WindowType::Menu),
+    ("_NET_WM_WINDOW_TYPE_POPUP_MENU", WindowType::Menu),
+    ("_NET_WM_WINDOW_TYPE_NOTIFICATION", WindowType::Notification),
+    ("_NET_WM_WINDOW_TYPE_DESKTOP", WindowType::Desktop),
+    ("_NET_WM_WINDOW_TYPE_DOCK", WindowType::Dock),
+];
 
 /// A custom error handler to prevent xlib from crashing the whole WM.
 /// Necessary because a few events may call the error routine.
@@ -51,6 +71,18 @@ pub struct XlibWindowSystem {
     display: *mut xlib::Display,
     root: Window,
     ewmh_child: Window,
+    /// Modifiers that are ignored in bindings, like CapsLock and NumLock
+    lock_mask: Cell<KeyModifiers>,
+    /// The modifiers each keycode is mapped to, see `key_modifiers`
+    modifier_map: RefCell<Vec<KeyModifiers>>,
+    /// The `_NET_WM_WINDOW_TYPE` atom
+    window_type: u64,
+    /// The atoms of `WINDOW_TYPES`, interned once
+    window_types: Vec<(u64, WindowType)>,
+    /// The `_NET_WM_STRUT` atom
+    strut: u64,
+    /// The `_NET_WM_STRUT_PARTIAL` atom
+    partial_strut: u64,
 }
 
 impl Default for XlibWindowSystem {
@@ -86,12 +118,29 @@ impl XlibWindowSystem {
             xlib::XSync(display, 0);
 
             xlib::XUngrabButton(display, 0, 0x8000, root);
+            // Only report a key release once the key is actually released,
+            // not for every repetition while it's held down
+            xlib::XkbSetDetectableAutoRepeat(display, 1, null_mut());
 
             let mut res = XlibWindowSystem {
                 display,
                 root: root as u64,
                 ewmh_child: 0,
+                lock_mask: Cell::new(KeyModifiers::LOCKMASK | KeyModifiers::MOD2MASK),
+                modifier_map: RefCell::new(Vec::new()),
+                window_type: 0,
+                window_types: Vec::new(),
+                strut: 0,
+                partial_strut: 0,
             };
+            res.modifier_map.replace(res.read_modifier_map());
+            res.window_type = res.get_atom("_NET_WM_WINDOW_TYPE");
+            res.window_types = WINDOW_TYPES
+                .iter()
+                .map(|&(name, window_type)| (res.get_atom(name), window_type))
+                .collect();
+            res.strut = res.get_atom("_NET_WM_STRUT");
+            res.partial_strut = res.get_atom("_NET_WM_STRUT_PARTIAL");
 
             // let name = (*CString::new(&b"wtftw"[..]).unwrap()).as_ptr();
             let name = "wtftw";
@@ -242,18 +291,6 @@ impl XlibWindowSystem {
         }
     }
 
-    fn get_property_from_string(&self, s: &str, window: Window) -> Option<Vec<u64>> {
-        unsafe {
-            match CString::new(s.as_bytes()) {
-                Ok(b) => {
-                    let atom = xlib::XInternAtom(self.display, b.as_ptr(), 0);
-                    self.get_property(atom as u64, window)
-                }
-                _ => None,
-            }
-        }
-    }
-
     fn get_atom(&self, s: &str) -> u64 {
         unsafe {
             match CString::new(s) {
@@ -325,25 +362,83 @@ impl XlibWindowSystem {
             self.set_button_grab(false, window);
         }
     }
+
+    /// Ask the server which modifiers each keycode is mapped to.
+    /// Only needed again when the mapping changes.
+    fn read_modifier_map(&self) -> Vec<KeyModifiers> {
+        let mut modifiers = vec![KeyModifiers::NONEMASK; 256];
+        unsafe {
+            let keymap = xlib::XGetModifierMapping(self.display);
+            if keymap.is_null() {
+                return modifiers;
+            }
+            let per_modifier = (*keymap).max_keypermod as usize;
+            let keycodes = from_raw_parts((*keymap).modifiermap, 8 * per_modifier);
+            for (i, &keycode) in keycodes.iter().enumerate() {
+                if keycode != 0 {
+                    modifiers[keycode as usize] |=
+                        KeyModifiers::from_bits_truncate(1 << (i / per_modifier));
+                }
+            }
+            xlib::XFreeModifiermap(keymap);
+        }
+        modifiers
+    }
+
+    /// The modifiers the key with the given keycode is mapped to, if any
+    fn key_modifiers(&self, keycode: u32) -> KeyModifiers {
+        self.modifier_map
+            .borrow()
+            .get(keycode as usize)
+            .cloned()
+            .unwrap_or(KeyModifiers::NONEMASK)
+    }
 }
 
 impl WindowSystem for XlibWindowSystem {
     fn get_partial_strut(&self, window: Window) -> Option<Vec<u64>> {
-        self.get_property_from_string("_NET_WM_STRUT_PARTIAL", window)
+        self.get_property(self.partial_strut, window)
     }
 
     fn get_strut(&self, window: Window) -> Option<Vec<u64>> {
-        self.get_property_from_string("_NET_WM_STRUT", window)
+        self.get_property(self.strut, window)
+    }
+
+    fn watch_struts(&self, window: Window) {
+        unsafe {
+            xlib::XSelectInput(self.display, window, xlib::PropertyChangeMask);
+        }
     }
 
     fn is_dock(&self, window: Window) -> bool {
-        let dock = self.get_atom("_NET_WM_WINDOW_TYPE_DOCK");
-        let desk = self.get_atom("_NET_WM_WINDOW_TYPE_DESKTOP");
+        matches!(
+            self.get_window_type(window),
+            WindowType::Dock | WindowType::Desktop
+        )
+    }
 
-        if let Some(rs) = self.get_property_from_string("_NET_WM_WINDOW_TYPE", window) {
-            rs.iter().any(|&x| x == dock || x == desk)
-        } else {
-            false
+    fn get_window_type(&self, window: Window) -> WindowType {
+        // The types are listed in order of preference, the first known one wins
+        self.get_property(self.window_type, window)
+            .unwrap_or_default()
+            .iter()
+            .find_map(|&atom| {
+                self.window_types
+                    .iter()
+                    .find(|&&(type_atom, _)| type_atom == atom)
+                    .map(|&(_, window_type)| window_type)
+            })
+            .unwrap_or(WindowType::Normal)
+    }
+
+    fn get_transient_for(&self, window: Window) -> Option<Window> {
+        unsafe {
+            let mut parent = 0;
+            if xlib::XGetTransientForHint(self.display, window, &mut parent) != 0 && parent != 0 {
+                Some(parent)
+            } else {
+                None
+            }
         }
     }
 
@@ -670,6 +765,10 @@ impl WindowSystem for XlibWindowSystem {
         unsafe { xlib::XPending(self.display) != 0 }
     }
 
+    fn get_connection_fd(&self) -> Option<RawFd> {
+        Some(unsafe { xlib::XConnectionNumber(self.display) })
+    }
+
     fn get_event(&self) -> WindowSystemEvent {
         let mut event = xlib::XEvent { pad: [0; 24] };
         unsafe {
@@ -697,11 +796,15 @@ impl WindowSystem for XlibWindowSystem {
             }
             PROPERTYNOTIFY => {
                 let event = xlib::XPropertyEvent::from(event);
-                WindowSystemEvent::PropertyMessageEvent(
-                    event.window as u64 == self.root,
-                    event.window as u64,
-                    event.atom,
-                )
+                if event.atom == self.strut || event.atom == self.partial_strut {
+                    WindowSystemEvent::StrutsChanged(event.window as u64)
+                } else {
+                    WindowSystemEvent::PropertyMessageEvent(
+                        event.window as u64 == self.root,
+                        event.window as u64,
+                        event.atom,
+                    )
+                }
             }
             CONFIGUREREQUEST => {
                 let event = xlib::XConfigureRequestEvent::from(event);
@@ -757,7 +860,7 @@ impl WindowSystem for XlibWindowSystem {
                 let event = xlib::XButtonEvent::from(event);
                 let button = MouseCommand {
                     button: event.button,
-                    mask: KeyModifiers::from_bits(0xEF & event.state as u32).unwrap(),
+                    mask: KeyModifiers::from_state(event.state, self.lock_mask.get()),
                 };
                 WindowSystemEvent::ButtonPressed(
                     event.window as u64,
@@ -767,19 +870,55 @@ impl WindowSystem for XlibWindowSystem {
                     event.y_root as u32,
                 )
             }
-            BUTTONRELEASE => WindowSystemEvent::ButtonReleased,
+            BUTTONRELEASE => {
+                let event = xlib::XButtonEvent::from(event);
+                let button = MouseCommand {
+                    button: event.button,
+                    mask: KeyModifiers::from_state(event.state, self.lock_mask.get()),
+                };
+                WindowSystemEvent::ButtonReleased(
+                    event.window as u64,
+                    event.subwindow as u64,
+                    button,
+                    event.x_root as u32,
+                    event.y_root as u32,
+                )
+            }
             KEYPRESS => unsafe {
                 let event = xlib::XKeyEvent::from(event);
                 let key = KeyCommand {
                     key: xlib::XKeycodeToKeysym(self.display, event.keycode as u8, 0) as u64,
-                    mask: KeyModifiers::from_bits(0xEF & event.state as u32).unwrap(),
+                    mask: KeyModifiers::from_state(event.state, self.lock_mask.get()),
                 };
                 WindowSystemEvent::KeyPressed(event.window as u64, key)
             },
+            KEYRELEASE => unsafe {
+                let event = xlib::XKeyEvent::from(event);
+                // The state still contains the modifier that is being
+                // released, so releasing Super_L doesn't look like M4-Super_L
+                let mask = KeyModifiers::from_state(event.state, self.lock_mask.get())
+                    - self.key_modifiers(event.keycode);
+                let key = KeyCommand {
+                    key: xlib::XKeycodeToKeysym(self.display, event.keycode as u8, 0) as u64,
+                    mask,
+                };
+                WindowSystemEvent::KeyReleased(event.window as u64, key)
+            },
             MOTIONOTIFY => {
                 let event = xlib::XMotionEvent::from(event);
                 WindowSystemEvent::MouseMotion(event.x_root as u32, event.y_root as u32)
             }
+            MAPPINGNOTIFY => unsafe {
+                let mut event = xlib::XMappingEvent::from(event);
+                xlib::XRefreshKeyboardMapping(&mut event);
+                // Changes to the pointer mapping don't affect our grabs
+                if event.request == xlib::MappingPointer {
+                    WindowSystemEvent::UnknownEvent
+                } else {
+                    self.modifier_map.replace(self.read_modifier_map());
+                    WindowSystemEvent::KeyboardMappingChanged
+                }
+            },
             _ => {
                 debug!("unknown event is {}", event_type);
                 WindowSystemEvent::UnknownEvent
@@ -787,44 +926,66 @@ impl WindowSystem for XlibWindowSystem {
         }
     }
 
+    fn set_lock_mask(&self, locks: KeyModifiers) {
+        self.lock_mask.set(locks);
+    }
+
     fn grab_keys(&self, keys: Vec<KeyCommand>) {
+        let locks = KeyModifiers::lock_combinations(self.lock_mask.get());
         for &key in keys.iter() {
+            for &lock in locks.iter() {
+                unsafe {
+                    xlib::XGrabKey(
+                        self.display,
+                        xlib::XKeysymToKeycode(self.display, key.key as u64) as i32,
+                        (key.mask | lock).get_mask(),
+                        self.root as u64,
+                        1,
+                        1,
+                        1,
+                    );
+                }
+            }
+        }
+    }
+
+    fn ungrab_keys(&self) {
+        unsafe {
+            xlib::XUngrabKey(
+                self.display,
+                xlib::AnyKey,
+                xlib::AnyModifier,
+                self.root as u64,
+            );
+        }
+    }
+
+    fn grab_button(&self, button: MouseCommand) {
+        for &lock in KeyModifiers::lock_combinations(self.lock_mask.get()).iter() {
             unsafe {
-                xlib::XGrabKey(
+                xlib::XGrabButton(
                     self.display,
-                    xlib::XKeysymToKeycode(self.display, key.key as u64) as i32,
-                    key.mask.get_mask(),
+                    button.button,
+                    (button.mask | lock).get_mask(),
                     self.root as u64,
+                    0,
+                    4,
                     1,
-                    1,
-                    1,
-                );
-                xlib::XGrabKey(
-                    self.display,
-                    xlib::XKeysymToKeycode(self.display, key.key as u64) as i32,
-                    key.mask.get_mask() | 0x10,
-                    self.root as u64,
-                    1,
-                    1,
-                    1,
+                    0,
+                    0,
+                    0,
                 );
             }
         }
     }
 
-    fn grab_button(&self, button: MouseCommand) {
+    fn ungrab_buttons(&self) {
         unsafe {
-            xlib::XGrabButton(
+            xlib::XUngrabButton(
                 self.display,
-                button.button,
-                button.mask.get_mask(),
+                xlib::AnyButton as u32,
+                xlib::AnyModifier,
                 self.root as u64,
-                0,
-                4,
-                1,
-                0,
-                0,
-                0,
             );
         }
     }
@@ -841,6 +1002,18 @@ impl WindowSystem for XlibWindowSystem {
         }
     }
 
+    fn grab_keyboard(&self) {
+        unsafe {
+            xlib::XGrabKeyboard(self.display, self.root as u64, 1, 1, 1, 0);
+        }
+    }
+
+    fn ungrab_keyboard(&self) {
+        unsafe {
+            xlib::XUngrabKeyboard(self.display, 0);
+        }
+    }
+
     fn remove_enter_events(&self) {
         unsafe {
             let mut event = xlib::XEvent { pad: [0; 24] };
@@ -904,6 +1077,12 @@ impl WindowSystem for XlibWindowSystem {
         }
     }
 
+    fn lower_window(&self, window: Window) {
+        unsafe {
+            xlib::XLowerWindow(self.display, window);
+        }
+    }
+
     fn close_client(&self, window: Window) {
         unsafe {
             xlib::XKillClient(self.display, window as u64);